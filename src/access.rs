@@ -0,0 +1,194 @@
+use std::fmt;
+
+use crate::chip_8::Chip8;
+use crate::constants;
+use crate::instruction::ParsedInstruction;
+
+// What a watchpoint can watch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Location {
+    Register(usize),
+    Index,
+    Memory(usize),
+}
+
+impl Location {
+    pub fn value(&self, chip8: &Chip8) -> u16 {
+        match *self {
+            Location::Register(register) => chip8.registers()[register] as u16,
+            Location::Index => chip8.index_register(),
+            Location::Memory(address) => chip8.ram()[address] as u16,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Register(register) => write!(formatter, "V{:X}", register),
+            Location::Index => write!(formatter, "I"),
+            Location::Memory(address) => write!(formatter, "{:03X}", address),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// Locations kept in place, so working out the accesses of each instruction does not allocate
+#[derive(Debug, Clone, Copy)]
+struct Locations {
+    locations: [Location; constants::MAX_ACCESSED_LOCATIONS],
+    len: usize,
+}
+
+impl Locations {
+    fn new() -> Self {
+        Locations {
+            locations: [Location::Index; constants::MAX_ACCESSED_LOCATIONS],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, location: Location) {
+        self.locations[self.len] = location;
+        self.len += 1;
+    }
+
+    fn extend(&mut self, locations: impl IntoIterator<Item = Location>) {
+        for location in locations {
+            self.push(location);
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Location> + '_ {
+        self.locations[..self.len].iter().copied()
+    }
+}
+
+// What one instruction reads and writes, see `next_accesses`
+#[derive(Debug, Clone, Copy)]
+pub struct Accesses {
+    reads: Locations,
+    writes: Locations,
+}
+
+impl Accesses {
+    // The reads come before the writes
+    pub fn iter(&self) -> impl Iterator<Item = (Location, Access)> + '_ {
+        self.reads
+            .iter()
+            .map(|location| (location, Access::Read))
+            .chain(self.writes.iter().map(|location| (location, Access::Write)))
+    }
+}
+
+fn memory_range(start: usize, len: usize) -> impl Iterator<Item = Location> {
+    (start..start + len).map(|address| Location::Memory(address % constants::RAM_LEN))
+}
+
+// Locations the instruction at the program counter reads and writes, worked out before it runs.
+// Fetching the instruction itself is not counted as reading memory.
+pub fn next_accesses(chip8: &Chip8) -> Accesses {
+    let mut reads = Locations::new();
+    let mut writes = Locations::new();
+    let ram = chip8.ram();
+    let program_counter = chip8.program_counter();
+    if program_counter + 1 >= constants::RAM_LEN {
+        return Accesses { reads, writes };
+    }
+    let instruction = ParsedInstruction::build(
+        (ram[program_counter] as u16) << 8 | ram[program_counter + 1] as u16,
+    );
+    let quirks = chip8.quirks();
+    let (x, y) = (
+        Location::Register(instruction.x as usize),
+        Location::Register(instruction.y as usize),
+    );
+    let flag = Location::Register(0xF);
+    let index_register = chip8.index_register() as usize;
+
+    match (instruction.opcode, instruction.n, instruction.nn) {
+        (0x3 | 0x4 | 0xE, _, _) => reads.push(x),
+        (0x5 | 0x9, _, _) => reads.extend([x, y]),
+        (0x6 | 0xC, _, _) => writes.push(x),
+        (0x7, _, _) => {
+            reads.push(x);
+            writes.push(x);
+        }
+        (0x8, 0x0, _) => {
+            reads.push(y);
+            writes.push(x);
+        }
+        (0x8, 0x1..=0x3, _) => {
+            reads.extend([x, y]);
+            writes.push(x);
+            if quirks.reset_flag {
+                writes.push(flag);
+            }
+        }
+        (0x8, 0x4 | 0x5 | 0x7, _) => {
+            reads.extend([x, y]);
+            writes.extend([x, flag]);
+        }
+        (0x8, 0x6 | 0xE, _) => {
+            reads.push(if quirks.shift_in_place { x } else { y });
+            writes.extend([x, flag]);
+        }
+        (0xA, _, _) => writes.push(Location::Index),
+        (0xB, _, _) => reads.push(if quirks.jump_plus_x_register {
+            x
+        } else {
+            Location::Register(0)
+        }),
+        (0xD, _, _) => {
+            // Only the rows that end up on screen are read
+            let y_coordinate =
+                chip8.registers()[instruction.y as usize] as usize % constants::DISPLAY_HEIGHT;
+            let rows = if quirks.wrap_sprites {
+                instruction.n as usize
+            } else {
+                (instruction.n as usize).min(constants::DISPLAY_HEIGHT - y_coordinate)
+            };
+            reads.extend([x, y, Location::Index]);
+            reads.extend(memory_range(index_register, rows));
+            writes.push(flag);
+        }
+        (0xF, _, 0x07 | 0x0A) => writes.push(x),
+        (0xF, _, 0x15 | 0x18) => reads.push(x),
+        (0xF, _, 0x1E) => {
+            reads.extend([x, Location::Index]);
+            writes.push(Location::Index);
+        }
+        (0xF, _, 0x29) => {
+            reads.push(x);
+            writes.push(Location::Index);
+        }
+        (0xF, _, 0x33) => {
+            reads.extend([x, Location::Index]);
+            writes.extend(memory_range(index_register, 3));
+        }
+        (0xF, _, 0x55) => {
+            reads.extend((0..=instruction.x as usize).map(Location::Register));
+            reads.push(Location::Index);
+            writes.extend(memory_range(index_register, instruction.x as usize + 1));
+            if quirks.increment_index_register {
+                writes.push(Location::Index);
+            }
+        }
+        (0xF, _, 0x65) => {
+            reads.push(Location::Index);
+            reads.extend(memory_range(index_register, instruction.x as usize + 1));
+            writes.extend((0..=instruction.x as usize).map(Location::Register));
+            if quirks.increment_index_register {
+                writes.push(Location::Index);
+            }
+        }
+        _ => {}
+    }
+
+    Accesses { reads, writes }
+}
@@ -0,0 +1,478 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::constants;
+use crate::disassembler::disassemble_with_symbols;
+use crate::instruction::ParsedInstruction;
+use crate::symbols::Symbols;
+
+const FLAG_REGISTER: u8 = 0xF;
+
+// What is known along one path through the code, each address is only walked with the state of
+// the first path reaching it
+#[derive(Clone, Copy, Default)]
+struct PathState {
+    // Set by `LD I, NNN`, unknown after anything else changing I
+    index_register: Option<u16>,
+    // A store or load that advances I with `increment_index_register`, if I was not set since
+    index_advanced_at: Option<usize>,
+    // A logic instruction that clears VF with `reset_flag`, if VF was not written since
+    flag_reset_at: Option<usize>,
+}
+
+fn is_known(instruction: u16) -> bool {
+    let ParsedInstruction { opcode, nn, n, .. } = ParsedInstruction::build(instruction);
+    matches!(
+        (opcode, nn, n),
+        (0x0, 0xE0 | 0xEE, _)
+            | (0x1..=0x4 | 0x6 | 0x7 | 0xA..=0xD, _, _)
+            | (0x5 | 0x9, _, 0x0)
+            | (0x8, _, 0x0..=0x7 | 0xE)
+            | (0xE, 0x9E | 0xA1, _)
+            | (0xF, 0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x33 | 0x55 | 0x65, _)
+    )
+}
+
+fn uses_index_register(instruction: u16) -> bool {
+    let ParsedInstruction { opcode, nn, .. } = ParsedInstruction::build(instruction);
+    matches!((opcode, nn), (0xD, _) | (0xF, 0x1E | 0x33 | 0x55 | 0x65))
+}
+
+// Whether the instruction reads a register, taking either quirk into account
+fn reads_register(instruction: u16, register: u8) -> bool {
+    let ParsedInstruction {
+        opcode,
+        x,
+        y,
+        n,
+        nn,
+        ..
+    } = ParsedInstruction::build(instruction);
+    match (opcode, nn, n) {
+        (0x3 | 0x4 | 0x7 | 0xE, _, _) | (0xF, 0x15 | 0x18 | 0x1E | 0x29 | 0x33, _) => x == register,
+        (0x8, _, 0x0) => y == register,
+        (0x5 | 0x8 | 0x9 | 0xD, _, _) => x == register || y == register,
+        (0xB, _, _) => register == 0 || x == register,
+        (0xF, 0x55, _) => register <= x,
+        _ => false,
+    }
+}
+
+fn writes_register(instruction: u16, register: u8) -> bool {
+    let ParsedInstruction {
+        opcode, x, n, nn, ..
+    } = ParsedInstruction::build(instruction);
+    match (opcode, nn, n) {
+        (0x8, _, 0x4..=0x7 | 0xE) | (0xD, _, _) => register == FLAG_REGISTER || x == register,
+        (0x6 | 0x7 | 0x8 | 0xC, _, _) | (0xF, 0x07 | 0x0A, _) => x == register,
+        (0xF, 0x65, _) => register <= x,
+        _ => false,
+    }
+}
+
+// Static analysis of a ROM, walking every path from the program start without running it.
+// Computed jumps (`JP V0, NNN`) are followed to NNN only, so code only reached through jump
+// tables may show up as unreachable.
+pub struct Analysis<'a> {
+    ram: Vec<u8>,
+    rom_len: usize,
+    symbols: &'a Symbols,
+    // Addresses of the first byte of each reachable instruction
+    code: BTreeSet<usize>,
+    // Addresses loaded into I, with where they were loaded
+    data: BTreeMap<usize, usize>,
+    // The address of the instruction with what is wrong with it
+    issues: Vec<(usize, String)>,
+    // Stores to memory with a known I, as the instruction and the first and last address written
+    writes: Vec<(usize, usize, usize)>,
+    // Quirks the ROM likely depends on, with the instructions depending on them and why
+    quirks: BTreeMap<&'static str, BTreeMap<usize, String>>,
+}
+
+impl<'a> Analysis<'a> {
+    pub fn build(rom: &[u8], symbols: &'a Symbols) -> Self {
+        let rom_len = rom.len().min(constants::BANK_LEN);
+        let mut ram = vec![0; constants::RAM_LEN];
+        ram[constants::PROGRAM_START..constants::PROGRAM_START + rom_len]
+            .copy_from_slice(&rom[..rom_len]);
+        let mut analysis = Analysis {
+            ram,
+            rom_len,
+            symbols,
+            code: BTreeSet::new(),
+            data: BTreeMap::new(),
+            issues: Vec::new(),
+            writes: Vec::new(),
+            quirks: BTreeMap::new(),
+        };
+        if rom.len() > constants::BANK_LEN {
+            analysis.quirks.entry("bank_switching").or_default().insert(
+                constants::PROGRAM_START,
+                format!(
+                    "the ROM is {} bytes, only the first {} are analyzed",
+                    rom.len(),
+                    constants::BANK_LEN
+                ),
+            );
+        }
+        analysis.walk();
+        analysis
+    }
+
+    fn rom_end(&self) -> usize {
+        constants::PROGRAM_START + self.rom_len
+    }
+
+    fn instruction(&self, address: usize) -> u16 {
+        (self.ram[address] as u16) << 8 | self.ram[(address + 1) % constants::RAM_LEN] as u16
+    }
+
+    fn describe(&self, address: usize) -> String {
+        disassemble_with_symbols(self.instruction(address), self.symbols)
+    }
+
+    fn add_quirk(&mut self, name: &'static str, address: usize, reason: String) {
+        let description = format!("{}: {}", self.describe(address), reason);
+        self.quirks
+            .entry(name)
+            .or_default()
+            .entry(address)
+            .or_insert(description);
+    }
+
+    fn walk(&mut self) {
+        let mut pending = vec![(constants::PROGRAM_START, PathState::default())];
+        while let Some((address, state)) = pending.pop() {
+            if self.code.contains(&address) {
+                continue;
+            }
+            if address + 2 > self.rom_end() {
+                self.issues.push((
+                    address,
+                    "Execution runs past the end of the ROM".to_string(),
+                ));
+                continue;
+            }
+            self.code.insert(address);
+            for successor in self.step(address, state) {
+                pending.push(successor);
+            }
+        }
+    }
+
+    // Records what the instruction reveals and returns where execution goes next
+    fn step(&mut self, address: usize, mut state: PathState) -> Vec<(usize, PathState)> {
+        let instruction = self.instruction(address);
+        if !is_known(instruction) {
+            self.issues.push((
+                address,
+                format!(
+                    "Unknown instruction {:04X}, the path ends here",
+                    instruction
+                ),
+            ));
+            return Vec::new();
+        }
+        let ParsedInstruction {
+            opcode,
+            x,
+            y,
+            n,
+            nn,
+            nnn,
+        } = ParsedInstruction::build(instruction);
+        let nnn = nnn as usize;
+
+        if let Some(advanced_at) = state.index_advanced_at {
+            if uses_index_register(instruction) {
+                let reason = format!("uses I as advanced by {:03X}", advanced_at);
+                self.add_quirk("increment_index_register", address, reason);
+            }
+        }
+        if let Some(reset_at) = state.flag_reset_at {
+            if reads_register(instruction, FLAG_REGISTER) {
+                let reason = format!("reads VF as left by {:03X}", reset_at);
+                self.add_quirk("reset_flag", address, reason);
+            }
+        }
+        if writes_register(instruction, FLAG_REGISTER) {
+            state.flag_reset_at = None;
+        }
+
+        match (opcode, nn, n) {
+            (0x8, _, 0x1..=0x3) => state.flag_reset_at = Some(address),
+            (0x8, _, 0x6 | 0xE) if x != y => {
+                let reason = "shifts VY into VX, or VX in place".to_string();
+                self.add_quirk("shift_in_place", address, reason);
+            }
+            (0xA, _, _) => {
+                state.index_register = Some(nnn as u16);
+                state.index_advanced_at = None;
+                self.data.entry(nnn).or_insert(address);
+            }
+            (0xB, _, _) if x != 0 => {
+                let reason = format!("jumps to {:03X} plus V0, or plus V{:X}", nnn, x);
+                self.add_quirk("jump_plus_x_register", address, reason);
+            }
+            (0xF, 0x1E | 0x29, _) => {
+                state.index_register = None;
+                state.index_advanced_at = None;
+            }
+            (0xF, 0x33 | 0x55, _) => {
+                if let Some(index_register) = state.index_register {
+                    let len = if nn == 0x33 { 3 } else { x as usize + 1 };
+                    let start = index_register as usize;
+                    self.writes.push((address, start, start + len - 1));
+                    if (start..start + len).contains(&constants::BANK_SELECT_ADDRESS) {
+                        let reason = "selects the bank mapped at the program start".to_string();
+                        self.add_quirk("bank_switching", address, reason);
+                    }
+                }
+            }
+            _ => {}
+        }
+        if let (0xF, 0x55 | 0x65) = (opcode, nn) {
+            state.index_register = None;
+            state.index_advanced_at = Some(address);
+        }
+
+        let next = address + 2;
+        let targets = match (opcode, nn, n) {
+            (0x0, 0xEE, _) => Vec::new(),
+            (0x1, _, _) => vec![nnn],
+            // The subroutine may change I
+            (0x2, _, _) => {
+                let returned = PathState {
+                    index_register: None,
+                    ..state
+                };
+                let mut targets = self.jump_targets(address, &[nnn], state);
+                targets.push((next, returned));
+                return targets;
+            }
+            (0x3 | 0x4 | 0x5 | 0x9, _, _) | (0xE, _, _) => vec![next, next + 2],
+            (0xB, _, _) => {
+                self.issues.push((
+                    address,
+                    format!(
+                        "Computed jump, only followed to {:03X} as the base of its table",
+                        nnn
+                    ),
+                ));
+                vec![nnn]
+            }
+            _ => vec![next],
+        };
+        self.jump_targets(address, &targets, state)
+    }
+
+    // Drops targets outside of the ROM, reporting jumps and calls to them
+    fn jump_targets(
+        &mut self,
+        address: usize,
+        targets: &[usize],
+        state: PathState,
+    ) -> Vec<(usize, PathState)> {
+        let is_jump = matches!(self.ram[address] >> 4, 0x1 | 0x2 | 0xB);
+        let mut successors = Vec::new();
+        for &target in targets {
+            if is_jump && !(constants::PROGRAM_START..self.rom_end()).contains(&target) {
+                let place = if target < constants::PROGRAM_START {
+                    "below the program start"
+                } else {
+                    "past the end of the ROM"
+                };
+                self.issues
+                    .push((address, format!("Jump to {:03X}, {}", target, place)));
+                continue;
+            }
+            successors.push((target, state));
+        }
+        successors
+    }
+
+    fn is_code(&self, address: usize) -> bool {
+        self.code.contains(&address) || (address > 0 && self.code.contains(&(address - 1)))
+    }
+
+    // Runs of ROM bytes no path executes, as first and last address
+    fn unreachable_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for address in constants::PROGRAM_START..self.rom_end() {
+            if self.is_code(address) {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == address => *end = address,
+                _ => ranges.push((address, address)),
+            }
+        }
+        ranges
+    }
+
+    // Stores over reachable code or below the program start, where the font lives
+    fn self_modifying_writes(&self) -> Vec<(usize, String)> {
+        self.writes
+            .iter()
+            .filter_map(|&(address, start, end)| {
+                let place = if (start..=end).any(|written| self.is_code(written)) {
+                    "over code"
+                } else if start < constants::PROGRAM_START {
+                    "below the program start"
+                } else {
+                    return None;
+                };
+                let range = if start == end {
+                    format!("{:03X}", start)
+                } else {
+                    format!("{:03X}-{:03X}", start, end)
+                };
+                Some((
+                    address,
+                    format!("{} writes {} {}", self.describe(address), range, place),
+                ))
+            })
+            .collect()
+    }
+
+    pub fn text_report(&self) -> String {
+        let code_bytes = (constants::PROGRAM_START..self.rom_end())
+            .filter(|&address| self.is_code(address))
+            .count();
+        let mut text = format!(
+            "Reachable code: {} of {} ROM bytes from {:03X}\n",
+            code_bytes,
+            self.rom_len,
+            constants::PROGRAM_START
+        );
+
+        writeln!(text, "\nUnreachable bytes").unwrap();
+        let unreachable_ranges = self.unreachable_ranges();
+        for &(start, end) in &unreachable_ranges {
+            // Sprites and other data are loaded into I
+            let loaded_at = self.data.range(start..=end).next();
+            let note = match (loaded_at, self.symbols.name(start)) {
+                (Some((_, &loaded_at)), _) => format!("data, loaded into I at {:03X}", loaded_at),
+                (None, Some(name)) => name.to_string(),
+                (None, None) => String::new(),
+            };
+            let line = format!(
+                "  {:03X}-{:03X}  {:<5}  {}",
+                start,
+                end,
+                end - start + 1,
+                note
+            );
+            writeln!(text, "{}", line.trim_end()).unwrap();
+        }
+        if unreachable_ranges.is_empty() {
+            writeln!(text, "  none").unwrap();
+        }
+
+        let mut issues = self.issues.clone();
+        issues.extend(self.self_modifying_writes());
+        issues.sort();
+        issues.dedup();
+        writeln!(text, "\nSuspicious instructions").unwrap();
+        for (address, issue) in &issues {
+            writeln!(text, "  {:03X}  {}", address, issue).unwrap();
+        }
+        if issues.is_empty() {
+            writeln!(text, "  none").unwrap();
+        }
+
+        writeln!(text, "\nLikely quirk dependencies").unwrap();
+        for (name, instructions) in &self.quirks {
+            writeln!(text, "  {}", name).unwrap();
+            for (address, description) in instructions {
+                writeln!(text, "    {:03X}  {}", address, description).unwrap();
+            }
+        }
+        if self.quirks.is_empty() {
+            writeln!(text, "  none").unwrap();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(rom: &[u8]) -> String {
+        Analysis::build(rom, &Symbols::new()).text_report()
+    }
+
+    #[test]
+    fn data_after_the_code_is_unreachable() {
+        // Draws the sprite after a loop forever
+        let rom = [0xA2, 0x06, 0xD0, 0x13, 0x12, 0x04, 0xFF, 0x81, 0xFF];
+        assert_eq!(
+            report(&rom),
+            "Reachable code: 6 of 9 ROM bytes from 200\n\
+             \n\
+             Unreachable bytes\n  \
+               206-208  3      data, loaded into I at 200\n\
+             \n\
+             Suspicious instructions\n  \
+               none\n\
+             \n\
+             Likely quirk dependencies\n  \
+               none\n"
+        );
+    }
+
+    #[test]
+    fn every_path_is_checked_for_suspicious_instructions() {
+        // Skips over a jump past the end of the ROM, stores V0 over the first instruction, then
+        // either runs into an unknown instruction or off the end of the ROM
+        let rom = [
+            0x30, 0x01, 0x13, 0x00, 0xA2, 0x00, 0xF0, 0x55, 0x40, 0x00, 0x00, 0x23, 0x60,
+        ];
+        let report = report(&rom);
+        let suspicious = report
+            .split("\n\n")
+            .find(|section| section.starts_with("Suspicious instructions"));
+        assert_eq!(
+            suspicious,
+            Some(
+                "Suspicious instructions\n  \
+                   202  Jump to 300, past the end of the ROM\n  \
+                   206  LD [I], V0 writes 200 over code\n  \
+                   20A  Unknown instruction 0023, the path ends here\n  \
+                   20C  Execution runs past the end of the ROM"
+            ),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn quirk_dependencies_are_found_along_the_path() {
+        // Reads VF after OR, shifts V1 into V0, draws with I as left by a store and jumps to
+        // 100 plus V0 with X = 1
+        let rom = [
+            0x80, 0x11, 0x3F, 0x00, 0x80, 0x16, 0xA3, 0x00, 0xF1, 0x55, 0xD0, 0x15, 0xB1, 0x00,
+        ];
+        let report = report(&rom);
+        let quirks = report.split("\n\n").last();
+        assert_eq!(
+            quirks,
+            Some(
+                "Likely quirk dependencies\n  \
+                   increment_index_register\n    \
+                     20A  DRW V0, V1, 5: uses I as advanced by 208\n  \
+                   jump_plus_x_register\n    \
+                     20C  JP V0, 100: jumps to 100 plus V0, or plus V1\n  \
+                   reset_flag\n    \
+                     202  SE VF, 00: reads VF as left by 200\n  \
+                   shift_in_place\n    \
+                     204  SHR V0, V1: shifts VY into VX, or VX in place\n"
+            ),
+            "{}",
+            report
+        );
+    }
+}
@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+
+use crate::constants;
+use crate::source_map::SourceMap;
+use crate::symbols::Symbols;
+
+// Opcodes skipping the next instruction when a condition holds, and when it does not
+struct Skip {
+    when_true: u16,
+    when_false: u16,
+}
+
+enum Block {
+    // Jump to patch once the end of the `if` or `else` part is known
+    If(usize),
+    Loop { start: u16, breaks: Vec<usize> },
+}
+
+// A 12-bit address to fill in once a label further down is defined
+struct Fixup {
+    position: usize,
+    label: String,
+    line: usize,
+}
+
+// Number in decimal, hexadecimal (`0x`) or binary (`0b`), negative numbers wrap around
+fn parse_number(token: &str) -> Option<i32> {
+    let (is_negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if is_negative { -value } else { value })
+}
+
+struct Assembler<'a> {
+    tokens: Vec<(&'a str, usize)>,
+    next_token: usize,
+    rom: Vec<u8>,
+    labels: HashMap<&'a str, u16>,
+    constants: HashMap<&'a str, i32>,
+    aliases: HashMap<&'a str, u8>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    source_map: SourceMap,
+}
+
+// What `assemble_with_debug_info` produces
+pub struct Assembly {
+    pub rom: Vec<u8>,
+    // The address of every label
+    pub symbols: Symbols,
+    // The source line of every instruction
+    pub source_map: SourceMap,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(source: &'a str) -> Self {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                let code = line.split('#').next().unwrap_or_default();
+                code.split_whitespace().map(move |token| (token, index + 1))
+            })
+            .collect();
+
+        Assembler {
+            tokens,
+            next_token: 0,
+            rom: Vec::new(),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            source_map: SourceMap::new(),
+        }
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.next_token.saturating_sub(1))
+            .map_or(0, |&(_, line)| line)
+    }
+
+    fn error(&self, message: String) -> String {
+        format!("Line {}: {}", self.line(), message)
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let token = self
+            .tokens
+            .get(self.next_token)
+            .map(|&(token, _)| token)
+            .ok_or_else(|| self.error("Unexpected end of source".to_string()))?;
+        self.next_token += 1;
+        Ok(token)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.next_token).map(|&(token, _)| token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(self.error(format!("Expected {}, found {}", expected, token))),
+        }
+    }
+
+    fn address(&self) -> u16 {
+        (constants::PROGRAM_START + self.rom.len()) as u16
+    }
+
+    fn emit(&mut self, instruction: u16) -> usize {
+        let position = self.rom.len();
+        // The jump to `main` has no line of its own
+        if self.next_token > 0 {
+            self.source_map.insert(self.address() as usize, self.line());
+        }
+        self.rom.extend(instruction.to_be_bytes());
+        position
+    }
+
+    fn patch(&mut self, position: usize, address: u16) {
+        self.rom[position] |= (address >> 8) as u8 & 0x0F;
+        self.rom[position + 1] = address as u8;
+    }
+
+    fn is_register(&self, token: &str) -> bool {
+        self.aliases.contains_key(token)
+            || (token.len() == 2
+                && token.starts_with(['v', 'V'])
+                && token[1..].chars().all(|digit| digit.is_ascii_hexdigit()))
+    }
+
+    fn register(&mut self) -> Result<u16, String> {
+        let token = self.next()?;
+        if let Some(&register) = self.aliases.get(token) {
+            return Ok(register as u16);
+        }
+        if self.is_register(token) {
+            return Ok(u16::from_str_radix(&token[1..], 16).unwrap());
+        }
+        Err(self.error(format!("Expected a register, found {}", token)))
+    }
+
+    fn value(&mut self, max: i32) -> Result<u16, String> {
+        let token = self.next()?;
+        let value = parse_number(token)
+            .or_else(|| self.constants.get(token).copied())
+            .ok_or_else(|| self.error(format!("Expected a number, found {}", token)))?;
+        if value > max || value < -(max + 1) / 2 {
+            return Err(self.error(format!("Value out of range: {}", token)));
+        }
+        Ok(value as u16 & max as u16)
+    }
+
+    // Labels may be used before they are defined
+    fn emit_with_address(&mut self, opcode: u16) -> Result<(), String> {
+        let token = self.next()?;
+        if let Some(value) = parse_number(token).or_else(|| self.constants.get(token).copied()) {
+            if !(0..=0xFFF).contains(&value) {
+                return Err(self.error(format!("Address out of range: {}", token)));
+            }
+            self.emit(opcode | value as u16);
+            return Ok(());
+        }
+        let position = self.emit(opcode);
+        self.fixups.push(Fixup {
+            position,
+            label: token.to_string(),
+            line: self.line(),
+        });
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Skip, String> {
+        let x = self.register()? << 8;
+        let skip = match self.next()? {
+            "key" => Skip {
+                when_true: 0xE09E | x,
+                when_false: 0xE0A1 | x,
+            },
+            "-key" => Skip {
+                when_true: 0xE0A1 | x,
+                when_false: 0xE09E | x,
+            },
+            comparison @ ("==" | "!=") => {
+                let (equal, not_equal) = if self.peek().is_some_and(|token| self.is_register(token))
+                {
+                    let y = self.register()? << 4;
+                    (0x5000 | x | y, 0x9000 | x | y)
+                } else {
+                    let value = self.value(0xFF)?;
+                    (0x3000 | x | value, 0x4000 | x | value)
+                };
+                match comparison {
+                    "==" => Skip {
+                        when_true: equal,
+                        when_false: not_equal,
+                    },
+                    _ => Skip {
+                        when_true: not_equal,
+                        when_false: equal,
+                    },
+                }
+            }
+            comparison @ ("<" | ">" | "<=" | ">=") => {
+                if x == 0xF00 {
+                    return Err(self.error(format!("vF cannot be compared with {}", comparison)));
+                }
+                // As in Octo, the operand is loaded into vF and subtracted from or by vX, leaving
+                // the no-borrow flag in vF
+                if self.peek().is_some_and(|token| self.is_register(token)) {
+                    let y = self.register()? << 4;
+                    self.emit(0x8F00 | y);
+                } else {
+                    let value = self.value(0xFF)?;
+                    self.emit(0x6F00 | value);
+                }
+                let subtract = match comparison {
+                    "<" | ">=" => 0x8F07,
+                    _ => 0x8F05,
+                };
+                self.emit(subtract | x >> 4);
+                // Strict comparisons hold when the subtraction borrowed
+                match comparison {
+                    "<" | ">" => Skip {
+                        when_true: 0x3F00,
+                        when_false: 0x4F00,
+                    },
+                    _ => Skip {
+                        when_true: 0x4F00,
+                        when_false: 0x3F00,
+                    },
+                }
+            }
+            token => return Err(self.error(format!("Unsupported condition: {}", token))),
+        };
+        Ok(skip)
+    }
+
+    fn register_statement(&mut self) -> Result<(), String> {
+        let x = self.register()? << 8;
+        let operator = self.next()?;
+        let is_register_operand = self.peek().is_some_and(|token| self.is_register(token));
+        let instruction = match operator {
+            ":=" if is_register_operand => 0x8000 | x | self.register()? << 4,
+            ":=" => match self.peek() {
+                Some("random") => {
+                    self.next()?;
+                    0xC000 | x | self.value(0xFF)?
+                }
+                Some("delay") => {
+                    self.next()?;
+                    0xF007 | x
+                }
+                Some("key") => {
+                    self.next()?;
+                    0xF00A | x
+                }
+                _ => 0x6000 | x | self.value(0xFF)?,
+            },
+            "+=" if is_register_operand => 0x8004 | x | self.register()? << 4,
+            "+=" => 0x7000 | x | self.value(0xFF)?,
+            // Subtracting a constant adds its negation
+            "-=" if !is_register_operand => 0x7000 | x | (self.value(0xFF)?.wrapping_neg() & 0xFF),
+            _ => {
+                let n = match operator {
+                    "|=" => 0x1,
+                    "&=" => 0x2,
+                    "^=" => 0x3,
+                    "-=" => 0x5,
+                    ">>=" => 0x6,
+                    "=-" => 0x7,
+                    "<<=" => 0xE,
+                    _ => return Err(self.error(format!("Unknown operator: {}", operator))),
+                };
+                0x8000 | x | self.register()? << 4 | n
+            }
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        match token {
+            ":" => {
+                let name = self.next()?;
+                if self.labels.insert(name, self.address()).is_some() {
+                    return Err(self.error(format!("Label defined twice: {}", name)));
+                }
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.value(0xFFFF)?;
+                self.constants.insert(name, value as i32);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register as u8);
+            }
+            ":byte" => {
+                let value = self.value(0xFF)?;
+                self.rom.push(value as u8);
+            }
+            // Only moves forward, the gap is filled with zeros
+            ":org" => {
+                let address = self.value(0xFFF)?;
+                if address < self.address() {
+                    return Err(self.error(format!(
+                        ":org cannot move back from {:#05X} to {:#05X}",
+                        self.address(),
+                        address
+                    )));
+                }
+                self.rom
+                    .resize(address as usize - constants::PROGRAM_START, 0);
+            }
+            ":call" => self.emit_with_address(0x2000)?,
+            "clear" => {
+                self.emit(0x00E0);
+            }
+            "return" | ";" => {
+                self.emit(0x00EE);
+            }
+            "jump" => self.emit_with_address(0x1000)?,
+            "jump0" => self.emit_with_address(0xB000)?,
+            "i" => match self.next()? {
+                ":=" if self.peek() == Some("hex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(0xF029 | x << 8);
+                }
+                ":=" => self.emit_with_address(0xA000)?,
+                "+=" => {
+                    let x = self.register()?;
+                    self.emit(0xF01E | x << 8);
+                }
+                operator => return Err(self.error(format!("Unknown operator: {}", operator))),
+            },
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(if token == "delay" { 0xF015 } else { 0xF018 } | x << 8);
+            }
+            "bcd" | "save" | "load" => {
+                let x = self.register()?;
+                let low_byte = match token {
+                    "bcd" => 0x33,
+                    "save" => 0x55,
+                    _ => 0x65,
+                };
+                self.emit(0xF000 | x << 8 | low_byte);
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.value(0xF)?;
+                self.emit(0xD000 | x << 8 | y << 4 | n);
+            }
+            "if" => {
+                let skip = self.condition()?;
+                match self.next()? {
+                    "then" => {
+                        self.emit(skip.when_false);
+                    }
+                    "begin" => {
+                        self.emit(skip.when_true);
+                        let jump = self.emit(0x1000);
+                        self.blocks.push(Block::If(jump));
+                    }
+                    token => {
+                        return Err(self.error(format!("Expected then or begin, found {}", token)))
+                    }
+                }
+            }
+            "else" => {
+                let Some(Block::If(jump)) = self.blocks.pop() else {
+                    return Err(self.error("else without if".to_string()));
+                };
+                let end_jump = self.emit(0x1000);
+                self.patch(jump, self.address());
+                self.blocks.push(Block::If(end_jump));
+            }
+            "end" => {
+                let Some(Block::If(jump)) = self.blocks.pop() else {
+                    return Err(self.error("end without if".to_string()));
+                };
+                self.patch(jump, self.address());
+            }
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.address(),
+                breaks: Vec::new(),
+            }),
+            "while" => {
+                let skip = self.condition()?;
+                self.emit(skip.when_true);
+                let jump = self.emit(0x1000);
+                match self.blocks.last_mut() {
+                    Some(Block::Loop { breaks, .. }) => breaks.push(jump),
+                    _ => return Err(self.error("while outside of loop".to_string())),
+                }
+            }
+            "again" => {
+                let Some(Block::Loop { start, breaks }) = self.blocks.pop() else {
+                    return Err(self.error("again without loop".to_string()));
+                };
+                self.emit(0x1000 | start);
+                for jump in breaks {
+                    self.patch(jump, self.address());
+                }
+            }
+            _ if self.is_register(token) => {
+                self.next_token -= 1;
+                self.register_statement()?;
+            }
+            _ => match parse_number(token).or_else(|| self.constants.get(token).copied()) {
+                // Bare numbers are data, other names call the subroutine at that label
+                Some(value) if (-128..=0xFF).contains(&value) => self.rom.push(value as u8),
+                Some(_) => return Err(self.error(format!("Byte out of range: {}", token))),
+                None => {
+                    self.next_token -= 1;
+                    self.emit_with_address(0x2000)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn assemble(mut self) -> Result<Assembly, String> {
+        // Execution starts at `main`, jumped to unless the program starts with it
+        let has_main = self
+            .tokens
+            .windows(2)
+            .any(|pair| pair[0].0 == ":" && pair[1].0 == "main");
+        let starts_with_main = self.tokens.len() >= 2 && self.tokens[1].0 == "main";
+        if has_main && !starts_with_main {
+            let position = self.emit(0x1000);
+            self.fixups.push(Fixup {
+                position,
+                label: "main".to_string(),
+                line: 0,
+            });
+        }
+
+        while self.next_token < self.tokens.len() {
+            self.statement()?;
+        }
+        if !self.blocks.is_empty() {
+            return Err("Unterminated if or loop at end of source".to_string());
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let address = *self
+                .labels
+                .get(fixup.label.as_str())
+                .ok_or_else(|| format!("Line {}: Unknown label: {}", fixup.line, fixup.label))?;
+            self.patch(fixup.position, address);
+        }
+
+        if constants::PROGRAM_START + self.rom.len() > constants::RAM_LEN {
+            return Err(format!("Program too large: {} bytes", self.rom.len()));
+        }
+
+        // Sorted, so the same name wins for labels sharing an address every time
+        let mut labels: Vec<(&str, u16)> = self.labels.into_iter().collect();
+        labels.sort();
+        let mut symbols = Symbols::new();
+        for (name, address) in labels {
+            symbols.insert(name.to_string(), address as usize);
+        }
+        Ok(Assembly {
+            rom: self.rom,
+            symbols,
+            source_map: self.source_map,
+        })
+    }
+}
+
+// Assembles the CHIP-8 subset of Octo: labels, `:const`, `:alias`, `:byte`, `:org` (forward only),
+// `:call`, data bytes, the standard statements, `if ... then`, `if ... begin ... else ... end` and
+// `loop ... while ... again` with `key`, `-key`, `==`, `!=`, `<`, `>`, `<=` and `>=` conditions.
+// Macros, `:calc`, `:next`, `:unpack` and XO-CHIP instructions are not supported.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    assemble_with_debug_info(source).map(|assembly| assembly.rom)
+}
+
+// Also names the address of every label and maps instructions back to source lines
+pub fn assemble_with_debug_info(source: &str) -> Result<Assembly, String> {
+    Assembler::new(source).assemble()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip_8::{Chip8, Platform, Quirks};
+    use crate::clock::VirtualClock;
+    use crate::rng::Pcg32;
+
+    // Runs the program long enough to reach its final `loop again`
+    fn run(source: &str) -> Chip8 {
+        let rom = assemble(source).unwrap();
+        let mut chip8 = Chip8::build(
+            &rom,
+            1_000,
+            false,
+            Quirks::new(Platform::Chip8),
+            Box::new(Pcg32::new(0)),
+            Box::new(VirtualClock::new()),
+        );
+        for _ in 0..100 {
+            chip8.cycle();
+        }
+        chip8
+    }
+
+    #[test]
+    fn comparisons_are_lowered_through_vf() {
+        let rom = assemble("if v1 < 5 then v2 := 1").unwrap();
+        assert_eq!(rom, [0x6F, 0x05, 0x8F, 0x17, 0x4F, 0x00, 0x62, 0x01]);
+        let rom = assemble("if v1 >= v3 then v2 := 1").unwrap();
+        assert_eq!(rom, [0x8F, 0x30, 0x8F, 0x17, 0x3F, 0x00, 0x62, 0x01]);
+    }
+
+    #[test]
+    fn comparisons_hold_as_in_octo() {
+        // V0 = 5 and V1 = 7
+        for (condition, holds) in [
+            ("v0 < v1", true),
+            ("v1 < v0", false),
+            ("v0 < 5", false),
+            ("v0 > v1", false),
+            ("v1 > 6", true),
+            ("v1 > 7", false),
+            ("v0 <= 5", true),
+            ("v1 <= v0", false),
+            ("v1 >= 8", false),
+            ("v0 >= v0", true),
+            ("v1 >= 7", true),
+        ] {
+            let source = format!(
+                "v0 := 5 v1 := 7 v2 := 0 if {} then v2 := 1 loop again",
+                condition
+            );
+            let chip8 = run(&source);
+            assert_eq!(chip8.registers()[2] == 1, holds, "{}", condition);
+            assert_eq!(chip8.registers()[..2], [5, 7], "{}", condition);
+        }
+    }
+
+    #[test]
+    fn org_pads_with_zeros_before_bytes() {
+        let rom = assemble("v0 := 1 :org 0x206 :byte 0xAB :byte -1").unwrap();
+        assert_eq!(rom, [0x60, 0x01, 0x00, 0x00, 0x00, 0x00, 0xAB, 0xFF]);
+    }
+
+    #[test]
+    fn invalid_directives_are_rejected() {
+        for (source, error) in [
+            (
+                "v0 := 1 v1 := 2 :org 0x202",
+                "Line 1: :org cannot move back from 0x204 to 0x202",
+            ),
+            (":byte 256", "Line 1: Value out of range: 256"),
+            (":org 0x1000", "Line 1: Value out of range: 0x1000"),
+            (
+                "if vF < 3 then v0 := 1",
+                "Line 1: vF cannot be compared with <",
+            ),
+        ] {
+            assert_eq!(assemble(source).err().as_deref(), Some(error), "{}", source);
+        }
+    }
+}
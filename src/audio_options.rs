@@ -0,0 +1,35 @@
+use clap::ValueEnum;
+
+use crate::constants;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AudioBackend {
+    Sdl,
+    #[cfg(feature = "cpal")]
+    Cpal,
+}
+
+// Settings for the audio device, to trade latency for stability on crackling systems
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioOptions {
+    pub backend: AudioBackend,
+    pub sample_rate: i32,
+    // Samples per device buffer, left to the audio driver when None
+    pub buffer_samples: Option<u16>,
+}
+
+impl AudioOptions {
+    pub fn new() -> Self {
+        AudioOptions {
+            backend: AudioBackend::Sdl,
+            sample_rate: constants::AUDIO_SAMPLE_RATE,
+            buffer_samples: None,
+        }
+    }
+}
+
+impl Default for AudioOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
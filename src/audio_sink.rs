@@ -0,0 +1,26 @@
+use crate::constants;
+
+// An output for the beep, so embedders can play it without SDL
+pub trait AudioSink {
+    // Starts the tone, or keeps it going
+    fn play(&mut self);
+
+    // Stops the tone, fading it out where supported
+    fn stop(&mut self);
+
+    // Plays a repeating 1-bit pattern, most significant bit first, instead of the tone.
+    // Like XO-CHIP audio, the playback rate is in samples of the pattern per second.
+    fn queue_pattern(&mut self, pattern: [u8; constants::AUDIO_PATTERN_LEN], playback_rate: f32);
+
+    // Percentage of the full volume, 0 mutes
+    fn set_volume(&mut self, volume: u8);
+
+    // Called once per frame before the timers are decremented
+    fn update(&mut self, sound_timer: u8) {
+        if sound_timer > 0 {
+            self.play();
+        } else {
+            self.stop();
+        }
+    }
+}
@@ -1,57 +1,113 @@
-use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::mem;
+
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::Sdl;
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32,
-}
-
-impl AudioCallback for SquareWave {
-    type Channel = f32;
-
-    fn callback(&mut self, out: &mut [f32]) {
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-        }
-    }
-}
+use crate::audio_options::{AudioBackend, AudioOptions};
+use crate::audio_sink::AudioSink;
+use crate::constants;
+#[cfg(feature = "cpal")]
+use crate::cpal_beep::CpalBeep;
+use crate::tone::{ToneGenerator, ToneOptions};
 
+// Queues the tone for as long as the sound timer runs instead of toggling a callback device
 pub struct Beep {
-    device: AudioDevice<SquareWave>,
+    queue: AudioQueue<f32>,
+    tone_generator: ToneGenerator,
+    samples_per_frame: usize,
+    is_playing: bool,
 }
 
 impl Beep {
-    pub fn build(sdl: &Sdl) -> Self {
+    pub fn build(sdl: &Sdl, tone: ToneOptions, audio: AudioOptions) -> Self {
         let audio_subsystem = sdl.audio().unwrap();
 
         let desired_spec = AudioSpecDesired {
-            freq: Some(44100),
+            freq: Some(audio.sample_rate),
             channels: Some(1),
-            samples: None,
+            samples: audio.buffer_samples,
         };
 
-        let device = audio_subsystem
-            .open_playback(None, &desired_spec, |spec| SquareWave {
-                phase_inc: 440.0 / spec.freq as f32,
-                phase: 0.0,
-                volume: 0.25,
-            })
+        let queue = audio_subsystem
+            .open_queue::<f32, _>(None, &desired_spec)
             .unwrap();
+        let sample_rate = queue.spec().freq;
+        queue.resume();
+
+        Beep {
+            queue,
+            tone_generator: ToneGenerator::new(sample_rate, tone),
+            samples_per_frame: sample_rate as usize / constants::FRAME_RATE as usize,
+            is_playing: false,
+        }
+    }
+
+    fn queue_samples(&mut self, count: usize) {
+        let mut samples = vec![0.0; count];
+        self.tone_generator.fill(&mut samples);
+        self.queue.queue_audio(&samples).unwrap();
+    }
+
+    fn queued_samples(&self) -> usize {
+        self.queue.size() as usize / mem::size_of::<f32>()
+    }
+
+    // Tops the queue up to the given length, so the tone never restarts
+    fn play_for(&mut self, samples: usize) {
+        let queued = self.queued_samples();
+        if samples > queued {
+            self.tone_generator.set_playing(true);
+            self.queue_samples(samples - queued);
+            self.is_playing = true;
+        }
+    }
+}
+
+impl AudioSink for Beep {
+    fn play(&mut self) {
+        self.play_for(self.samples_per_frame);
+    }
+
+    fn stop(&mut self) {
+        if !self.is_playing {
+            return;
+        }
 
-        Beep { device }
+        // The sound was cut short, so drop the rest of it
+        if self.queued_samples() > self.samples_per_frame {
+            self.queue.clear();
+        }
+        // A frame of silence leaves room for the release of the envelope
+        self.tone_generator.set_playing(false);
+        self.queue_samples(self.samples_per_frame);
+        self.is_playing = false;
+    }
+
+    // Applies to samples queued from now on
+    fn queue_pattern(&mut self, pattern: [u8; constants::AUDIO_PATTERN_LEN], playback_rate: f32) {
+        self.tone_generator.set_pattern(pattern, playback_rate);
     }
 
-    pub fn play(&self) {
-        self.device.resume();
+    // Applies to samples queued from now on
+    fn set_volume(&mut self, volume: u8) {
+        self.tone_generator.set_volume(volume);
     }
 
-    pub fn stop(&self) {
-        self.device.pause();
+    // Queues the whole duration of the sound timer at once, so it is not cut into frames
+    fn update(&mut self, sound_timer: u8) {
+        if sound_timer > 0 {
+            self.play_for(sound_timer as usize * self.samples_per_frame);
+        } else {
+            self.stop();
+        }
+    }
+}
+
+// The beep of the SDL frontend, played by the backend picked with `--audio-backend`
+pub fn build_audio_sink(sdl: &Sdl, tone: ToneOptions, audio: AudioOptions) -> Box<dyn AudioSink> {
+    match audio.backend {
+        AudioBackend::Sdl => Box::new(Beep::build(sdl, tone, audio)),
+        #[cfg(feature = "cpal")]
+        AudioBackend::Cpal => Box::new(CpalBeep::build(tone, audio)),
     }
 }
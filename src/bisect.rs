@@ -0,0 +1,102 @@
+use crate::chip_8::{Chip8, QuirkFlag, Quirks, QUIRK_FLAGS};
+use crate::clock::VirtualClock;
+use crate::constants;
+use crate::input_log::InputPlayer;
+use crate::rng::Pcg32;
+
+fn get_flag(mut quirks: Quirks, flag: QuirkFlag) -> bool {
+    *flag(&mut quirks)
+}
+
+// FNV-1a, see http://www.isthe.com/chongo/tech/comp/fnv/
+fn hash_frame(buffer: &[bool; constants::DISPLAY_LEN]) -> u64 {
+    buffer.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &is_set| {
+        (hash ^ is_set as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+// Each run replays the same input, if any, with the random numbers of its recording
+fn frame_hashes(
+    rom: &[u8],
+    instruction_time: u128,
+    quirks: Quirks,
+    frames: usize,
+    input: Option<&InputPlayer>,
+) -> Vec<u64> {
+    let seed = input.map_or(constants::BISECT_SEED, InputPlayer::seed);
+    let rng = Box::new(Pcg32::new(seed));
+    let clock = Box::new(VirtualClock::new());
+    let mut chip8 = Chip8::build(rom, instruction_time, false, quirks, rng, clock);
+    if let Some(input) = input {
+        chip8.play_input(input.clone());
+    }
+
+    let mut hashes = Vec::with_capacity(frames);
+    while hashes.len() < frames {
+        if chip8.is_timer_decrement_due() {
+            chip8.decrement_timers();
+            hashes.push(hash_frame(chip8.display_buffer()));
+        }
+        while chip8.is_cycle_due() {
+            chip8.cycle();
+        }
+        chip8.sleep_until_due();
+    }
+    hashes
+}
+
+fn first_divergence(reference: &[u64], hashes: &[u64]) -> Option<usize> {
+    reference.iter().zip(hashes).position(|(a, b)| a != b)
+}
+
+pub fn bisect(
+    rom: &[u8],
+    instruction_time: u128,
+    good: Quirks,
+    bad: Quirks,
+    frames: usize,
+    input: Option<&InputPlayer>,
+) {
+    let good_hashes = frame_hashes(rom, instruction_time, good, frames, input);
+    if first_divergence(
+        &good_hashes,
+        &frame_hashes(rom, instruction_time, bad, frames, input),
+    )
+    .is_none()
+    {
+        println!("No difference within {} frames", frames);
+        return;
+    }
+
+    let differing_flags: Vec<usize> = (0..QUIRK_FLAGS.len())
+        .filter(|&index| {
+            let flag = QUIRK_FLAGS[index].1;
+            get_flag(good, flag) != get_flag(bad, flag)
+        })
+        .collect();
+
+    // Try every combination of differing flags, smallest first
+    let mut combinations: Vec<u32> = (1..1 << differing_flags.len()).collect();
+    combinations.sort_by_key(|combination| combination.count_ones());
+    for combination in combinations {
+        let mut quirks = good;
+        let mut toggled_flags = Vec::new();
+        for (bit, &index) in differing_flags.iter().enumerate() {
+            if combination & (1 << bit) != 0 {
+                let (name, flag) = QUIRK_FLAGS[index];
+                *flag(&mut quirks) = get_flag(bad, flag);
+                toggled_flags.push(format!("{}={}", name, get_flag(bad, flag)));
+            }
+        }
+
+        let hashes = frame_hashes(rom, instruction_time, quirks, frames, input);
+        if let Some(frame) = first_divergence(&good_hashes, &hashes) {
+            println!(
+                "Output first diverges at frame {} with: {}",
+                frame,
+                toggled_flags.join(", ")
+            );
+            return;
+        }
+    }
+}
@@ -1,53 +1,43 @@
 use clap::ValueEnum;
-use rand;
-use sdl2::{self, event::Event, keyboard::Keycode, keyboard::Scancode};
-use std::collections::HashSet;
-use std::time;
+use std::{collections::VecDeque, fmt};
 
-use crate::beep::Beep;
+use crate::access::next_accesses;
+use crate::clock::Clock;
 use crate::constants;
-use crate::display::Display;
-
-fn get_epoch_ns() -> u128 {
-    time::SystemTime::now()
-        .duration_since(time::UNIX_EPOCH)
-        .unwrap()
-        .as_nanos()
+use crate::coverage::Coverage;
+use crate::halt::{HaltAction, HaltDetector};
+use crate::hot_spots::HotSpots;
+use crate::input::{InputEventKind, InputState};
+use crate::input_log::{InputPlayer, InputRecorder};
+use crate::inspect_server::InspectServer;
+use crate::instruction::ParsedInstruction;
+use crate::rng::Rng;
+use crate::trace::{TracedState, Tracer};
+
+// Runs after every instruction with its address and value, see `Script`
+pub trait CycleHook {
+    fn after_cycle(&mut self, chip8: &mut Chip8, program_counter: usize, instruction: u16);
 }
 
-fn map_scancode_to_value(scancode: Scancode) -> Option<u8> {
-    match scancode {
-        Scancode::X => Some(0x00),
-        Scancode::Num1 => Some(0x01),
-        Scancode::Num2 => Some(0x02),
-        Scancode::Num3 => Some(0x03),
-        Scancode::Q => Some(0x04),
-        Scancode::W => Some(0x05),
-        Scancode::E => Some(0x06),
-        Scancode::A => Some(0x07),
-        Scancode::S => Some(0x08),
-        Scancode::D => Some(0x09),
-        Scancode::Z => Some(0x0A),
-        Scancode::C => Some(0x0B),
-        Scancode::Num4 => Some(0x0C),
-        Scancode::R => Some(0x0D),
-        Scancode::F => Some(0x0E),
-        Scancode::V => Some(0x0F),
-        _ => None,
-    }
-}
-
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum Platform {
     Chip8,
     SuperChip,
+    // Experimental: CHIP-8 whose ROM may be larger than memory, see `Quirks::bank_switching`
+    Chip8Banked,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Quirks {
-    reset_flag: bool,
-    increment_index_register: bool,
-    shift_in_place: bool,
-    jump_plus_x_register: bool,
+    pub reset_flag: bool,
+    pub increment_index_register: bool,
+    pub shift_in_place: bool,
+    pub jump_plus_x_register: bool,
+    pub wrap_sprites: bool,
+    // FX0A completes when a key is released rather than while one is down
+    pub wait_for_release: bool,
+    // Writing to BANK_SELECT_ADDRESS maps the given bank of the ROM into the program area
+    pub bank_switching: bool,
 }
 
 impl Quirks {
@@ -58,35 +48,171 @@ impl Quirks {
                 increment_index_register: true,
                 shift_in_place: false,
                 jump_plus_x_register: false,
+                wrap_sprites: false,
+                wait_for_release: true,
+                bank_switching: false,
             },
             Platform::SuperChip => Quirks {
                 reset_flag: false,
                 increment_index_register: false,
                 shift_in_place: true,
                 jump_plus_x_register: true,
+                wrap_sprites: false,
+                wait_for_release: false,
+                bank_switching: false,
+            },
+            Platform::Chip8Banked => Quirks {
+                bank_switching: true,
+                ..Quirks::new(Platform::Chip8)
             },
         }
     }
 }
 
-struct ParsedInstruction {
-    opcode: u8,
-    x: u8,
-    y: u8,
-    n: u8,
-    nn: u8,
-    nnn: u16,
+// Accessors for every quirk, named like its field
+pub type QuirkFlag = fn(&mut Quirks) -> &mut bool;
+
+pub const QUIRK_FLAGS: [(&str, QuirkFlag); 6] = [
+    ("reset_flag", |quirks| &mut quirks.reset_flag),
+    ("increment_index_register", |quirks| {
+        &mut quirks.increment_index_register
+    }),
+    ("shift_in_place", |quirks| &mut quirks.shift_in_place),
+    ("jump_plus_x_register", |quirks| {
+        &mut quirks.jump_plus_x_register
+    }),
+    ("wrap_sprites", |quirks| &mut quirks.wrap_sprites),
+    ("wait_for_release", |quirks| &mut quirks.wait_for_release),
+];
+
+fn key_mask(key: u8) -> u16 {
+    if key as usize >= constants::KEY_COUNT {
+        panic!("Key out of range: {:X}", key);
+    }
+    1 << key
+}
+
+// A subroutine call that has not returned yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Call {
+    pub call_site: usize,
+    pub subroutine: usize,
+}
+
+impl Call {
+    pub fn return_address(&self) -> usize {
+        self.call_site + 2
+    }
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{:03X} called at {:03X}",
+            self.subroutine, self.call_site
+        )
+    }
+}
+
+// The machine as a ROM sees it, for save states. Timing, input and the ROM banks are not included.
+#[derive(Clone)]
+pub struct MachineState {
+    ram: [u8; constants::RAM_LEN],
+    registers: [u8; constants::REGISTER_COUNT],
+    stack: [u16; constants::STACK_LEN],
+    subroutines: [u16; constants::STACK_LEN],
+    delay_timer: u8,
+    sound_timer: u8,
+    index_register: u16,
+    program_counter: usize,
+    stack_pointer: u8,
+    display_buffer: [bool; constants::DISPLAY_LEN],
+}
+
+// What turns a state back into an earlier one. Besides the registers, only the memory bytes and
+// pixels that changed are kept, so a delta per instruction is small.
+pub struct StateDelta {
+    registers: [u8; constants::REGISTER_COUNT],
+    stack: [u16; constants::STACK_LEN],
+    subroutines: [u16; constants::STACK_LEN],
+    delay_timer: u8,
+    sound_timer: u8,
+    index_register: u16,
+    program_counter: usize,
+    stack_pointer: u8,
+    // Address and earlier value of each changed byte
+    ram: Vec<(u16, u8)>,
+    // Index of each flipped pixel
+    pixels: Vec<u16>,
 }
 
-impl ParsedInstruction {
-    pub fn build(instruction: u16) -> ParsedInstruction {
-        ParsedInstruction {
-            opcode: ((instruction & 0xF000) >> 8) as u8,
-            x: ((instruction & 0x0F00) >> 8) as u8,
-            y: ((instruction & 0x00F0) >> 4) as u8,
-            n: (instruction & 0x000F) as u8,
-            nn: (instruction & 0x00FF) as u8,
-            nnn: instruction & 0x0FFF,
+impl MachineState {
+    // Brings the state up to date with the machine, returning the delta that undoes the update
+    pub fn update(&mut self, chip8: &Chip8) -> StateDelta {
+        let mut ram = Vec::new();
+        // Most instructions change neither, which comparing the whole arrays finds quickly
+        if self.ram != chip8.ram {
+            for (address, (old, &new)) in self.ram.iter_mut().zip(&chip8.ram).enumerate() {
+                if *old != new {
+                    ram.push((address as u16, *old));
+                    *old = new;
+                }
+            }
+        }
+        let mut pixels = Vec::new();
+        if self.display_buffer != chip8.display_buffer {
+            for (index, (old, &new)) in self
+                .display_buffer
+                .iter_mut()
+                .zip(&chip8.display_buffer)
+                .enumerate()
+            {
+                if *old != new {
+                    pixels.push(index as u16);
+                    *old = new;
+                }
+            }
+        }
+
+        let delta = StateDelta {
+            registers: self.registers,
+            stack: self.stack,
+            subroutines: self.subroutines,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            index_register: self.index_register,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            ram,
+            pixels,
+        };
+        self.registers = chip8.registers;
+        self.stack = chip8.stack;
+        self.subroutines = chip8.subroutines;
+        self.delay_timer = chip8.delay_timer;
+        self.sound_timer = chip8.sound_timer;
+        self.index_register = chip8.index_register;
+        self.program_counter = chip8.program_counter;
+        self.stack_pointer = chip8.stack_pointer;
+        delta
+    }
+
+    // Steps the state back by a delta returned from `update`
+    pub fn undo(&mut self, delta: &StateDelta) {
+        self.registers = delta.registers;
+        self.stack = delta.stack;
+        self.subroutines = delta.subroutines;
+        self.delay_timer = delta.delay_timer;
+        self.sound_timer = delta.sound_timer;
+        self.index_register = delta.index_register;
+        self.program_counter = delta.program_counter;
+        self.stack_pointer = delta.stack_pointer;
+        for &(address, value) in &delta.ram {
+            self.ram[address as usize] = value;
+        }
+        for &index in &delta.pixels {
+            self.display_buffer[index as usize] ^= true;
         }
     }
 }
@@ -95,19 +221,51 @@ pub struct Chip8 {
     ram: [u8; constants::RAM_LEN],
     registers: [u8; constants::REGISTER_COUNT],
     stack: [u16; constants::STACK_LEN],
+    // The subroutine each stack entry called, alongside its return address
+    subroutines: [u16; constants::STACK_LEN],
     delay_timer: u8,
     sound_timer: u8,
     index_register: u16,
     program_counter: usize,
     stack_pointer: u8,
     display_buffer: [bool; constants::DISPLAY_LEN],
+    input: InputState,
+    frame: u64,
+    // Instructions executed so far
+    instruction_count: u64,
+    draw_count: u64,
+    // Address and value of the latest instructions, oldest first, for crash dumps
+    recent_instructions: VecDeque<(usize, u16)>,
+    input_recorder: Option<InputRecorder>,
+    // Replaces the keys set by the frontend while playing
+    input_player: Option<InputPlayer>,
+    tracer: Option<Tracer>,
+    hot_spots: Option<HotSpots>,
+    coverage: Option<Coverage>,
+    cycle_hook: Option<Box<dyn CycleHook>>,
+    halt_detector: Option<HaltDetector>,
+    inspect_server: Option<InspectServer>,
+    // Set once the run should end, frontends stop and the process exits with it
+    exit_code: Option<i32>,
+    max_instructions: Option<u64>,
+    max_frames: Option<u64>,
+    polling_keys: u16,
+    polled_keys: u16,
+    // Keys pressed while FX0A waits, one of which has to be released to end the wait
+    key_wait_presses: u16,
+    banks: Vec<Vec<u8>>,
+    initial_state: MachineState,
 
-    display: Display,
-    beep: Beep,
-    sdl_context: sdl2::Sdl,
     debug: bool,
     instruction_time: u128,
     quirks: Quirks,
+    rng: Box<dyn Rng>,
+    clock: Box<dyn Clock>,
+
+    // Emulated time runs at a percentage of the clock, see `now`
+    speed: u32,
+    speed_change_clock_time: u128,
+    speed_change_time: u128,
 
     last_instruction_time: u128,
     last_decrement_timer_time: u128,
@@ -116,46 +274,86 @@ pub struct Chip8 {
 
 impl Chip8 {
     pub fn build(
-        rom_file: &str,
+        rom: &[u8],
         instruction_time: u128,
-        scale: u32,
-        background_color: (u8, u8, u8),
-        foreground_color: (u8, u8, u8),
         debug: bool,
         quirks: Quirks,
+        rng: Box<dyn Rng>,
+        clock: Box<dyn Clock>,
     ) -> Self {
-        let bytes = std::fs::read(rom_file)
-            .unwrap_or_else(|error| panic!("Failed to read file: {:?}", error));
-
         let mut ram = [0; constants::RAM_LEN];
         ram[constants::FONT_START..constants::FONT_END].copy_from_slice(&constants::FONT);
-        let program_end = constants::PROGRAM_START + bytes.len();
-        ram[constants::PROGRAM_START..program_end].copy_from_slice(&bytes);
+        // A banked ROM is split into program-area sized banks, the first of which is mapped at start
+        let banks: Vec<Vec<u8>> = if quirks.bank_switching {
+            rom.chunks(constants::BANK_LEN)
+                .map(|bank| bank.to_vec())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let program = banks.first().map_or(rom, |bank| bank.as_slice());
+        let program_end = constants::PROGRAM_START + program.len();
+        ram[constants::PROGRAM_START..program_end].copy_from_slice(program);
 
-        let current_epoch_ns = get_epoch_ns();
-        let last_instruction_time = current_epoch_ns;
-        let last_decrement_timer_time = current_epoch_ns;
-        let sdl_context = sdl2::init().unwrap();
-        let display = Display::build(&sdl_context, scale, background_color, foreground_color);
-        let beep = Beep::build(&sdl_context);
+        let current_time = clock.now();
+        let last_instruction_time = current_time;
+        let last_decrement_timer_time = current_time;
 
-        Chip8 {
+        let initial_state = MachineState {
             ram,
             registers: [0; constants::REGISTER_COUNT],
             stack: [0; constants::STACK_LEN],
+            subroutines: [0; constants::STACK_LEN],
             delay_timer: 0,
             sound_timer: 0,
             index_register: 0,
             program_counter: constants::PROGRAM_START,
             stack_pointer: 0,
             display_buffer: [false; constants::DISPLAY_LEN],
+        };
+
+        Chip8 {
+            ram,
+            registers: initial_state.registers,
+            stack: initial_state.stack,
+            subroutines: initial_state.subroutines,
+            delay_timer: initial_state.delay_timer,
+            sound_timer: initial_state.sound_timer,
+            index_register: initial_state.index_register,
+            program_counter: initial_state.program_counter,
+            stack_pointer: initial_state.stack_pointer,
+            display_buffer: initial_state.display_buffer,
+            input: InputState::new(),
+            frame: 0,
+            instruction_count: 0,
+            draw_count: 0,
+            recent_instructions: VecDeque::with_capacity(constants::CRASH_DUMP_INSTRUCTIONS),
+            input_recorder: None,
+            input_player: None,
+            tracer: None,
+            hot_spots: None,
+            coverage: None,
+            cycle_hook: None,
+            halt_detector: None,
+            inspect_server: None,
+            exit_code: None,
+            max_instructions: None,
+            max_frames: None,
+            polling_keys: 0,
+            polled_keys: 0,
+            key_wait_presses: 0,
+            banks,
+            initial_state,
 
-            sdl_context,
-            beep,
-            display,
             debug,
             instruction_time,
             quirks,
+            rng,
+            clock,
+
+            speed: 100,
+            speed_change_clock_time: current_time,
+            speed_change_time: current_time,
 
             last_instruction_time,
             last_decrement_timer_time,
@@ -163,58 +361,450 @@ impl Chip8 {
         }
     }
 
-    pub fn run(&mut self) {
-        let mut event_pump = self.sdl_context.event_pump().unwrap();
+    fn now(&self) -> u128 {
+        self.speed_change_time
+            + (self.clock.now() - self.speed_change_clock_time) * self.speed as u128 / 100
+    }
 
-        'running: loop {
-            let current_epoch_ns = get_epoch_ns();
-            let valid_decrement_timer_time = current_epoch_ns - self.last_decrement_timer_time
-                >= constants::TIMER_DECREMENT_TIME;
-            if valid_decrement_timer_time {
-                if self.delay_timer > 0 {
-                    self.delay_timer -= 1;
-                }
-                if self.sound_timer > 0 {
-                    self.beep.play();
-                    self.sound_timer -= 1;
-                } else {
-                    self.beep.stop();
-                }
-                self.last_decrement_timer_time = current_epoch_ns;
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    // Percentage of real time that passes for the core, 0 pauses it
+    pub fn set_speed(&mut self, speed: u32) {
+        self.speed_change_time = self.now();
+        self.speed_change_clock_time = self.clock.now();
+        self.speed = speed;
+    }
+
+    // Lets time pass for the core regardless of its speed, e.g. a frame at a time while paused
+    pub fn advance_time(&mut self, duration: u128) {
+        self.speed_change_time += duration;
+    }
+
+    pub fn is_timer_decrement_due(&self) -> bool {
+        match self.tick_player() {
+            Some(player) => player.is_tick_due(self.instruction_count),
+            None => {
+                self.now().saturating_sub(self.last_decrement_timer_time)
+                    >= constants::TIMER_DECREMENT_TIME
             }
+        }
+    }
 
-            let pressed_keys: HashSet<u8> = event_pump
-                .keyboard_state()
-                .pressed_scancodes()
-                .filter_map(map_scancode_to_value)
-                .collect();
-
-            for event in event_pump.poll_iter() {
-                match event {
-                    Event::Quit { .. }
-                    | Event::KeyDown {
-                        keycode: Some(Keycode::Escape),
-                        ..
-                    } => break 'running,
-                    Event::KeyDown {
-                        keycode: Some(Keycode::Return),
-                        ..
-                    } => {
-                        if self.debug {
-                            self.cycle(&pressed_keys);
-                        }
-                    }
-                    _ => {}
+    // The input log being replayed, if the timers tick as it recorded them
+    fn tick_player(&self) -> Option<&InputPlayer> {
+        self.input_player
+            .as_ref()
+            .filter(|player| player.replays_ticks())
+    }
+
+    // In debug mode nothing runs on its own and frontends step by calling `cycle`
+    pub fn set_debug(&mut self, debug: bool) {
+        if self.debug && !debug {
+            // Instructions are due from now on rather than catching up on the time spent stepping
+            self.last_instruction_time = self.now();
+        }
+        self.debug = debug;
+    }
+
+    pub fn is_cycle_due(&self) -> bool {
+        !self.debug
+            && self.exit_code.is_none()
+            && self.now().saturating_sub(self.last_instruction_time) >= self.instruction_time
+            // A replayed tick comes first
+            && !self
+                .tick_player()
+                .is_some_and(|player| player.is_tick_due(self.instruction_count))
+    }
+
+    pub fn sleep_until_due(&self) {
+        let mut next_due_time = self.timer_decrement_due_time();
+        if !self.debug {
+            next_due_time = next_due_time.min(self.last_instruction_time + self.instruction_time);
+        }
+
+        self.sleep_until(next_due_time);
+    }
+
+    // Skips the wake-ups between instructions, which then run in a burst at the start of a frame
+    pub fn sleep_until_timer_decrement_due(&self) {
+        self.sleep_until(self.timer_decrement_due_time());
+    }
+
+    // A replayed tick is due at an instruction rather than a time, so a replay waits for the next
+    // instruction instead
+    fn timer_decrement_due_time(&self) -> u128 {
+        match self.tick_player() {
+            Some(_) => self.last_instruction_time + self.instruction_time,
+            None => self.last_decrement_timer_time + constants::TIMER_DECREMENT_TIME,
+        }
+    }
+
+    fn sleep_until(&self, due_time: u128) {
+        let current_time = self.now();
+        if due_time > current_time {
+            // Nothing becomes due while paused, so just wait for the next frame of the frontend
+            let duration = match self.speed {
+                0 => constants::TIMER_DECREMENT_TIME,
+                speed => (due_time - current_time) * 100 / speed as u128,
+            };
+            self.clock.sleep(duration);
+        }
+    }
+
+    pub fn display_buffer(&self) -> &[bool; constants::DISPLAY_LEN] {
+        &self.display_buffer
+    }
+
+    pub fn take_update_display(&mut self) -> bool {
+        std::mem::take(&mut self.update_display)
+    }
+
+    pub fn input(&self) -> &InputState {
+        &self.input
+    }
+
+    pub fn set_pressed_keys(&mut self, pressed_keys: u16) {
+        if self.input_player.is_none() {
+            self.apply_pressed_keys(pressed_keys);
+        }
+    }
+
+    // Presses a single key without touching the others, for tests and frontends tracking keys
+    // one at a time
+    pub fn press_key(&mut self, key: u8) {
+        self.set_pressed_keys(self.input.pressed_keys() | key_mask(key));
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        self.set_pressed_keys(self.input.pressed_keys() & !key_mask(key));
+    }
+
+    fn apply_pressed_keys(&mut self, pressed_keys: u16) {
+        if pressed_keys != self.input.pressed_keys() {
+            if let Some(recorder) = &mut self.input_recorder {
+                if let Err(error) =
+                    recorder.record(self.instruction_count, self.frame, pressed_keys)
+                {
+                    eprintln!("Failed to record input, recording stopped: {:?}", error);
+                    self.input_recorder = None;
                 }
             }
+        }
+        self.input.set_pressed_keys(pressed_keys, self.frame);
+    }
+
+    // Logs every keypad state change from now on, see `InputRecorder`
+    pub fn record_input(&mut self, recorder: InputRecorder) {
+        self.input_recorder = Some(recorder);
+    }
+
+    // Takes the keys from a recorded log instead of the frontend from now on, the core should be
+    // built with the seed of the log for the replay to match
+    pub fn play_input(&mut self, player: InputPlayer) {
+        self.input_player = Some(player);
+    }
+
+    // Logs every executed instruction in the tracer's range from now on, see `Tracer`
+    pub fn trace(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    // Counts executed instructions by address and kind from now on
+    pub fn profile_hot_spots(&mut self) {
+        self.hot_spots = Some(HotSpots::new());
+    }
 
-            let valid_cycle_time =
-                current_epoch_ns - self.last_instruction_time >= self.instruction_time;
-            if valid_cycle_time && !self.debug {
-                self.cycle(&pressed_keys);
-                self.last_instruction_time = get_epoch_ns();
+    pub fn hot_spots(&self) -> Option<&HotSpots> {
+        self.hot_spots.as_ref()
+    }
+
+    // Records which bytes are executed or read as data from now on, see `Coverage`
+    pub fn track_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn hook_cycles(&mut self, hook: Box<dyn CycleHook>) {
+        self.cycle_hook = Some(hook);
+    }
+
+    // Watches for the ROM halting from now on, see `HaltDetector`
+    pub fn detect_halts(&mut self, action: HaltAction) {
+        self.halt_detector = Some(HaltDetector::new(action));
+    }
+
+    fn halt(&mut self, address: usize) {
+        let Some(halt_detector) = &self.halt_detector else {
+            return;
+        };
+        eprintln!("ROM halted in a loop at {:03X}", address);
+        match halt_detector.action() {
+            HaltAction::Notify => {}
+            // Frontends with a pause pick this up, see `SdlFrontend`
+            HaltAction::Pause => self.set_speed(0),
+            HaltAction::Exit => self.exit(constants::HALT_EXIT_CODE),
+        }
+    }
+
+    // Keeps the server's view of the machine up to date, once per frame
+    pub fn serve_inspection(&mut self, inspect_server: InspectServer) {
+        inspect_server.update(self);
+        self.inspect_server = Some(inspect_server);
+    }
+
+    // Ends the run, no more cycles become due
+    pub fn exit(&mut self, code: i32) {
+        self.exit_code = Some(code);
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    // Exits once this many instructions ran in total
+    pub fn limit_instructions(&mut self, max_instructions: u64) {
+        self.max_instructions = Some(max_instructions);
+    }
+
+    // Exits once this many frames passed in total, counting frames keeps runs reproducible
+    pub fn limit_frames(&mut self, max_frames: u64) {
+        self.max_frames = Some(max_frames);
+    }
+
+    fn traced_state(&self) -> TracedState {
+        TracedState {
+            registers: self.registers,
+            index_register: self.index_register,
+        }
+    }
+
+    pub fn is_playing_input(&self) -> bool {
+        self.input_player.is_some()
+    }
+
+    // Hands the keys back to the frontend mid-replay, so a recording continues from there
+    pub fn stop_input_playback(&mut self) {
+        self.input_player = None;
+    }
+
+    // Memory mapped pointer extension, not part of any original platform
+    pub fn set_mouse_state(&mut self, position: Option<(u8, u8)>, buttons: u8) {
+        let (x, y) = position.unwrap_or((
+            constants::MOUSE_OUTSIDE_DISPLAY,
+            constants::MOUSE_OUTSIDE_DISPLAY,
+        ));
+        self.ram[constants::MOUSE_X_ADDRESS] = x;
+        self.ram[constants::MOUSE_Y_ADDRESS] = y;
+        self.ram[constants::MOUSE_BUTTONS_ADDRESS] = buttons;
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    // DXYN instructions executed since the start
+    pub fn draw_count(&self) -> u64 {
+        self.draw_count
+    }
+
+    pub fn registers(&self) -> &[u8; constants::REGISTER_COUNT] {
+        &self.registers
+    }
+
+    pub fn index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    // Return addresses of the active subroutine calls, innermost last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[1..=self.stack_pointer as usize]
+    }
+
+    // The subroutine calls leading to the current instruction, innermost first
+    pub fn call_stack(&self) -> Vec<Call> {
+        (1..=self.stack_pointer as usize)
+            .rev()
+            .map(|depth| Call {
+                call_site: self.stack[depth] as usize - 2,
+                subroutine: self.subroutines[depth] as usize,
+            })
+            .collect()
+    }
+
+    // Panics with the call chain appended, so crash reports show how the ROM got there
+    fn crash(&self, message: String) -> ! {
+        let calls: String = self
+            .call_stack()
+            .iter()
+            .map(|call| format!("\n  in {}", call))
+            .collect();
+        panic!("{}{}", message, calls);
+    }
+
+    pub fn recent_instructions(&self) -> &VecDeque<(usize, u16)> {
+        &self.recent_instructions
+    }
+
+    pub fn ram(&self) -> &[u8; constants::RAM_LEN] {
+        &self.ram
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    // Setters for debuggers changing the machine between cycles
+    pub fn set_register(&mut self, register: usize, value: u8) {
+        self.registers[register] = value;
+    }
+
+    pub fn set_index_register(&mut self, value: u16) {
+        self.index_register = value;
+    }
+
+    pub fn set_program_counter(&mut self, address: usize) {
+        self.program_counter = address;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    // Unlike a ROM writing memory, this has no side effects such as switching banks
+    pub fn poke(&mut self, address: usize, value: u8) {
+        self.ram[address] = value;
+    }
+
+    pub fn save_state(&self) -> MachineState {
+        MachineState {
+            ram: self.ram,
+            registers: self.registers,
+            stack: self.stack,
+            subroutines: self.subroutines,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            index_register: self.index_register,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            display_buffer: self.display_buffer,
+        }
+    }
+
+    pub fn load_state(&mut self, state: &MachineState) {
+        self.ram = state.ram;
+        self.registers = state.registers;
+        self.stack = state.stack;
+        self.subroutines = state.subroutines;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.index_register = state.index_register;
+        self.program_counter = state.program_counter;
+        self.stack_pointer = state.stack_pointer;
+        self.display_buffer = state.display_buffer;
+        // A key wait in the loaded state starts over
+        self.key_wait_presses = 0;
+        self.update_display = true;
+    }
+
+    // Restarts the ROM, a banked ROM starts over from its first bank
+    pub fn reset(&mut self) {
+        let initial_state = self.initial_state.clone();
+        self.load_state(&initial_state);
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn decrement_timers(&mut self) {
+        if let Some(player) = self
+            .input_player
+            .as_mut()
+            .filter(|player| player.replays_ticks())
+        {
+            let pressed_keys = player.take_keys(self.instruction_count);
+            player.take_tick();
+            if let Some(pressed_keys) = pressed_keys {
+                self.apply_pressed_keys(pressed_keys);
             }
         }
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+        self.frame += 1;
+        if let Some(recorder) = &mut self.input_recorder {
+            if let Err(error) = recorder.record_tick(self.instruction_count, self.frame) {
+                eprintln!("Failed to record input, recording stopped: {:?}", error);
+                self.input_recorder = None;
+            }
+        }
+        self.polled_keys = std::mem::take(&mut self.polling_keys);
+        self.last_decrement_timer_time = self.now();
+        if self
+            .max_frames
+            .is_some_and(|max_frames| self.frame >= max_frames)
+        {
+            self.exit(constants::LIMIT_EXIT_CODE);
+        }
+        if let Some(inspect_server) = &self.inspect_server {
+            inspect_server.update(self);
+        }
+    }
+
+    // Keys checked by EX9E, EXA1 or FX0A during the last frame, FX0A checks all of them
+    pub fn polled_keys(&self) -> u16 {
+        self.polled_keys
+    }
+
+    fn poll_key(&mut self, key: u8) {
+        if (key as usize) < constants::KEY_COUNT {
+            self.polling_keys |= 1 << key;
+        }
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8) {
+        self.ram[address] = value;
+
+        if self.quirks.bank_switching && address == constants::BANK_SELECT_ADDRESS {
+            let bank = self.banks.get(value as usize).unwrap_or_else(|| {
+                self.crash(format!(
+                    "Bank {} out of range, ROM has {}",
+                    value,
+                    self.banks.len()
+                ))
+            });
+            let bank_end = constants::PROGRAM_START + bank.len();
+            self.ram[constants::PROGRAM_START..bank_end].copy_from_slice(bank);
+            self.ram[bank_end..].fill(0);
+        }
     }
 
     fn fetch_instruction(&mut self) -> u16 {
@@ -225,44 +815,55 @@ impl Chip8 {
         ((instruction_first_byte as u16) << 8) | instruction_second_byte as u16
     }
 
-    fn cycle(&mut self, pressed_keys: &HashSet<u8>) {
-        let instruction = self.fetch_instruction();
-        let parsed_instruction = ParsedInstruction::build(instruction);
+    pub fn cycle(&mut self) {
+        if let Some(pressed_keys) = self
+            .input_player
+            .as_mut()
+            .and_then(|player| player.take_keys(self.instruction_count))
+        {
+            self.apply_pressed_keys(pressed_keys);
+        }
 
-        if self.debug {
-            println!(
-                "Instruction: {:04X} | Opcode: {:X} | X: {:X} | Y: {:X} | N: {:X} | NN: {:X} | NNN: {:X}",
-                instruction,
-                parsed_instruction.opcode,
-                parsed_instruction.x,
-                parsed_instruction.y,
-                parsed_instruction.n,
-                parsed_instruction.nn,
-                parsed_instruction.nnn,
-            );
-            for i in 0..constants::REGISTER_COUNT {
-                print!("V{:X}: {:X} | ", i, self.registers[i]);
+        let program_counter = self.program_counter;
+        let traced_state = self
+            .tracer
+            .as_ref()
+            .filter(|tracer| tracer.is_traced(program_counter))
+            .map(|_| self.traced_state());
+        if self.coverage.is_some() {
+            let accesses = next_accesses(self);
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record(program_counter, &accesses);
             }
-            println!("I: {:X}", self.index_register);
         }
+        let instruction = self.fetch_instruction();
+        if self.recent_instructions.len() == constants::CRASH_DUMP_INSTRUCTIONS {
+            self.recent_instructions.pop_front();
+        }
+        self.recent_instructions
+            .push_back((program_counter, instruction));
+        if let Some(hot_spots) = &mut self.hot_spots {
+            hot_spots.record(program_counter, instruction);
+        }
+        let parsed_instruction = ParsedInstruction::build(instruction);
 
         match parsed_instruction.opcode {
-            0x00 => match parsed_instruction.nn {
+            0x0 => match parsed_instruction.nn {
                 0xE0 => self.clear_screen(),
                 0xEE => self.return_from_subroutine(),
-                _ => panic!(
+                _ => self.crash(format!(
                     "Unrecognized second byte: {:X} for opcode: {:X}",
                     parsed_instruction.nn, parsed_instruction.opcode
-                ),
+                )),
             },
-            0x10 => self.jump_to_address(parsed_instruction.nnn),
-            0x20 => self.call_subroutine_at_address(parsed_instruction.nnn),
-            0x30 => self.skip_if_equal_to_value(parsed_instruction.x, parsed_instruction.nn),
-            0x40 => self.skip_if_not_equal_to_value(parsed_instruction.x, parsed_instruction.nn),
-            0x50 => self.skip_if_equal_to_register(parsed_instruction.x, parsed_instruction.y),
-            0x60 => self.set_register_to_value(parsed_instruction.x, parsed_instruction.nn),
-            0x70 => self.add_value_to_register(parsed_instruction.x, parsed_instruction.nn),
-            0x80 => match parsed_instruction.n {
+            0x1 => self.jump_to_address(parsed_instruction.nnn),
+            0x2 => self.call_subroutine_at_address(parsed_instruction.nnn),
+            0x3 => self.skip_if_equal_to_value(parsed_instruction.x, parsed_instruction.nn),
+            0x4 => self.skip_if_not_equal_to_value(parsed_instruction.x, parsed_instruction.nn),
+            0x5 => self.skip_if_equal_to_register(parsed_instruction.x, parsed_instruction.y),
+            0x6 => self.set_register_to_value(parsed_instruction.x, parsed_instruction.nn),
+            0x7 => self.add_value_to_register(parsed_instruction.x, parsed_instruction.nn),
+            0x8 => match parsed_instruction.n {
                 0x00 => self.set_register_to_register(parsed_instruction.x, parsed_instruction.y),
                 0x01 => self.or_register_with_register(parsed_instruction.x, parsed_instruction.y),
                 0x02 => self.and_register_with_register(parsed_instruction.x, parsed_instruction.y),
@@ -283,31 +884,31 @@ impl Chip8 {
                     parsed_instruction.x,
                     parsed_instruction.y,
                 ),
-                _ => panic!(
+                _ => self.crash(format!(
                     "Unrecognized fourth nibble: {:X} for opcode: {:X}",
                     parsed_instruction.n, parsed_instruction.opcode
-                ),
+                )),
             },
-            0x90 => self.skip_if_not_equal_to_register(parsed_instruction.x, parsed_instruction.y),
-            0xA0 => self.set_index_register_to_value(parsed_instruction.nnn),
-            0xB0 => self.jump_to_address_with_offset(parsed_instruction.x, parsed_instruction.nnn),
-            0xC0 => self.set_register_to_random(parsed_instruction.x, parsed_instruction.nn),
-            0xD0 => self.display(
+            0x9 => self.skip_if_not_equal_to_register(parsed_instruction.x, parsed_instruction.y),
+            0xA => self.set_index_register_to_value(parsed_instruction.nnn),
+            0xB => self.jump_to_address_with_offset(parsed_instruction.x, parsed_instruction.nnn),
+            0xC => self.set_register_to_random(parsed_instruction.x, parsed_instruction.nn),
+            0xD => self.display(
                 parsed_instruction.x,
                 parsed_instruction.y,
                 parsed_instruction.n,
             ),
-            0xE0 => match parsed_instruction.nn {
-                0x9E => self.skip_if_key_pressed(parsed_instruction.x, pressed_keys),
-                0xA1 => self.skip_if_key_not_pressed(parsed_instruction.x, pressed_keys),
-                _ => panic!(
+            0xE => match parsed_instruction.nn {
+                0x9E => self.skip_if_key_pressed(parsed_instruction.x),
+                0xA1 => self.skip_if_key_not_pressed(parsed_instruction.x),
+                _ => self.crash(format!(
                     "Unrecognized second byte: {:X} for opcode: {:X}",
                     parsed_instruction.nn, parsed_instruction.opcode
-                ),
+                )),
             },
-            0xF0 => match parsed_instruction.nn {
+            0xF => match parsed_instruction.nn {
                 0x07 => self.set_register_to_delay_timer(parsed_instruction.x),
-                0x0A => self.set_register_to_key_with_wait(parsed_instruction.x, pressed_keys),
+                0x0A => self.set_register_to_key_with_wait(parsed_instruction.x),
                 0x15 => self.set_delay_timer_to_register(parsed_instruction.x),
                 0x18 => self.set_sound_timer_to_register(parsed_instruction.x),
                 0x1E => self.add_register_to_index_register(parsed_instruction.x),
@@ -315,18 +916,56 @@ impl Chip8 {
                 0x33 => self.set_index_register_to_bcd(parsed_instruction.x),
                 0x55 => self.store_registers_in_memory(parsed_instruction.x),
                 0x65 => self.load_registers_from_memory(parsed_instruction.x),
-                _ => panic!(
+                _ => self.crash(format!(
                     "Unrecognized second byte: {:X} for opcode: {:X}",
                     parsed_instruction.nn, parsed_instruction.opcode
-                ),
+                )),
             },
-            _ => panic!("Unrecognized opcode: {:X}", parsed_instruction.opcode),
+            _ => self.crash(format!(
+                "Unrecognized opcode: {:X}",
+                parsed_instruction.opcode
+            )),
+        }
+
+        if let Some(before) = traced_state {
+            let after = self.traced_state();
+            if let Some(tracer) = &mut self.tracer {
+                if let Err(error) = tracer.trace(program_counter, instruction, &before, &after) {
+                    eprintln!("Failed to write trace, tracing stopped: {:?}", error);
+                    self.tracer = None;
+                }
+            }
         }
 
-        if self.update_display {
-            self.display.render_buffer(self.display_buffer);
-            self.update_display = false;
+        // Taken out while it runs, as it gets the whole machine
+        if let Some(mut hook) = self.cycle_hook.take() {
+            hook.after_cycle(self, program_counter, instruction);
+            self.cycle_hook = Some(hook);
         }
+        if let Some(mut halt_detector) = self.halt_detector.take() {
+            let halts = halt_detector.record(self, program_counter, instruction);
+            self.halt_detector = Some(halt_detector);
+            if halts {
+                self.halt(program_counter);
+            }
+        }
+
+        self.input.clear_events();
+        self.instruction_count += 1;
+        if self
+            .max_instructions
+            .is_some_and(|max_instructions| self.instruction_count >= max_instructions)
+        {
+            self.exit(constants::LIMIT_EXIT_CODE);
+        }
+        // Advance by a fixed step so cycles lost to a blocking present are caught up, while steps
+        // of the debugger happen whenever the user asks and must not schedule ahead of the clock
+        self.last_instruction_time = if self.debug {
+            self.now()
+        } else {
+            let catch_up_limit = self.now().saturating_sub(constants::MAX_CATCH_UP_TIME);
+            (self.last_instruction_time + self.instruction_time).max(catch_up_limit)
+        };
     }
 
     // 0x00E0
@@ -338,7 +977,7 @@ impl Chip8 {
     // 0x00EE
     fn return_from_subroutine(&mut self) {
         if self.stack_pointer == 0 {
-            panic!("Stack pointer is 0, cannot return from subroutine");
+            self.crash("Stack pointer is 0, cannot return from subroutine".to_string());
         }
         self.program_counter = self.stack[self.stack_pointer as usize] as usize;
         self.stack_pointer -= 1;
@@ -351,8 +990,15 @@ impl Chip8 {
 
     // 0x2NNN
     fn call_subroutine_at_address(&mut self, address: u16) {
+        if self.stack_pointer as usize == constants::STACK_LEN - 1 {
+            self.crash(format!(
+                "Stack overflow, cannot call subroutine {:03X}",
+                address
+            ));
+        }
         self.stack_pointer += 1;
         self.stack[self.stack_pointer as usize] = self.program_counter as u16;
+        self.subroutines[self.stack_pointer as usize] = address;
         self.program_counter = address as usize;
     }
 
@@ -483,27 +1129,35 @@ impl Chip8 {
 
     // 0xCXNN
     fn set_register_to_random(&mut self, register: u8, value: u8) {
-        let random_value = rand::random::<u8>();
+        let random_value = self.rng.next_u8();
         self.registers[register as usize] = random_value & value;
     }
 
     // 0xDXYN
     fn display(&mut self, x_register: u8, y_register: u8, height: u8) {
-        let x_coordinate = self.registers[x_register as usize] % constants::DISPLAY_WIDTH as u8;
-        let y_coordinate = self.registers[y_register as usize] % constants::DISPLAY_HEIGHT as u8;
+        self.draw_count += 1;
+        let x_coordinate = self.registers[x_register as usize] as usize % constants::DISPLAY_WIDTH;
+        let y_coordinate = self.registers[y_register as usize] as usize % constants::DISPLAY_HEIGHT;
         self.registers[0x0F] = 0;
 
-        for row in 0..height {
-            let current_y_coordinate = (y_coordinate + row) as usize;
+        for row in 0..height as usize {
+            let mut current_y_coordinate = y_coordinate + row;
             if current_y_coordinate >= constants::DISPLAY_HEIGHT {
-                break;
+                if !self.quirks.wrap_sprites {
+                    break;
+                }
+                current_y_coordinate %= constants::DISPLAY_HEIGHT;
             }
 
-            let sprite_data = self.ram[(self.index_register + row as u16) as usize];
+            let sprite_address = (self.index_register as usize + row) % constants::RAM_LEN;
+            let sprite_data = self.ram[sprite_address];
             for column in 0..8 {
-                let current_x_coordinate = (x_coordinate + column) as usize;
+                let mut current_x_coordinate = x_coordinate + column;
                 if current_x_coordinate >= constants::DISPLAY_WIDTH {
-                    break;
+                    if !self.quirks.wrap_sprites {
+                        break;
+                    }
+                    current_x_coordinate %= constants::DISPLAY_WIDTH;
                 }
 
                 let current_coordinate =
@@ -523,17 +1177,19 @@ impl Chip8 {
     }
 
     // 0xEX9E
-    fn skip_if_key_pressed(&mut self, register: u8, pressed_keys: &HashSet<u8>) {
+    fn skip_if_key_pressed(&mut self, register: u8) {
         let key = self.registers[register as usize];
-        if pressed_keys.contains(&key) {
+        self.poll_key(key);
+        if self.input.is_pressed(key) {
             self.program_counter += 2;
         }
     }
 
     // 0xEXA1
-    fn skip_if_key_not_pressed(&mut self, register: u8, pressed_keys: &HashSet<u8>) {
+    fn skip_if_key_not_pressed(&mut self, register: u8) {
         let key = self.registers[register as usize];
-        if !pressed_keys.contains(&key) {
+        self.poll_key(key);
+        if !self.input.is_pressed(key) {
             self.program_counter += 2;
         }
     }
@@ -544,12 +1200,35 @@ impl Chip8 {
     }
 
     // 0xFX0A
-    fn set_register_to_key_with_wait(&mut self, register: u8, pressed_keys: &HashSet<u8>) {
-        if pressed_keys.is_empty() {
-            self.program_counter -= 2;
+    fn set_register_to_key_with_wait(&mut self, register: u8) {
+        self.polling_keys = u16::MAX;
+        let key = if self.quirks.wait_for_release {
+            // A press and release since the wait began, so neither a key held down registers twice
+            // nor does releasing a key held from before end the wait
+            let mut released_key = None;
+            for event in self.input.events() {
+                let mask = 1 << event.key;
+                match event.kind {
+                    InputEventKind::Press => self.key_wait_presses |= mask,
+                    InputEventKind::Release if self.key_wait_presses & mask != 0 => {
+                        released_key = Some(event.key);
+                        break;
+                    }
+                    InputEventKind::Release => {}
+                }
+            }
+            released_key
         } else {
-            let key = pressed_keys.iter().next().unwrap();
-            self.registers[register as usize] = *key;
+            let pressed_keys = self.input.pressed_keys();
+            (pressed_keys != 0).then(|| pressed_keys.trailing_zeros() as u8)
+        };
+
+        match key {
+            Some(key) => {
+                self.registers[register as usize] = key;
+                self.key_wait_presses = 0;
+            }
+            None => self.program_counter -= 2,
         }
     }
 
@@ -581,19 +1260,22 @@ impl Chip8 {
         let tens = (value / 10) % 10;
         let ones = value % 10;
 
-        self.ram[self.index_register as usize] = hundreds;
-        self.ram[self.index_register as usize + 1] = tens;
-        self.ram[self.index_register as usize + 2] = ones;
+        self.write_ram(self.index_register as usize, hundreds);
+        self.write_ram(self.index_register as usize + 1, tens);
+        self.write_ram(self.index_register as usize + 2, ones);
     }
 
     // 0xFX55
     fn store_registers_in_memory(&mut self, x: u8) {
         for i in 0..=x {
             if self.quirks.increment_index_register {
-                self.ram[self.index_register as usize] = self.registers[i as usize];
+                self.write_ram(self.index_register as usize, self.registers[i as usize]);
                 self.index_register += 1;
             } else {
-                self.ram[self.index_register as usize + i as usize] = self.registers[i as usize];
+                self.write_ram(
+                    self.index_register as usize + i as usize,
+                    self.registers[i as usize],
+                );
             }
         }
     }
@@ -610,3 +1292,234 @@ impl Chip8 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::rng::Pcg32;
+
+    #[test]
+    fn random_numbers_follow_the_seed() {
+        // Random bytes 71 3C F4 25 of PCG32 seeded with 42, masked by NN
+        let rom = [0xC0, 0xFF, 0xC1, 0x0F, 0xC2, 0xF0, 0xC3, 0xA5];
+        let mut chip8 = Chip8::build(
+            &rom,
+            1_000,
+            false,
+            Quirks::new(Platform::Chip8),
+            Box::new(Pcg32::new(42)),
+            Box::new(VirtualClock::new()),
+        );
+        for _ in 0..4 {
+            chip8.cycle();
+        }
+        assert_eq!(chip8.registers[..4], [0x71, 0x0C, 0xF0, 0x25]);
+    }
+
+    // Runs instructions as they become due, sleeping on the clock in between, until the timers
+    // are due to tick. Returns the number of instructions run.
+    fn run_until_tick(chip8: &mut Chip8) -> u64 {
+        let mut instruction_count = 0;
+        while !chip8.is_timer_decrement_due() {
+            while chip8.is_cycle_due() {
+                chip8.cycle();
+                instruction_count += 1;
+            }
+            chip8.sleep_until_due();
+        }
+        instruction_count
+    }
+
+    #[test]
+    fn timers_tick_once_per_frame_of_virtual_time() {
+        // An instruction time that divides a frame evenly
+        let instructions_per_frame = 4_999;
+        // Delay timer = 3, then loop
+        let mut chip8 = Chip8::build(
+            &[0x60, 0x03, 0xF0, 0x15, 0x12, 0x04],
+            constants::TIMER_DECREMENT_TIME / instructions_per_frame as u128,
+            false,
+            Quirks::new(Platform::Chip8),
+            Box::new(Pcg32::new(0)),
+            Box::new(VirtualClock::new()),
+        );
+        // The first instruction is due one instruction time after the start
+        assert_eq!(run_until_tick(&mut chip8), instructions_per_frame - 1);
+        assert_eq!(chip8.delay_timer, 3);
+
+        for frame in 1..=5 {
+            chip8.decrement_timers();
+            assert_eq!(chip8.delay_timer, 3u8.saturating_sub(frame));
+            assert_eq!(run_until_tick(&mut chip8), instructions_per_frame);
+        }
+    }
+
+    const SPRITE_ADDRESS: usize = 0x300;
+
+    fn build(rom: &[u8], quirks: Quirks) -> Chip8 {
+        Chip8::build(
+            rom,
+            1_000,
+            false,
+            quirks,
+            Box::new(Pcg32::new(0)),
+            Box::new(VirtualClock::new()),
+        )
+    }
+
+    // Draws a solid sprite of the given height at x, y with DXYN
+    fn draw(x: u8, y: u8, height: u8, wrap_sprites: bool) -> Chip8 {
+        let mut quirks = Quirks::new(Platform::Chip8);
+        quirks.wrap_sprites = wrap_sprites;
+        // V0 = x, V1 = y, I = sprite, draw, then jump back to the draw
+        let draw = 0x10 | height;
+        let mut rom = vec![0x60, x, 0x61, y, 0xA3, 0x00, 0xD0, draw, 0x12, 0x06];
+        rom.resize(SPRITE_ADDRESS - constants::PROGRAM_START, 0);
+        rom.resize(rom.len() + height as usize, 0xFF);
+        let mut chip8 = build(&rom, quirks);
+        for _ in 0..4 {
+            chip8.cycle();
+        }
+        chip8
+    }
+
+    fn is_set(chip8: &Chip8, x: usize, y: usize) -> bool {
+        chip8.display_buffer()[x + y * constants::DISPLAY_WIDTH]
+    }
+
+    fn set_pixels(chip8: &Chip8) -> usize {
+        chip8
+            .display_buffer()
+            .iter()
+            .filter(|&&is_set| is_set)
+            .count()
+    }
+
+    #[test]
+    fn sprite_at_last_pixel_is_clipped() {
+        let chip8 = draw(63, 31, 2, false);
+        assert!(is_set(&chip8, 63, 31));
+        assert_eq!(set_pixels(&chip8), 1);
+    }
+
+    #[test]
+    fn sprite_at_last_pixel_wraps() {
+        let chip8 = draw(63, 31, 2, true);
+        for (x, y) in [(63, 31), (0, 31), (6, 31), (63, 0), (0, 0), (6, 0)] {
+            assert!(is_set(&chip8, x, y), "({}, {}) is not set", x, y);
+        }
+        assert_eq!(set_pixels(&chip8), 16);
+    }
+
+    #[test]
+    fn coordinates_beyond_display_wrap_before_drawing() {
+        for wrap_sprites in [false, true] {
+            let chip8 = draw(64 + 3, 32 + 2, 1, wrap_sprites);
+            assert!((3..11).all(|x| is_set(&chip8, x, 2)));
+            assert_eq!(set_pixels(&chip8), 8);
+
+            let chip8 = draw(255, 255, 1, wrap_sprites);
+            assert!(is_set(&chip8, 63, 31));
+        }
+    }
+
+    #[test]
+    fn tall_sprite_near_255_is_clipped() {
+        // 250 is column 58 and row 26, leaving 6 columns and 6 rows of the display
+        let chip8 = draw(250, 250, 15, false);
+        assert!(is_set(&chip8, 58, 26));
+        assert!(is_set(&chip8, 63, 31));
+        assert!(!is_set(&chip8, 0, 0));
+        assert_eq!(set_pixels(&chip8), 6 * 6);
+    }
+
+    #[test]
+    fn tall_sprite_near_255_wraps() {
+        let chip8 = draw(250, 250, 15, true);
+        assert!(is_set(&chip8, 58, 26));
+        assert!(is_set(&chip8, 1, 8));
+        assert!(!is_set(&chip8, 2, 9));
+        assert_eq!(set_pixels(&chip8), 15 * 8);
+    }
+
+    #[test]
+    fn drawing_twice_erases_and_reports_collision() {
+        let mut chip8 = draw(63, 31, 2, true);
+        assert_eq!(chip8.registers[0x0F], 0);
+        // Jump back to the draw and draw again
+        chip8.cycle();
+        chip8.cycle();
+        assert_eq!(chip8.registers[0x0F], 1);
+        assert_eq!(set_pixels(&chip8), 0);
+    }
+
+    #[test]
+    fn deltas_undo_instructions() {
+        // Draws a random digit, stores the BCD of a counter and clears the screen in a loop
+        let rom = [
+            0x60, 0x00, 0x61, 0x00, 0xF0, 0x29, 0xD0, 0x15, 0xC0, 0x0F, 0x71, 0x01, 0xA3, 0x00,
+            0xF1, 0x33, 0x00, 0xE0, 0x12, 0x04,
+        ];
+        let mut chip8 = build(&rom, Quirks::new(Platform::Chip8));
+        let mut states = vec![chip8.save_state()];
+        let mut latest_state = chip8.save_state();
+        let mut deltas = Vec::new();
+        for _ in 0..200 {
+            chip8.cycle();
+            states.push(chip8.save_state());
+            deltas.push(latest_state.update(&chip8));
+        }
+
+        for (delta, state) in deltas.iter().zip(&states).rev() {
+            latest_state.undo(delta);
+            assert!(latest_state.ram == state.ram);
+            assert!(latest_state.display_buffer == state.display_buffer);
+            assert_eq!(latest_state.registers, state.registers);
+            assert_eq!(latest_state.index_register, state.index_register);
+            assert_eq!(latest_state.program_counter, state.program_counter);
+        }
+    }
+
+    #[test]
+    fn key_wait_needs_press_and_release() {
+        let mut quirks = Quirks::new(Platform::Chip8);
+        quirks.wait_for_release = true;
+        // V0 = 0, then wait for a key into V3
+        let mut chip8 = build(&[0x60, 0x00, 0xF3, 0x0A], quirks);
+        chip8.press_key(0x5);
+        chip8.cycle();
+        chip8.cycle();
+
+        // Releasing the key held since before the wait does not end it
+        chip8.release_key(0x5);
+        chip8.cycle();
+        assert_eq!(chip8.program_counter(), 0x202);
+
+        chip8.press_key(0x7);
+        chip8.cycle();
+        assert_eq!(chip8.program_counter(), 0x202);
+        chip8.release_key(0x7);
+        chip8.cycle();
+        assert_eq!(chip8.program_counter(), 0x204);
+        assert_eq!(chip8.registers()[0x3], 0x7);
+    }
+
+    #[test]
+    fn resuming_after_debugger_steps_keeps_the_schedule() {
+        // Loop forever
+        let mut chip8 = build(&[0x12, 0x00], Quirks::new(Platform::Chip8));
+        chip8.set_debug(true);
+        for _ in 0..10 {
+            chip8.cycle();
+        }
+        chip8.set_debug(false);
+        assert!(!chip8.is_cycle_due());
+
+        // The next instruction is due one instruction time later, without catching up on the steps
+        chip8.sleep_until_due();
+        assert!(chip8.is_cycle_due());
+        chip8.cycle();
+        assert!(!chip8.is_cycle_due());
+    }
+}
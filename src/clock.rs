@@ -0,0 +1,90 @@
+use std::{cell::Cell, thread, time};
+
+use crate::constants;
+
+// Parses a positive number of seconds into the frames passing in that time
+pub fn parse_seconds(value: &str) -> Result<u64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => {
+            Ok((seconds * constants::FRAME_RATE as f64).round() as u64)
+        }
+        _ => Err(format!(
+            "Invalid duration, expected a positive number of seconds: {}",
+            value
+        )),
+    }
+}
+
+pub trait Clock {
+    // Monotonic time in nanoseconds
+    fn now(&self) -> u128;
+
+    // Hint that nothing needs to happen for the given nanoseconds
+    fn sleep(&self, duration: u128);
+}
+
+pub struct SystemClock {
+    start: time::Instant,
+    sleep_granularity: u128,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self::with_sleep_granularity(constants::SLEEP_GRANULARITY)
+    }
+
+    // Sleeps shorter than the granularity are spun instead, as are its last nanoseconds otherwise
+    pub fn with_sleep_granularity(sleep_granularity: u128) -> Self {
+        SystemClock {
+            start: time::Instant::now(),
+            sleep_granularity,
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> u128 {
+        self.start.elapsed().as_nanos()
+    }
+
+    fn sleep(&self, duration: u128) {
+        // Short sleeps overshoot by more than they save, so keep spinning for those
+        if duration > self.sleep_granularity {
+            let duration = duration - self.sleep_granularity;
+            thread::sleep(time::Duration::from_nanos(duration as u64));
+        }
+    }
+}
+
+// Time only passes when sleeping, so a core driven by it runs as fast as possible
+pub struct VirtualClock {
+    now: Cell<u128>,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        VirtualClock { now: Cell::new(0) }
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> u128 {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: u128) {
+        self.now.set(self.now.get() + duration);
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+
+use crate::chip_8::Chip8;
+use crate::constants;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(usize),
+    Index,
+    ProgramCounter,
+    DelayTimer,
+    SoundTimer,
+    // A byte of memory, written as `[0x300]` or `[300]`
+    Memory(usize),
+    Value(u16),
+}
+
+impl Operand {
+    pub fn value(&self, chip8: &Chip8) -> u16 {
+        match *self {
+            Operand::Register(register) => chip8.registers()[register] as u16,
+            Operand::Index => chip8.index_register(),
+            Operand::ProgramCounter => chip8.program_counter() as u16,
+            Operand::DelayTimer => chip8.delay_timer() as u16,
+            Operand::SoundTimer => chip8.sound_timer() as u16,
+            Operand::Memory(address) => chip8.ram()[address] as u16,
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+impl Operand {
+    // Whether the debugger's `set` command can store the value here
+    pub fn check_value(&self, value: u16) -> Result<(), String> {
+        let limit = match self {
+            Operand::Register(_)
+            | Operand::DelayTimer
+            | Operand::SoundTimer
+            | Operand::Memory(_) => 0xFF,
+            Operand::Index => constants::RAM_LEN as u16 - 1,
+            // Both bytes of the next instruction are fetched from here, odd addresses included as
+            // jumps can reach them too
+            Operand::ProgramCounter => constants::RAM_LEN as u16 - 2,
+            Operand::Value(_) => return Err(format!("Cannot set a value: {}", self)),
+        };
+        if value > limit {
+            return Err(format!("{} cannot hold {:#X}", self, value));
+        }
+        Ok(())
+    }
+
+    // Takes a value that passed `check_value`
+    pub fn set(&self, chip8: &mut Chip8, value: u16) {
+        match *self {
+            Operand::Register(register) => chip8.set_register(register, value as u8),
+            Operand::Index => chip8.set_index_register(value),
+            Operand::ProgramCounter => chip8.set_program_counter(value as usize),
+            Operand::DelayTimer => chip8.set_delay_timer(value as u8),
+            Operand::SoundTimer => chip8.set_sound_timer(value as u8),
+            Operand::Memory(address) => chip8.poke(address, value as u8),
+            Operand::Value(_) => {}
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(register) => write!(formatter, "V{:X}", register),
+            Operand::Index => write!(formatter, "I"),
+            Operand::ProgramCounter => write!(formatter, "PC"),
+            Operand::DelayTimer => write!(formatter, "DT"),
+            Operand::SoundTimer => write!(formatter, "ST"),
+            Operand::Memory(address) => write!(formatter, "[{:#X}]", address),
+            Operand::Value(value) => write!(formatter, "{:#X}", value),
+        }
+    }
+}
+
+// Hexadecimal with a `0x` prefix, decimal otherwise
+pub fn parse_number(value: &str) -> Option<u16> {
+    match value.strip_prefix("0x") {
+        Some(digits) => u16::from_str_radix(digits, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+// Hexadecimal with or without a `0x` prefix, as addresses are shown everywhere else
+pub fn parse_address(value: &str) -> Result<usize, String> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    usize::from_str_radix(digits, 16)
+        .ok()
+        .filter(|&address| address < constants::RAM_LEN)
+        .ok_or_else(|| format!("Invalid address: {}", value))
+}
+
+pub fn parse_operand(value: &str) -> Result<Operand, String> {
+    let invalid = || format!("Invalid operand: {}", value);
+    let operand = match value.to_ascii_uppercase().as_str() {
+        "I" => Operand::Index,
+        "PC" => Operand::ProgramCounter,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        name if name.len() == 2 && name.starts_with('V') => {
+            Operand::Register(usize::from_str_radix(&name[1..], 16).map_err(|_| invalid())?)
+        }
+        _ => match value
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            Some(address) => Operand::Memory(parse_address(address.trim())?),
+            None => Operand::Value(parse_number(value).ok_or_else(invalid)?),
+        },
+    };
+    Ok(operand)
+}
+
+// Longer operators first, so `<=` is not taken for `<`
+const COMPARISONS: [&str; 6] = ["==", "!=", "<=", ">=", "<", ">"];
+
+// A comparison between machine state and values, e.g. `V3 == 0x1F` or `I >= 0xE00`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    left: Operand,
+    comparison: &'static str,
+    right: Operand,
+}
+
+impl Condition {
+    pub fn is_met(&self, chip8: &Chip8) -> bool {
+        let (left, right) = (self.left.value(chip8), self.right.value(chip8));
+        match self.comparison {
+            "==" => left == right,
+            "!=" => left != right,
+            "<=" => left <= right,
+            ">=" => left >= right,
+            "<" => left < right,
+            _ => left > right,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{} {} {}",
+            self.left, self.comparison, self.right
+        )
+    }
+}
+
+pub fn parse_condition(value: &str) -> Result<Condition, String> {
+    let (comparison, (left, right)) = COMPARISONS
+        .iter()
+        .find_map(|&comparison| Some((comparison, value.split_once(comparison)?)))
+        .ok_or_else(|| format!("Missing comparison in condition: {}", value))?;
+    Ok(Condition {
+        left: parse_operand(left.trim())?,
+        comparison,
+        right: parse_operand(right.trim())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip_8::{Platform, Quirks};
+    use crate::clock::VirtualClock;
+    use crate::rng::Pcg32;
+
+    // V3 = 0x1F, I = 0xE00 and 7 at 0x300
+    fn build() -> Chip8 {
+        let mut chip8 = Chip8::build(
+            &[],
+            1_000,
+            false,
+            Quirks::new(Platform::Chip8),
+            Box::new(Pcg32::new(0)),
+            Box::new(VirtualClock::new()),
+        );
+        chip8.set_register(3, 0x1F);
+        chip8.set_index_register(0xE00);
+        chip8.poke(0x300, 7);
+        chip8
+    }
+
+    #[test]
+    fn comparisons_are_evaluated_against_the_machine() {
+        let chip8 = build();
+        for (condition, is_met) in [
+            ("V3 == 0x1F", true),
+            ("v3 == 31", true),
+            ("V3 != 0x1F", false),
+            ("V3 < 0x20", true),
+            ("V3 > 0x1F", false),
+            ("V3 <= 0x1F", true),
+            ("V3 >= 0x20", false),
+            ("I >= 0xE00", true),
+            ("PC == 0x200", true),
+            ("[300] == 7", true),
+            ("[0x300] > V3", false),
+        ] {
+            let parsed = parse_condition(condition).unwrap();
+            assert_eq!(parsed.is_met(&chip8), is_met, "{}", condition);
+        }
+    }
+
+    #[test]
+    fn longer_operators_are_matched_first() {
+        assert_eq!(parse_condition("V1<=V2").unwrap().to_string(), "V1 <= V2");
+        assert_eq!(parse_condition("DT >= 3").unwrap().to_string(), "DT >= 0x3");
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        for condition in ["V3", "VG == 1", "[1000] == 0", "V3 == 0x1G", "V3 == 70000"] {
+            assert!(parse_condition(condition).is_err(), "{}", condition);
+        }
+    }
+
+    #[test]
+    fn values_are_checked_against_what_holds_them() {
+        assert!(Operand::Register(0).check_value(0xFF).is_ok());
+        assert!(Operand::Register(0).check_value(0x100).is_err());
+        assert!(Operand::Index.check_value(0xFFF).is_ok());
+        assert!(Operand::ProgramCounter.check_value(0xFFE).is_ok());
+        assert!(Operand::ProgramCounter.check_value(0xFFF).is_err());
+        assert!(Operand::Value(1).check_value(1).is_err());
+    }
+}
@@ -2,12 +2,45 @@ pub const RAM_LEN: usize = 0x1000;
 pub const PROGRAM_START: usize = 0x200;
 pub const REGISTER_COUNT: usize = 16;
 pub const STACK_LEN: usize = 256;
+pub const KEY_COUNT: usize = 16;
+// Most locations one instruction reads or writes, DXYN reads VX, VY, I and up to 15 sprite rows
+pub const MAX_ACCESSED_LOCATIONS: usize = 18;
+
+pub const MOUSE_X_ADDRESS: usize = 0x1F0;
+pub const MOUSE_Y_ADDRESS: usize = 0x1F1;
+pub const MOUSE_BUTTONS_ADDRESS: usize = 0x1F2;
+pub const MOUSE_OUTSIDE_DISPLAY: u8 = 0xFF;
+pub const BANK_SELECT_ADDRESS: usize = 0x1FF;
+pub const BANK_LEN: usize = RAM_LEN - PROGRAM_START;
 
 pub const DISPLAY_WIDTH: usize = 64;
 pub const DISPLAY_HEIGHT: usize = 32;
 pub const DISPLAY_LEN: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
 
-pub const TIMER_DECREMENT_TIME: u128 = 1_000_000_000 / 60;
+pub const FRAME_RATE: u64 = 60;
+pub const TIMER_DECREMENT_TIME: u128 = 1_000_000_000 / FRAME_RATE as u128;
+pub const BISECT_SEED: u64 = 0;
+// Addresses listed in the hot spot report, the JSON report has all of them
+pub const HOT_SPOT_COUNT: usize = 20;
+// Latest instructions kept for crash dumps
+pub const CRASH_DUMP_INSTRUCTIONS: usize = 32;
+pub const CRASH_DUMP_FILE: &str = "crash-dump.txt";
+// Exit status when a ROM halts with `--on-halt exit`
+pub const HALT_EXIT_CODE: i32 = 3;
+// Exit status when a run reaches `--max-instructions` or `--exit-after-seconds`
+pub const LIMIT_EXIT_CODE: i32 = 0;
+// How long the inspection server waits for a request to arrive
+pub const INSPECT_TIMEOUT_SECONDS: u64 = 5;
+// Bytes per row of the coverage map
+pub const COVERAGE_ROW_LEN: usize = 32;
+// Matching instructions shown before the first divergence of two traces
+pub const TRACE_DIFF_CONTEXT: usize = 5;
+pub const SLEEP_GRANULARITY: u128 = 1_000_000;
+pub const MAX_CATCH_UP_TIME: u128 = TIMER_DECREMENT_TIME;
+// Speeds in percent cycled through for slow motion
+pub const SLOW_MOTION_SPEEDS: [u32; 3] = [100, 50, 25];
+pub const FAST_FORWARD_FACTOR: u32 = 4;
+pub const SPEED_STEPS: [u32; 6] = [25, 50, 100, 200, 400, 800];
 
 pub const FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -30,4 +63,117 @@ pub const FONT: [u8; 80] = [
 pub const FONT_START: usize = 0x50;
 pub const FONT_END: usize = FONT_START + FONT.len();
 
+pub const AUDIO_SAMPLE_RATE: i32 = 44100;
+pub const BEEP_FREQUENCY: f32 = 440.0;
+pub const BEEP_DUTY_CYCLE: f32 = 0.5;
+// Bytes in a 1-bit audio pattern, 128 samples
+pub const AUDIO_PATTERN_LEN: usize = 16;
+// Amplitude of the beep at a volume of 100
+pub const BEEP_VOLUME: f32 = 0.25;
+pub const DEFAULT_VOLUME: u8 = 100;
+// Time the beep takes to fade in and out, avoiding pops
+pub const BEEP_ENVELOPE_TIME: f32 = 0.005;
+pub const VOLUME_STEP: u8 = 10;
+
+// Where keys bound at runtime are saved without `--keymap`
+pub const KEYMAP_FILE: &str = "keymap.toml";
+// SDL scancode name of each keypad key
+pub const DEFAULT_KEYMAP: [(&str, u8); KEY_COUNT] = [
+    ("X", 0x0),
+    ("1", 0x1),
+    ("2", 0x2),
+    ("3", 0x3),
+    ("Q", 0x4),
+    ("W", 0x5),
+    ("E", 0x6),
+    ("A", 0x7),
+    ("S", 0x8),
+    ("D", 0x9),
+    ("Z", 0xA),
+    ("C", 0xB),
+    ("4", 0xC),
+    ("R", 0xD),
+    ("F", 0xE),
+    ("V", 0xF),
+];
+pub const NUMPAD_KEYMAP: [(&str, u8); KEY_COUNT] = [
+    ("Keypad 0", 0x0),
+    ("Keypad 1", 0x1),
+    ("Keypad 2", 0x2),
+    ("Keypad 3", 0x3),
+    ("Keypad 4", 0x4),
+    ("Keypad 5", 0x5),
+    ("Keypad 6", 0x6),
+    ("Keypad 7", 0x7),
+    ("Keypad 8", 0x8),
+    ("Keypad 9", 0x9),
+    ("Keypad /", 0xA),
+    ("Keypad *", 0xB),
+    ("Keypad -", 0xC),
+    ("Keypad +", 0xD),
+    ("Keypad Enter", 0xE),
+    ("Keypad .", 0xF),
+];
+// SDL game controller button name of each bound keypad key
+pub const DEFAULT_CONTROLLER_MAP: [(&str, u8); 6] = [
+    ("dpup", 0x5),
+    ("dpleft", 0x7),
+    ("dpdown", 0x8),
+    ("dpright", 0x9),
+    ("a", 0x6),
+    ("b", 0x4),
+];
+// Keypad keys of the touch D-pad, matching the controller d-pad
+pub const DEFAULT_TOUCH_KEYS: [u8; 4] = [0x5, 0x7, 0x8, 0x9];
+// Mouse ID SDL gives mouse events synthesized from touches
+pub const TOUCH_MOUSE_ID: u32 = u32::MAX;
+
+pub const TERMINAL_KEY_HOLD_FRAMES: u64 = 10;
+pub const BRAILLE_BLANK: u32 = 0x2800;
+// Bit of each dot in a braille character, by row and column
+pub const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
 pub const WINDOW_TITLE: &str = "CHIP-8";
+pub const DEFAULT_DPI: f32 = 96.0;
+pub const SCANLINE_ALPHA: u8 = 96;
+pub const PIXEL_CORNER_ALPHA: u8 = 128;
+pub const IDLE_DIM_ALPHA: u8 = 160;
+pub const SOUND_FLASH_TINT: (u8, u8, u8, u8) = (255, 160, 0, 96);
+pub const IDLE_SHIFT_INTERVAL: u64 = 60 * FRAME_RATE;
+pub const OVERLAY_GLYPH_ADVANCE: (u32, u32) = (4, 6);
+pub const OVERLAY_TEXT_SCALE_DIVISOR: u32 = 4;
+pub const OVERLAY_BACKGROUND_ALPHA: u8 = 192;
+pub const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+pub const KEYPAD_CELL_SIZE: (u32, u32) = (5, 7);
+pub const IDLE_SHIFT_OFFSETS: [(i32, i32); 4] = [(0, 0), (1, 0), (1, 1), (0, 1)];
+
+pub const DEBUGGER_HELP: &str =
+    "step [count], back [count], next, finish, continue, run [until draw|key|return|frame N], pause, break|bp add [address|file:line|if condition], \
+    delete|bp del address|file:line|if condition, watch|rwatch [Vx|I|address], unwatch location, mem [address [length]], sprites address [length [height]], regs, stack, \
+    set Vx|I|PC|DT|ST|[address] value, poke address value, quit (Enter repeats); addresses are hex, other numbers decimal unless prefixed with 0x";
+pub const DEBUGGER_HEX_ROW_LEN: usize = 16;
+// Rows of the sprites the sprite view shows by default
+pub const DEBUGGER_SPRITE_HEIGHT: usize = 8;
+// Columns between two sprites of the sprite view
+pub const DEBUGGER_SPRITE_SPACING: usize = 2;
+// Rows Page Up and Page Down scroll the memory and sprite views by
+pub const DEBUGGER_HEX_PAGE_ROWS: isize = 16;
+// Machine states kept for stepping backwards
+pub const DEBUGGER_HISTORY_LEN: usize = 1024;
+// Instructions shown before and after the program counter
+pub const DEBUGGER_CODE_CONTEXT: (usize, usize) = (5, 10);
+// Source lines shown before and after the line of the program counter
+pub const DEBUGGER_SOURCE_CONTEXT: (usize, usize) = (5, 10);
+pub const DEBUGGER_STACK_ROWS: usize = 16;
+// Largest packet gdb may send, enough to write all of memory in hex
+pub const GDB_PACKET_SIZE: usize = 2 * RAM_LEN + 32;
+pub const DEBUGGER_MEMORY_ROWS: usize = 4;
+pub const DEBUGGER_MEMORY_ROW_LEN: usize = 8;
+
+pub const GIF_MIN_DELAY: u64 = 2;
+pub const GIF_QUANTIZE_SPEED: i32 = 10;
@@ -0,0 +1,172 @@
+use std::fmt::Write;
+
+use crate::access::{Access, Accesses, Location};
+use crate::constants;
+use crate::disassembler::disassemble;
+use crate::symbols::Symbols;
+
+// What a byte of the ROM was used for, a byte read as data may also have been executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Usage {
+    Executed,
+    Read,
+    Unused,
+}
+
+impl Usage {
+    fn symbol(self) -> char {
+        match self {
+            Usage::Executed => '#',
+            Usage::Read => 'd',
+            Usage::Unused => '.',
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Usage::Executed => "executed",
+            Usage::Read => "read",
+            Usage::Unused => "unused",
+        }
+    }
+}
+
+// Tracks which bytes of the program area were executed or read as data, e.g. by drawing a sprite,
+// so the rest shows up as dead code. Banked ROMs are covered as the program area, whatever bank
+// is mapped.
+pub struct Coverage {
+    rom_len: usize,
+    executed: Vec<bool>,
+    read: Vec<bool>,
+}
+
+impl Coverage {
+    pub fn new(rom_len: usize) -> Self {
+        Coverage {
+            rom_len: rom_len.min(constants::BANK_LEN),
+            executed: vec![false; constants::RAM_LEN],
+            read: vec![false; constants::RAM_LEN],
+        }
+    }
+
+    // Takes the instruction's accesses as worked out before it runs, see `next_accesses`
+    pub fn record(&mut self, program_counter: usize, accesses: &Accesses) {
+        self.executed[program_counter] = true;
+        self.executed[(program_counter + 1) % constants::RAM_LEN] = true;
+        for (location, access) in accesses.iter() {
+            if let (Location::Memory(address), Access::Read) = (location, access) {
+                self.read[address] = true;
+            }
+        }
+    }
+
+    fn usage(&self, address: usize) -> Usage {
+        if self.executed[address] {
+            Usage::Executed
+        } else if self.read[address] {
+            Usage::Read
+        } else {
+            Usage::Unused
+        }
+    }
+
+    fn rom_addresses(&self) -> std::ops::Range<usize> {
+        constants::PROGRAM_START..constants::PROGRAM_START + self.rom_len
+    }
+
+    fn count(&self, usage: Usage) -> usize {
+        self.rom_addresses()
+            .filter(|&address| self.usage(address) == usage)
+            .count()
+    }
+
+    fn summary(&self) -> String {
+        let executed = self.count(Usage::Executed);
+        format!(
+            "Executed {} of {} ROM bytes ({:.1}%), {} read as data, {} never used",
+            executed,
+            self.rom_len,
+            executed as f64 * 100.0 / self.rom_len.max(1) as f64,
+            self.count(Usage::Read),
+            self.count(Usage::Unused)
+        )
+    }
+
+    // Runs of bytes that were neither executed nor read, as first and last address
+    fn unused_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for address in self.rom_addresses() {
+            if self.usage(address) != Usage::Unused {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == address => *end = address,
+                _ => ranges.push((address, address)),
+            }
+        }
+        ranges
+    }
+
+    // A row of characters per bytes of the ROM, `#` for executed, `d` for read as data and `.`
+    // for never used, followed by the unused ranges
+    pub fn text_report(&self, symbols: &Symbols) -> String {
+        let mut text = format!("{}\n\n", self.summary());
+        for row_start in self.rom_addresses().step_by(constants::COVERAGE_ROW_LEN) {
+            let row_end = (row_start + constants::COVERAGE_ROW_LEN).min(self.rom_addresses().end);
+            let row: String = (row_start..row_end)
+                .map(|address| self.usage(address).symbol())
+                .collect();
+            writeln!(text, "{:03X}  {}", row_start, row).unwrap();
+        }
+
+        writeln!(text, "\nNever used  Bytes  Label").unwrap();
+        for (start, end) in self.unused_ranges() {
+            let line = format!(
+                "{:03X}-{:03X}     {:<5}  {}",
+                start,
+                end,
+                end - start + 1,
+                symbols.name(start).unwrap_or_default()
+            );
+            writeln!(text, "{}", line.trim_end()).unwrap();
+        }
+        text
+    }
+
+    // The ROM's bytes colored by usage, hovering over one shows its address and instruction
+    pub fn html_report(&self, ram: &[u8], symbols: &Symbols) -> String {
+        let mut rows = String::new();
+        for row_start in self.rom_addresses().step_by(constants::COVERAGE_ROW_LEN) {
+            let row_end = (row_start + constants::COVERAGE_ROW_LEN).min(self.rom_addresses().end);
+            write!(rows, "<div>{:03X} ", row_start).unwrap();
+            for address in row_start..row_end {
+                let instruction =
+                    (ram[address] as u16) << 8 | ram[(address + 1) % constants::RAM_LEN] as u16;
+                let label = symbols
+                    .name(address)
+                    .map_or_else(String::new, |name| format!("{}: ", name));
+                write!(
+                    rows,
+                    "<span class=\"{}\" title=\"{}{:03X} {}\">{:02X}</span>",
+                    self.usage(address).class(),
+                    label,
+                    address,
+                    disassemble(instruction),
+                    ram[address]
+                )
+                .unwrap();
+            }
+            writeln!(rows, "</div>").unwrap();
+        }
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Coverage</title>\n\
+             <style>\nbody {{ font-family: monospace; }}\nspan {{ margin-right: 0.5ch; }}\n\
+             .executed {{ background: #9e9; }}\n.read {{ background: #9cf; }}\n\
+             .unused {{ background: #f99; }}\n</style>\n</head>\n<body>\n<p>{}</p>\n\
+             <p><span class=\"executed\">executed</span><span class=\"read\">read as data</span>\
+             <span class=\"unused\">never used</span></p>\n{}</body>\n</html>\n",
+            self.summary(),
+            rows
+        )
+    }
+}
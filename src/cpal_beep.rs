@@ -0,0 +1,82 @@
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, SampleRate, Stream, StreamConfig};
+
+use crate::audio_options::AudioOptions;
+use crate::audio_sink::AudioSink;
+use crate::constants;
+use crate::tone::{ToneGenerator, ToneOptions};
+
+// Plays the beep through cpal, for systems where SDL audio is problematic
+pub struct CpalBeep {
+    // Playback stops when the stream is dropped
+    _stream: Stream,
+    tone_generator: Arc<Mutex<ToneGenerator>>,
+}
+
+impl CpalBeep {
+    pub fn build(tone: ToneOptions, audio: AudioOptions) -> Self {
+        let device = cpal::default_host()
+            .default_output_device()
+            .unwrap_or_else(|| panic!("No audio output device found"));
+        let channels = device
+            .default_output_config()
+            .map_or(1, |config| config.channels());
+        let config = StreamConfig {
+            channels,
+            sample_rate: SampleRate(audio.sample_rate as u32),
+            buffer_size: audio.buffer_samples.map_or(BufferSize::Default, |samples| {
+                BufferSize::Fixed(samples as u32)
+            }),
+        };
+
+        let tone_generator = Arc::new(Mutex::new(ToneGenerator::new(audio.sample_rate, tone)));
+        let callback_tone_generator = Arc::clone(&tone_generator);
+        let mut samples = Vec::new();
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |out: &mut [f32], _| {
+                    // The tone is mono, so all channels of a frame get the same sample
+                    samples.resize(out.len() / channels as usize, 0.0);
+                    callback_tone_generator.lock().unwrap().fill(&mut samples);
+                    for (frame, &sample) in out.chunks_exact_mut(channels as usize).zip(&samples) {
+                        frame.fill(sample);
+                    }
+                },
+                |error| eprintln!("Audio stream error: {:?}", error),
+                None,
+            )
+            .unwrap_or_else(|error| panic!("Failed to open audio stream: {:?}", error));
+        stream
+            .play()
+            .unwrap_or_else(|error| panic!("Failed to start audio stream: {:?}", error));
+
+        CpalBeep {
+            _stream: stream,
+            tone_generator,
+        }
+    }
+}
+
+impl AudioSink for CpalBeep {
+    fn play(&mut self) {
+        self.tone_generator.lock().unwrap().set_playing(true);
+    }
+
+    fn stop(&mut self) {
+        self.tone_generator.lock().unwrap().set_playing(false);
+    }
+
+    fn queue_pattern(&mut self, pattern: [u8; constants::AUDIO_PATTERN_LEN], playback_rate: f32) {
+        self.tone_generator
+            .lock()
+            .unwrap()
+            .set_pattern(pattern, playback_rate);
+    }
+
+    fn set_volume(&mut self, volume: u8) {
+        self.tone_generator.lock().unwrap().set_volume(volume);
+    }
+}
@@ -0,0 +1,107 @@
+use std::{fmt::Write, fs, io, panic, path::Path, sync::Mutex};
+
+use crate::chip_8::Chip8;
+use crate::constants;
+use crate::disassembler::disassemble;
+
+// The message of the latest panic, with where it happened
+static PANIC_MESSAGE: Mutex<Option<String>> = Mutex::new(None);
+
+// Keeps the message of each panic for the crash dump, then reports it as usual
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if let Ok(mut message) = PANIC_MESSAGE.lock() {
+            *message = Some(info.to_string());
+        }
+        default_hook(info);
+    }));
+}
+
+// Everything needed to understand a crash without the ROM at hand: the panic message, registers,
+// stack, the latest instructions and all of memory
+pub fn crash_dump(chip8: &Chip8) -> String {
+    let message = PANIC_MESSAGE
+        .lock()
+        .ok()
+        .and_then(|message| message.clone())
+        .unwrap_or_else(|| "Unknown error".to_string());
+    let mut dump = format!(
+        "{} {} crash dump\n{}\n\nInstructions executed: {}, frame {}\n{:?}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        message,
+        chip8.instruction_count(),
+        chip8.frame(),
+        chip8.quirks()
+    );
+
+    writeln!(dump, "\nRegisters").unwrap();
+    for (row, values) in chip8.registers().chunks(4).enumerate() {
+        let values: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(column, value)| format!("V{:X} {:02X}", row * 4 + column, value))
+            .collect();
+        writeln!(dump, "{}", values.join("  ")).unwrap();
+    }
+    writeln!(
+        dump,
+        "I {:03X}  PC {:03X}  SP {:X}  DT {}  ST {}",
+        chip8.index_register(),
+        chip8.program_counter(),
+        chip8.stack_pointer(),
+        chip8.delay_timer(),
+        chip8.sound_timer()
+    )
+    .unwrap();
+
+    writeln!(dump, "\nCall stack, innermost first").unwrap();
+    for call in chip8.call_stack() {
+        writeln!(dump, "{}", call).unwrap();
+    }
+    let stack: Vec<String> = chip8
+        .stack()
+        .iter()
+        .map(|address| format!("{:03X}", address))
+        .collect();
+    writeln!(dump, "Stack: {}", stack.join(" ")).unwrap();
+
+    writeln!(
+        dump,
+        "\nLast {} instructions, oldest first",
+        constants::CRASH_DUMP_INSTRUCTIONS
+    )
+    .unwrap();
+    for &(address, instruction) in chip8.recent_instructions() {
+        writeln!(
+            dump,
+            "{:03X}  {:04X}  {}",
+            address,
+            instruction,
+            disassemble(instruction)
+        )
+        .unwrap();
+    }
+
+    writeln!(dump, "\nRAM").unwrap();
+    for (row, bytes) in chip8
+        .ram()
+        .chunks(constants::DEBUGGER_HEX_ROW_LEN)
+        .enumerate()
+    {
+        let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        writeln!(
+            dump,
+            "{:03X}  {}",
+            row * constants::DEBUGGER_HEX_ROW_LEN,
+            bytes.join(" ")
+        )
+        .unwrap();
+    }
+    dump
+}
+
+pub fn write_crash_dump(chip8: &Chip8, path: &Path) -> io::Result<()> {
+    fs::write(path, crash_dump(chip8))
+}
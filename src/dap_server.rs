@@ -0,0 +1,653 @@
+use std::{collections::BTreeSet, io, net::TcpStream, path::Path};
+
+use serde_json::{json, Value};
+
+use crate::chip_8::Chip8;
+use crate::condition::{parse_number, parse_operand, Operand};
+use crate::constants;
+use crate::debugger::DebuggerCommand;
+use crate::disassembler::disassemble_with_symbols;
+use crate::remote_debugger::{accept, read_available, write_all, RemoteDebugger};
+use crate::source_map::SourceMap;
+use crate::symbols::Symbols;
+
+const THREAD_ID: u64 = 1;
+// A disassembly of memory from the program start with an instruction per line, the source of
+// instructions missing from the source map
+const DISASSEMBLY_REFERENCE: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+
+fn instruction_at(chip8: &Chip8, address: usize) -> u16 {
+    let ram = chip8.ram();
+    (ram[address] as u16) << 8 | ram[(address + 1) % constants::RAM_LEN] as u16
+}
+
+fn line_of(address: usize) -> Option<usize> {
+    (address >= constants::PROGRAM_START && (address - constants::PROGRAM_START).is_multiple_of(2))
+        .then(|| (address - constants::PROGRAM_START) / 2 + 1)
+}
+
+fn address_of(line: usize) -> Option<usize> {
+    let address = line
+        .checked_sub(1)?
+        .checked_mul(2)?
+        .checked_add(constants::PROGRAM_START)?;
+    (address < constants::RAM_LEN).then_some(address)
+}
+
+fn disassembly_source() -> Value {
+    json!({ "name": "disassembly", "sourceReference": DISASSEMBLY_REFERENCE })
+}
+
+// The Octo source and line of an address from the source map
+fn source_location(source_map: &SourceMap, address: usize) -> Option<(Value, usize)> {
+    let line = source_map.line(address)?;
+    let source = json!({
+        "name": source_map.file_name(),
+        "path": source_map.source_path()?,
+    });
+    Some((source, line))
+}
+
+fn disassembly(chip8: &Chip8, symbols: &Symbols) -> String {
+    (constants::PROGRAM_START..constants::RAM_LEN - 1)
+        .step_by(2)
+        .map(|address| {
+            let instruction = instruction_at(chip8, address);
+            format!(
+                "{:03X}  {:04X}  {}\n",
+                address,
+                instruction,
+                disassemble_with_symbols(instruction, symbols)
+            )
+        })
+        .collect()
+}
+
+// A Debug Adapter Protocol server for a single client such as VS Code, connected with
+// `"debugServer": <port>` in its launch configuration. Breakpoints and stepping work on the
+// Octo source given a source map, a disassembly source and the disassembly view, registers and
+// timers are variables.
+pub struct DapServer {
+    stream: Option<TcpStream>,
+    input: Vec<u8>,
+    sequence: u64,
+    disassembly_breakpoints: BTreeSet<usize>,
+    source_breakpoints: BTreeSet<usize>,
+    instruction_breakpoints: BTreeSet<usize>,
+    // Stepping over or out of calls runs until the stack pointer is back at this depth
+    stop_depth: Option<u8>,
+    // The reason of the stopped event the client waits for
+    stop_reason: Option<&'static str>,
+    symbols: Symbols,
+    source_map: SourceMap,
+}
+
+impl DapServer {
+    // Blocks until a client connects
+    pub fn listen(port: u16, symbols: Symbols, source_map: SourceMap) -> io::Result<Self> {
+        Ok(DapServer {
+            stream: Some(accept(port, "a debug adapter client")?),
+            input: Vec::new(),
+            sequence: 0,
+            disassembly_breakpoints: BTreeSet::new(),
+            source_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            stop_depth: None,
+            stop_reason: None,
+            symbols,
+            source_map,
+        })
+    }
+
+    // Removes the next message from the input, each has a `Content-Length` header
+    fn take_message(&mut self) -> Option<Value> {
+        let header_end = self
+            .input
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")?;
+        let header = String::from_utf8_lossy(&self.input[..header_end]).into_owned();
+        let len = header
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length:"))
+            .and_then(|len| len.trim().parse::<usize>().ok());
+        let body_start = header_end + 4;
+        let Some(message_end) = len.and_then(|len| body_start.checked_add(len)) else {
+            // Skip what cannot be understood
+            self.input.drain(..body_start);
+            return Some(Value::Null);
+        };
+        if self.input.len() < message_end {
+            return None;
+        }
+        let message: Vec<u8> = self.input.drain(..message_end).collect();
+        Some(serde_json::from_slice(&message[body_start..]).unwrap_or(Value::Null))
+    }
+
+    fn handle_request(&mut self, request: &Value, chip8: &mut Chip8) -> Option<DebuggerCommand> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        let mut debugger_command = None;
+        let body = match command {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsSetVariable": true,
+                    "supportsDisassembleRequest": true,
+                    "supportsInstructionBreakpoints": true,
+                });
+                self.respond(request, true, capabilities);
+                self.send_event("initialized", json!({}));
+                return None;
+            }
+            // The ROM is already running
+            "launch" | "attach" => Ok(json!({})),
+            // The machine starts stepping, so the client is told it stopped at the entry
+            "configurationDone" => {
+                self.stop_reason = Some("entry");
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace(chip8)),
+            "scopes" => Ok(json!({
+                "scopes": [{
+                    "name": "Registers",
+                    "variablesReference": REGISTERS_REFERENCE,
+                    "expensive": false,
+                }]
+            })),
+            "variables" => Ok(json!({ "variables": variables(chip8) })),
+            "setVariable" => set_variable(arguments, chip8),
+            "source" => Ok(json!({ "content": disassembly(chip8, &self.symbols) })),
+            "setBreakpoints" => Ok(self.set_source_breakpoints(arguments)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(arguments)),
+            "disassemble" => disassemble_request(arguments, chip8, &self.symbols, &self.source_map),
+            "continue" => {
+                self.stop_reason = Some("breakpoint");
+                debugger_command = Some(DebuggerCommand::Continue);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "stepIn" => {
+                self.stop_reason = Some("step");
+                debugger_command = Some(DebuggerCommand::Step(1));
+                Ok(json!({}))
+            }
+            // Like the debugger's `next` and `finish`
+            "next" | "stepOut" => {
+                let stack_pointer = chip8.stack_pointer();
+                self.stop_reason = Some("step");
+                debugger_command = Some(match (command, stack_pointer) {
+                    ("stepOut", 0) => DebuggerCommand::Step(1),
+                    ("stepOut", _) => {
+                        self.stop_depth = Some(stack_pointer - 1);
+                        DebuggerCommand::Continue
+                    }
+                    _ => {
+                        self.stop_depth = Some(stack_pointer);
+                        DebuggerCommand::Continue
+                    }
+                });
+                Ok(json!({}))
+            }
+            "pause" => {
+                self.stop_depth = None;
+                self.stop_reason = Some("pause");
+                debugger_command = Some(DebuggerCommand::Pause);
+                Ok(json!({}))
+            }
+            "disconnect" => {
+                self.respond(request, true, json!({}));
+                self.stream = None;
+                return Some(DebuggerCommand::Continue);
+            }
+            "terminate" => {
+                self.respond(request, true, json!({}));
+                self.send_event("terminated", json!({}));
+                return Some(DebuggerCommand::Quit);
+            }
+            _ => Err(format!("Unsupported request: {}", command)),
+        };
+        match body {
+            Ok(body) => self.respond(request, true, body),
+            Err(message) => self.respond(
+                request,
+                false,
+                json!({ "error": { "id": 1, "format": message } }),
+            ),
+        }
+        debugger_command
+    }
+
+    // The current instruction, then the call sites of the subroutines it is nested in
+    fn stack_trace(&self, chip8: &Chip8) -> Value {
+        let calls = chip8.call_stack();
+        let subroutines = calls.iter().map(|call| Some(call.subroutine)).chain([None]);
+        let addresses = [chip8.program_counter()]
+            .into_iter()
+            .chain(calls.iter().map(|call| call.call_site));
+        let frames: Vec<Value> = addresses
+            .zip(subroutines)
+            .enumerate()
+            .map(|(id, (address, subroutine))| {
+                let (source, line) = source_location(&self.source_map, address)
+                    .unwrap_or_else(|| (disassembly_source(), line_of(address).unwrap_or(0)));
+                json!({
+                    "id": id,
+                    "name": subroutine.map_or_else(
+                        || "start".to_string(),
+                        |address| self.symbols.label(address)
+                    ),
+                    "source": source,
+                    "line": line,
+                    "column": 1,
+                    "instructionPointerReference": format!("{:#X}", address),
+                })
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    // Breakpoints in the Octo source move to the next line with code, like in the debugger
+    fn set_source_breakpoints(&mut self, arguments: &Value) -> Value {
+        let lines: Vec<usize> = arguments["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .map(|line| line as usize)
+            .collect();
+        let is_source = arguments["source"]["path"]
+            .as_str()
+            .is_some_and(|path| self.source_map.is_source(Path::new(path)));
+        // The line each breakpoint ends up on and its address
+        let locations: Vec<Option<(usize, usize)>> = lines
+            .iter()
+            .map(|&line| {
+                if is_source {
+                    self.source_map.address(line)
+                } else {
+                    address_of(line).map(|address| (line, address))
+                }
+            })
+            .collect();
+        let addresses = locations.iter().flatten().map(|&(_, address)| address);
+        if is_source {
+            self.source_breakpoints = addresses.collect();
+        } else {
+            self.disassembly_breakpoints = addresses.collect();
+        }
+        let breakpoints: Vec<Value> = lines
+            .iter()
+            .zip(&locations)
+            .map(|(&line, location)| match location {
+                Some((line, _)) => json!({ "verified": true, "line": line }),
+                None => json!({ "verified": false, "line": line }),
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Value {
+        let addresses: Vec<Option<usize>> = arguments["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|breakpoint| {
+                let reference = parse_number(breakpoint["instructionReference"].as_str()?)?;
+                let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+                (reference as i64)
+                    .checked_add(offset)
+                    .and_then(|address| usize::try_from(address).ok())
+                    .filter(|&address| address < constants::RAM_LEN)
+            })
+            .collect();
+        self.instruction_breakpoints = addresses.iter().flatten().copied().collect();
+        let breakpoints: Vec<Value> = addresses
+            .iter()
+            .map(|address| json!({ "verified": address.is_some() }))
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn respond(&mut self, request: &Value, success: bool, body: Value) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": success,
+            "body": body,
+        });
+        if !success {
+            response["message"] = body["error"]["format"].clone();
+        }
+        self.send(response);
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn send(&mut self, mut message: Value) {
+        self.sequence += 1;
+        message["seq"] = self.sequence.into();
+        let content = message.to_string();
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+        let bytes = format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+        if let Err(error) = write_all(stream, bytes.as_bytes()) {
+            eprintln!("Lost debug adapter connection: {:?}", error);
+            self.stream = None;
+        }
+    }
+}
+
+impl RemoteDebugger for DapServer {
+    // The machine runs again once the client disconnects
+    fn poll_command(&mut self, chip8: &mut Chip8) -> Option<DebuggerCommand> {
+        let stream = self.stream.as_mut()?;
+        match read_available(stream, &mut self.input) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.stream = None;
+                return Some(DebuggerCommand::Continue);
+            }
+            Err(error) => {
+                eprintln!("Lost debug adapter connection: {:?}", error);
+                self.stream = None;
+                return Some(DebuggerCommand::Continue);
+            }
+        }
+
+        let mut command = None;
+        while let Some(message) = self.take_message() {
+            if message["type"] == "request" {
+                if let Some(request_command) = self.handle_request(&message, chip8) {
+                    command = Some(request_command);
+                }
+            }
+        }
+        command
+    }
+
+    fn check_stop(&mut self, chip8: &Chip8) -> bool {
+        let program_counter = chip8.program_counter();
+        if self.disassembly_breakpoints.contains(&program_counter)
+            || self.source_breakpoints.contains(&program_counter)
+            || self.instruction_breakpoints.contains(&program_counter)
+        {
+            self.stop_depth = None;
+            self.stop_reason = Some("breakpoint");
+            return true;
+        }
+        if self
+            .stop_depth
+            .is_some_and(|depth| chip8.stack_pointer() <= depth)
+        {
+            self.stop_depth = None;
+            return true;
+        }
+        false
+    }
+
+    fn report_stop(&mut self) {
+        if let Some(reason) = self.stop_reason.take() {
+            self.send_event(
+                "stopped",
+                json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+            );
+        }
+    }
+}
+
+fn variables(chip8: &Chip8) -> Vec<Value> {
+    let registers = (0..constants::REGISTER_COUNT).map(Operand::Register);
+    let others = [
+        Operand::Index,
+        Operand::ProgramCounter,
+        Operand::DelayTimer,
+        Operand::SoundTimer,
+    ];
+    registers
+        .chain(others)
+        .map(|operand| {
+            json!({
+                "name": operand.to_string(),
+                "value": format!("{:#04X}", operand.value(chip8)),
+                "variablesReference": 0,
+            })
+        })
+        .chain([json!({
+            "name": "SP",
+            "value": format!("{:#04X}", chip8.stack_pointer()),
+            "variablesReference": 0,
+        })])
+        .collect()
+}
+
+fn set_variable(arguments: &Value, chip8: &mut Chip8) -> Result<Value, String> {
+    let operand = parse_operand(arguments["name"].as_str().unwrap_or_default())?;
+    let value = arguments["value"].as_str().unwrap_or_default();
+    let value = parse_number(value).ok_or_else(|| format!("Invalid value: {}", value))?;
+    operand.check_value(value)?;
+    operand.set(chip8, value);
+    Ok(json!({ "value": format!("{:#04X}", value) }))
+}
+
+fn disassemble_request(
+    arguments: &Value,
+    chip8: &Chip8,
+    symbols: &Symbols,
+    source_map: &SourceMap,
+) -> Result<Value, String> {
+    let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+    let invalid_offset = || "Invalid offset".to_string();
+    let base = (parse_number(reference)
+        .ok_or_else(|| format!("Invalid memory reference: {}", reference))? as i64)
+        .checked_add(arguments["offset"].as_i64().unwrap_or(0))
+        .ok_or_else(invalid_offset)?;
+    let first = arguments["instructionOffset"].as_i64().unwrap_or(0);
+    // Memory holds no more instructions than this, the rest would all be invalid
+    let count = arguments["instructionCount"]
+        .as_i64()
+        .unwrap_or(0)
+        .clamp(0, constants::RAM_LEN as i64);
+    let end = first.checked_add(count).ok_or_else(invalid_offset)?;
+    let instructions: Vec<Value> = (first..end)
+        .map(|index| {
+            let address = index
+                .checked_mul(2)
+                .and_then(|offset| base.checked_add(offset))
+                .unwrap_or(i64::MIN);
+            match usize::try_from(address)
+                .ok()
+                .filter(|&address| address < constants::RAM_LEN - 1)
+            {
+                Some(address) => {
+                    let instruction = instruction_at(chip8, address);
+                    let mut disassembled = json!({
+                        "address": format!("{:#X}", address),
+                        "instructionBytes": format!("{:02X} {:02X}", instruction >> 8, instruction & 0xFF),
+                        "instruction": disassemble_with_symbols(instruction, symbols),
+                    });
+                    let location = source_location(source_map, address).or_else(|| {
+                        line_of(address).map(|line| (disassembly_source(), line))
+                    });
+                    if let Some((source, line)) = location {
+                        disassembled["location"] = source;
+                        disassembled["line"] = line.into();
+                    }
+                    disassembled
+                }
+                None => json!({
+                    "address": format!("{:#X}", address.max(0)),
+                    "instruction": "",
+                    "presentationHint": "invalid",
+                }),
+            }
+        })
+        .collect();
+    Ok(json!({ "instructions": instructions }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip_8::{Platform, Quirks};
+    use crate::clock::VirtualClock;
+    use crate::rng::Pcg32;
+
+    // Calls the subroutine at 0x206, which sets V1 = 2, then V0 = 1 and loop
+    fn build() -> Chip8 {
+        Chip8::build(
+            &[0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x02, 0x00, 0xEE],
+            1_000,
+            false,
+            Quirks::new(Platform::Chip8),
+            Box::new(Pcg32::new(0)),
+            Box::new(VirtualClock::new()),
+        )
+    }
+
+    // Responses go nowhere without a client
+    fn server() -> DapServer {
+        DapServer {
+            stream: None,
+            input: Vec::new(),
+            sequence: 0,
+            disassembly_breakpoints: BTreeSet::new(),
+            source_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            stop_depth: None,
+            stop_reason: None,
+            symbols: Symbols::new(),
+            source_map: SourceMap::new(),
+        }
+    }
+
+    fn frame(message: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+    }
+
+    #[test]
+    fn messages_are_split_on_their_content_length() {
+        let mut server = server();
+        let last = frame(r#"{"seq":3}"#);
+        let input = [
+            frame(r#"{"seq":1}"#),
+            "Content-Length: x\r\n\r\n".to_string(),
+            frame(r#"{"seq":2}"#),
+        ]
+        .concat();
+        server
+            .input
+            .extend(input.bytes().chain(last.bytes().take(20)));
+
+        assert_eq!(server.take_message(), Some(json!({ "seq": 1 })));
+        // A header without a length is skipped
+        assert_eq!(server.take_message(), Some(Value::Null));
+        assert_eq!(server.take_message(), Some(json!({ "seq": 2 })));
+        // The rest has not arrived yet
+        assert_eq!(server.take_message(), None);
+        server.input.extend(last.bytes().skip(20));
+        assert_eq!(server.take_message(), Some(json!({ "seq": 3 })));
+        assert!(server.input.is_empty());
+    }
+
+    #[test]
+    fn variables_are_set_within_what_holds_them() {
+        let mut chip8 = build();
+        for (name, value, result) in [
+            ("V3", "31", Ok(json!({ "value": "0x1F" }))),
+            ("PC", "0x206", Ok(json!({ "value": "0x206" }))),
+            ("V3", "256", Err("V3 cannot hold 0x100")),
+            ("PC", "0xFFF", Err("PC cannot hold 0xFFF")),
+            ("V3", "1F", Err("Invalid value: 1F")),
+        ] {
+            let arguments = json!({ "name": name, "value": value });
+            let expected = result.map_err(str::to_string);
+            assert_eq!(
+                set_variable(&arguments, &mut chip8),
+                expected,
+                "{} {}",
+                name,
+                value
+            );
+        }
+        assert_eq!(chip8.registers()[3], 0x1F);
+        assert_eq!(chip8.program_counter(), 0x206);
+    }
+
+    #[test]
+    fn stepping_over_a_call_stops_after_it_returns() {
+        let mut server = server();
+        let mut chip8 = build();
+        let request = json!({ "type": "request", "seq": 1, "command": "next" });
+        assert_eq!(
+            server.handle_request(&request, &mut chip8),
+            Some(DebuggerCommand::Continue)
+        );
+        // Into the subroutine, through it and back
+        for is_stopped in [false, false, true] {
+            chip8.cycle();
+            assert_eq!(server.check_stop(&chip8), is_stopped);
+        }
+        assert_eq!(chip8.program_counter(), 0x202);
+        assert_eq!(server.stop_reason, Some("step"));
+    }
+
+    #[test]
+    fn instruction_breakpoints_are_offset_from_their_reference() {
+        let mut server = server();
+        let mut chip8 = build();
+        let arguments = json!({ "breakpoints": [
+            { "instructionReference": "0x200", "offset": 6 },
+            { "instructionReference": "0xFFE", "offset": 2 },
+            { "instructionReference": "main" },
+        ]});
+        let verified: Vec<Value> = server.set_instruction_breakpoints(&arguments)["breakpoints"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|breakpoint| breakpoint["verified"].clone())
+            .collect();
+        assert_eq!(verified, [true, false, false]);
+
+        // The subroutine at 0x206
+        chip8.cycle();
+        assert!(server.check_stop(&chip8));
+        assert_eq!(server.stop_reason, Some("breakpoint"));
+    }
+
+    #[test]
+    fn disassembly_past_the_end_of_memory_is_invalid() {
+        let chip8 = build();
+        let arguments = json!({
+            "memoryReference": "0xFFC",
+            "instructionOffset": -1,
+            "instructionCount": 4,
+        });
+        let disassembly =
+            disassemble_request(&arguments, &chip8, &Symbols::new(), &SourceMap::new()).unwrap();
+        let instructions: Vec<(&str, bool)> = disassembly["instructions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|instruction| {
+                (
+                    instruction["address"].as_str().unwrap(),
+                    instruction["presentationHint"] == "invalid",
+                )
+            })
+            .collect();
+        assert_eq!(
+            instructions,
+            [
+                ("0xFFA", false),
+                ("0xFFC", false),
+                ("0xFFE", false),
+                ("0x1000", true)
+            ]
+        );
+    }
+}
@@ -0,0 +1,1130 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt::Write,
+    io::{self, IsTerminal, Stdout},
+    sync::mpsc::{self, Receiver},
+    thread, time,
+};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute, terminal,
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::Stylize,
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    Terminal,
+};
+
+use crate::access::{next_accesses, Access, Location};
+use crate::chip_8::{Chip8, MachineState, StateDelta};
+use crate::condition::{
+    parse_address, parse_condition, parse_number, parse_operand, Condition, Operand,
+};
+use crate::constants;
+use crate::disassembler::disassemble_with_symbols;
+use crate::source_map::SourceMap;
+use crate::symbols::Symbols;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebuggerCommand {
+    Step(u32),
+    StepBack(u32),
+    Continue,
+    Pause,
+    // Stores a value in a register, a timer or memory
+    Set(Operand, u16),
+    Quit,
+}
+
+// What `next`, `finish` and `run until` run until
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunUntil {
+    // The stack pointer is back at this depth
+    Depth(u8),
+    // An instruction clearing or drawing to the screen ran
+    Draw,
+    // A key not in these pressed keys went down, they are updated as keys are released
+    Key(u16),
+    // The frame counter reached this frame
+    Frame(u64),
+}
+
+// What is shown instead of the disassembly, see `mem` and `sprites`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MemoryView {
+    Hex {
+        start: usize,
+        len: usize,
+    },
+    Sprites {
+        start: usize,
+        len: usize,
+        height: usize,
+    },
+}
+
+// Where commands come from: the input line of the terminal UI, or lines of stdin when it is
+// not a terminal, e.g. commands piped in from a file
+enum Console {
+    Terminal(Terminal<CrosstermBackend<Stdout>>),
+    Stdin(Receiver<String>),
+}
+
+fn is_draw(instruction: u16) -> bool {
+    instruction == 0x00E0 || instruction >> 12 == 0xD
+}
+
+// The stack depth the current subroutine returns to
+fn caller_depth(chip8: &Chip8) -> Result<u8, String> {
+    chip8
+        .stack_pointer()
+        .checked_sub(1)
+        .ok_or_else(|| "Not in a subroutine".to_string())
+}
+
+// A register, the index register or a memory address
+fn parse_location(value: &str) -> Result<Location, String> {
+    if value.eq_ignore_ascii_case("i") {
+        return Ok(Location::Index);
+    }
+    match value.strip_prefix(['V', 'v']) {
+        Some(register) if register.len() == 1 => usize::from_str_radix(register, 16)
+            .map(Location::Register)
+            .map_err(|_| format!("Invalid register: {}", value)),
+        _ => parse_address(value).map(Location::Memory),
+    }
+}
+
+fn parse_command(input: &str) -> Result<DebuggerCommand, String> {
+    let mut words = input.split_whitespace();
+    let command = match words.next().unwrap_or("step") {
+        "s" | "step" => match words.next() {
+            Some(count) => DebuggerCommand::Step(
+                count
+                    .parse()
+                    .map_err(|_| format!("Invalid step count: {}", count))?,
+            ),
+            None => DebuggerCommand::Step(1),
+        },
+        "bs" | "back" => match words.next() {
+            Some(count) => DebuggerCommand::StepBack(
+                count
+                    .parse()
+                    .map_err(|_| format!("Invalid step count: {}", count))?,
+            ),
+            None => DebuggerCommand::StepBack(1),
+        },
+        "c" | "continue" => DebuggerCommand::Continue,
+        "p" | "pause" => DebuggerCommand::Pause,
+        command @ ("set" | "poke") => {
+            let (Some(target), Some(value)) = (words.next(), words.next()) else {
+                return Err(format!("Expected a target and a value: {}", input));
+            };
+            // `poke` takes an address like the other commands, `set` takes `[address]`
+            let target = if command == "poke" {
+                Operand::Memory(parse_address(target)?)
+            } else {
+                parse_operand(target)?
+            };
+            let value = parse_number(value).ok_or_else(|| format!("Invalid value: {}", value))?;
+            target.check_value(value)?;
+            DebuggerCommand::Set(target, value)
+        }
+        "q" | "quit" => DebuggerCommand::Quit,
+        command => return Err(format!("Unknown command: {}", command)),
+    };
+    match words.next() {
+        Some(word) => Err(format!("Unexpected argument: {}", word)),
+        None => Ok(command),
+    }
+}
+
+// Registers and timers
+fn register_lines(chip8: &Chip8) -> Vec<Line<'static>> {
+    let mut lines: Vec<Line> = chip8
+        .registers()
+        .chunks(4)
+        .enumerate()
+        .map(|(row_index, values)| {
+            values
+                .iter()
+                .enumerate()
+                .map(|(column, value)| format!("V{:X} {:02X}", row_index * 4 + column, value))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .into()
+        })
+        .collect();
+    lines.push(Line::default());
+    lines.push(
+        format!(
+            "I {:03X}  PC {:03X}  SP {:X}",
+            chip8.index_register(),
+            chip8.program_counter(),
+            chip8.stack_pointer()
+        )
+        .into(),
+    );
+    lines.push(
+        format!(
+            "DT {}  ST {}  Frame {}",
+            chip8.delay_timer(),
+            chip8.sound_timer(),
+            chip8.frame()
+        )
+        .into(),
+    );
+    lines
+}
+
+// Registers and timers on one line, for `regs`
+fn register_summary(chip8: &Chip8) -> String {
+    let mut summary: Vec<String> = chip8
+        .registers()
+        .iter()
+        .enumerate()
+        .map(|(register, value)| format!("V{:X}={:02X}", register, value))
+        .collect();
+    summary.push(format!(
+        "I={:03X} PC={:03X} SP={:X} DT={} ST={} frame={}",
+        chip8.index_register(),
+        chip8.program_counter(),
+        chip8.stack_pointer(),
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        chip8.frame()
+    ));
+    summary.join(" ")
+}
+
+// The innermost subroutine calls first, with where each was called from
+fn stack_lines(chip8: &Chip8, symbols: &Symbols) -> Vec<Line<'static>> {
+    let calls = chip8.call_stack();
+    calls
+        .iter()
+        .enumerate()
+        .take(constants::DEBUGGER_STACK_ROWS)
+        .map(|(index, call)| {
+            format!(
+                "{:2X} {} called at {}",
+                calls.len() - index,
+                symbols.label(call.subroutine),
+                symbols.label(call.call_site)
+            )
+            .into()
+        })
+        .collect()
+}
+
+// Disassembly around the program counter, which is highlighted, with breakpoints marked and
+// named addresses preceded by their name
+fn code_lines(
+    chip8: &Chip8,
+    breakpoints: &BTreeSet<usize>,
+    symbols: &Symbols,
+) -> Vec<Line<'static>> {
+    let ram = chip8.ram();
+    let program_counter = chip8.program_counter();
+    let (before, after) = constants::DEBUGGER_CODE_CONTEXT;
+    let start = program_counter.saturating_sub(before * 2);
+    let end = (program_counter + after * 2).min(constants::RAM_LEN - 2);
+
+    (start..=end)
+        .step_by(2)
+        .flat_map(|address| {
+            let instruction = (ram[address] as u16) << 8 | ram[address + 1] as u16;
+            let line = Line::from(format!(
+                "{}{:03X}  {:04X}  {}",
+                if breakpoints.contains(&address) {
+                    '*'
+                } else {
+                    ' '
+                },
+                address,
+                instruction,
+                disassemble_with_symbols(instruction, symbols)
+            ));
+            let line = if address == program_counter {
+                line.reversed()
+            } else {
+                line
+            };
+            let name = symbols
+                .name(address)
+                .map(|name| Line::from(format!("{}:", name)).bold());
+            name.into_iter().chain([line])
+        })
+        .collect()
+}
+
+// Source around the line of the program counter, which is highlighted, with lines holding a
+// breakpoint marked
+fn source_lines(
+    chip8: &Chip8,
+    breakpoints: &BTreeSet<usize>,
+    source_map: &SourceMap,
+) -> Vec<Line<'static>> {
+    let program_counter = chip8.program_counter();
+    let Some(current_line) = source_map.line(program_counter) else {
+        return vec![Line::from(format!("No source line for {:03X}", program_counter)).dim()];
+    };
+    let breakpoint_lines: BTreeSet<usize> = breakpoints
+        .iter()
+        .filter_map(|&address| source_map.line(address))
+        .collect();
+    let (before, after) = constants::DEBUGGER_SOURCE_CONTEXT;
+    let start = current_line.saturating_sub(before).max(1);
+    let end = (current_line + after).min(source_map.source_len());
+
+    (start..=end)
+        .map(|number| {
+            let line = Line::from(format!(
+                "{}{:4}  {}",
+                if breakpoint_lines.contains(&number) {
+                    '*'
+                } else {
+                    ' '
+                },
+                number,
+                source_map.source_line(number).unwrap_or_default()
+            ));
+            if number == current_line {
+                line.reversed()
+            } else {
+                line
+            }
+        })
+        .collect()
+}
+
+fn memory_lines(chip8: &Chip8) -> Vec<Line<'static>> {
+    let ram = chip8.ram();
+    let index_register = chip8.index_register() as usize;
+    let memory_end = (index_register
+        + constants::DEBUGGER_MEMORY_ROWS * constants::DEBUGGER_MEMORY_ROW_LEN)
+        .min(constants::RAM_LEN);
+    ram[index_register.min(memory_end)..memory_end]
+        .chunks(constants::DEBUGGER_MEMORY_ROW_LEN)
+        .enumerate()
+        .map(|(row, bytes)| {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            format!(
+                "{:03X}  {}",
+                index_register + row * constants::DEBUGGER_MEMORY_ROW_LEN,
+                bytes.join(" ")
+            )
+            .into()
+        })
+        .collect()
+}
+
+// Hex and ASCII dump of the given range, with the bytes at I and PC highlighted
+fn hex_lines(chip8: &Chip8, start: usize, len: usize) -> Vec<Line<'static>> {
+    let ram = chip8.ram();
+    let index_register = chip8.index_register() as usize;
+    let program_counter = chip8.program_counter();
+    let end = (start + len).min(constants::RAM_LEN);
+
+    (start..end)
+        .step_by(constants::DEBUGGER_HEX_ROW_LEN)
+        .map(|row_start| {
+            let row_end = (row_start + constants::DEBUGGER_HEX_ROW_LEN).min(end);
+            let mut spans = vec![Span::raw(format!("{:03X} ", row_start))];
+            for (address, value) in (row_start..row_end).zip(&ram[row_start..row_end]) {
+                let byte = Span::raw(format!(" {:02X}", value));
+                spans.push(if address == index_register {
+                    byte.reversed()
+                } else if address == program_counter || address == program_counter + 1 {
+                    byte.underlined()
+                } else {
+                    byte
+                });
+            }
+            let padding = constants::DEBUGGER_HEX_ROW_LEN - (row_end - row_start);
+            let ascii: String = ram[row_start..row_end]
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7E => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            spans.push(Span::raw(format!("{}  {}", "   ".repeat(padding), ascii)));
+            Line::from(spans)
+        })
+        .collect()
+}
+
+// The range as 8 pixel wide sprites side by side, each under its address and two rows of pixels
+// to a line of half blocks, the one at I reversed
+fn sprite_lines(
+    chip8: &Chip8,
+    start: usize,
+    len: usize,
+    height: usize,
+    width: usize,
+) -> Vec<Line<'static>> {
+    let ram = chip8.ram();
+    let index_register = chip8.index_register() as usize;
+    let end = (start + len).min(constants::RAM_LEN);
+    let per_row = ((width + constants::DEBUGGER_SPRITE_SPACING)
+        / (8 + constants::DEBUGGER_SPRITE_SPACING))
+        .max(1);
+    let spacing = " ".repeat(constants::DEBUGGER_SPRITE_SPACING);
+
+    let sprites: Vec<usize> = (start..end).step_by(height).collect();
+    let mut lines = Vec::new();
+    for row in sprites.chunks(per_row) {
+        let mut labels = Vec::new();
+        for &sprite in row {
+            let label = Span::raw(format!("{:<8}", format!("{:03X}", sprite)));
+            labels.push(if (sprite..sprite + height).contains(&index_register) {
+                label.reversed()
+            } else {
+                label
+            });
+            labels.push(Span::raw(spacing.clone()));
+        }
+        lines.push(Line::from(labels));
+
+        for pixel_row in (0..height).step_by(2) {
+            let mut text = String::new();
+            for &sprite in row {
+                let byte = |offset: usize| match sprite + offset {
+                    address if offset < height && address < end => ram[address],
+                    _ => 0,
+                };
+                let (top, bottom) = (byte(pixel_row), byte(pixel_row + 1));
+                for bit in (0..8).rev() {
+                    text.push(match (top >> bit & 1, bottom >> bit & 1) {
+                        (1, 1) => '█',
+                        (1, 0) => '▀',
+                        (0, 1) => '▄',
+                        _ => ' ',
+                    });
+                }
+                text.push_str(&spacing);
+            }
+            lines.push(Line::from(text));
+        }
+    }
+    lines
+}
+
+fn pane(title: &str, lines: Vec<Line<'static>>) -> Paragraph<'static> {
+    Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title.to_string()),
+    )
+}
+
+// A debugger in the terminal the game was started from, the game window keeps rendering
+pub struct Debugger {
+    console: Console,
+    input: String,
+    // Run again by submitting an empty line
+    last_input: Option<String>,
+    message: String,
+    run_until: Option<RunUntil>,
+    // The instruction about to run, see `record`
+    next_instruction: u16,
+    // Whether the frontend is stepping, commands from stdin wait for it to stop
+    is_stepping: bool,
+    // Where the program counter was last printed to stdout, so each stop is shown once
+    shown_program_counter: Option<usize>,
+    // The state before the latest instruction, kept up to date by `record`
+    latest_state: Option<MachineState>,
+    // Deltas back from it through the instructions before, newest last, for stepping backwards
+    history: VecDeque<StateDelta>,
+    memory_view: Option<MemoryView>,
+    breakpoints: BTreeSet<usize>,
+    symbols: Symbols,
+    source_map: SourceMap,
+    // Stop when they become true, so continuing does not stop again right away
+    conditions: Vec<(Condition, bool)>,
+    watchpoints: BTreeSet<(Location, Access)>,
+}
+
+// A watched location about to be accessed, with what is needed to report it afterwards
+pub struct WatchedAccess {
+    location: Location,
+    access: Access,
+    address: usize,
+    old_value: u16,
+}
+
+impl Debugger {
+    pub fn build(breakpoints: &[usize], symbols: Symbols, source_map: SourceMap) -> Self {
+        let console = if io::stdin().is_terminal() {
+            terminal::enable_raw_mode().unwrap();
+            execute!(io::stdout(), terminal::EnterAlternateScreen).unwrap();
+            let terminal = Terminal::new(CrosstermBackend::new(io::stdout()))
+                .unwrap_or_else(|error| panic!("Failed to open terminal debugger: {:?}", error));
+            Console::Terminal(terminal)
+        } else {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                for line in io::stdin().lines().map_while(Result::ok) {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+            Console::Stdin(receiver)
+        };
+
+        Debugger {
+            console,
+            input: String::new(),
+            last_input: None,
+            message: constants::DEBUGGER_HELP.to_string(),
+            run_until: None,
+            next_instruction: 0,
+            is_stepping: false,
+            shown_program_counter: None,
+            latest_state: None,
+            history: VecDeque::with_capacity(constants::DEBUGGER_HISTORY_LEN),
+            memory_view: None,
+            breakpoints: breakpoints.iter().copied().collect(),
+            symbols,
+            source_map,
+            conditions: Vec::new(),
+            watchpoints: BTreeSet::new(),
+        }
+    }
+
+    // Reads pending input without blocking, returning a command once one is entered
+    pub fn poll_command(&mut self, chip8: &Chip8, is_stepping: bool) -> Option<DebuggerCommand> {
+        self.is_stepping = is_stepping;
+        if let Console::Stdin(receiver) = &self.console {
+            // One line at a time, so piped commands run in order like typed ones
+            if !is_stepping {
+                return None;
+            }
+            let line = receiver.try_recv().ok()?;
+            // What the last command printed comes before the next one
+            self.print(chip8);
+            return self.submit(line, chip8);
+        }
+
+        while event::poll(time::Duration::ZERO).unwrap() {
+            let Event::Key(key_event) = event::read().unwrap() else {
+                continue;
+            };
+            if key_event.kind == KeyEventKind::Release {
+                continue;
+            }
+
+            match key_event.code {
+                KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Some(DebuggerCommand::Quit)
+                }
+                KeyCode::Char(character) => self.input.push(character),
+                KeyCode::Backspace => {
+                    self.input.pop();
+                }
+                KeyCode::Esc => self.input.clear(),
+                KeyCode::Up => self.scroll_memory_view(-1),
+                KeyCode::Down => self.scroll_memory_view(1),
+                KeyCode::PageUp => self.scroll_memory_view(-constants::DEBUGGER_HEX_PAGE_ROWS),
+                KeyCode::PageDown => self.scroll_memory_view(constants::DEBUGGER_HEX_PAGE_ROWS),
+                KeyCode::Enter => {
+                    let input = std::mem::take(&mut self.input);
+                    if let Some(command) = self.submit(input, chip8) {
+                        return Some(command);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // Runs an entered line, an empty one runs the last command again
+    fn submit(&mut self, input: String, chip8: &Chip8) -> Option<DebuggerCommand> {
+        let input = match input {
+            input if !input.trim().is_empty() => input,
+            _ => self.last_input.clone()?,
+        };
+        // Commands from stdin are echoed like a transcript, the input line shows them otherwise
+        let is_stdin = matches!(self.console, Console::Stdin(_));
+        if is_stdin {
+            println!("> {}", input);
+        }
+        match self.run_input(&input, chip8) {
+            Ok(Some(command)) => {
+                if !is_stdin {
+                    self.message = input.clone();
+                }
+                match command {
+                    DebuggerCommand::Continue => self.is_stepping = false,
+                    DebuggerCommand::Step(_)
+                    | DebuggerCommand::StepBack(_)
+                    | DebuggerCommand::Pause => self.is_stepping = true,
+                    _ => {}
+                }
+                self.last_input = Some(input);
+                Some(command)
+            }
+            Ok(None) => None,
+            Err(error) => {
+                self.message = error;
+                None
+            }
+        }
+    }
+
+    // A symbol name, a source line as `file:line` or an address, names win over addresses that
+    // look the same like `abc`
+    fn resolve_address(&self, value: &str) -> Result<usize, String> {
+        if let Some((file, line)) = value.split_once(':') {
+            return self.resolve_source_line(file, line);
+        }
+        self.symbols
+            .address(value)
+            .map_or_else(|| parse_address(value), Ok)
+    }
+
+    // The first instruction of the line, or of the next line with code like a blank line or a
+    // label. The file name may be left out, as there is only one.
+    fn resolve_source_line(&self, file: &str, line: &str) -> Result<usize, String> {
+        if self.source_map.is_empty() {
+            return Err("No source map loaded".to_string());
+        }
+        if !file.is_empty() && file != self.source_map.file_name() {
+            return Err(format!("Unknown source file: {}", file));
+        }
+        let line = line
+            .parse()
+            .map_err(|_| format!("Invalid line: {}", line))?;
+        self.source_map
+            .address(line)
+            .map(|(_, address)| address)
+            .ok_or_else(|| format!("No code at or after line {}", line))
+    }
+
+    // The name or address, followed by the source line if there is one
+    fn location(&self, address: usize) -> String {
+        match self.source_map.line(address) {
+            Some(line) => format!(
+                "{} ({}:{})",
+                self.symbols.label(address),
+                self.source_map.file_name(),
+                line
+            ),
+            None => self.symbols.label(address),
+        }
+    }
+
+    // Handles commands about the debugger itself, returning those for the frontend
+    fn run_input(&mut self, input: &str, chip8: &Chip8) -> Result<Option<DebuggerCommand>, String> {
+        let mut words = input.split_whitespace();
+        match words.next() {
+            // `bp add` and `bp del` are other names for `break` and `delete`
+            Some("bp") => {
+                let command = match words.next() {
+                    Some("add" | "list") | None => "break",
+                    Some("del" | "delete") => "delete",
+                    Some(command) => return Err(format!("Unknown bp command: {}", command)),
+                };
+                let arguments: Vec<&str> = words.collect();
+                self.run_input(&format!("{} {}", command, arguments.join(" ")), chip8)
+            }
+            Some("regs") => {
+                self.message = register_summary(chip8);
+                Ok(None)
+            }
+            Some("stack") => {
+                let calls: Vec<String> = chip8
+                    .call_stack()
+                    .iter()
+                    .map(|call| {
+                        format!(
+                            "{} called at {}",
+                            self.symbols.label(call.subroutine),
+                            self.location(call.call_site)
+                        )
+                    })
+                    .collect();
+                self.message = if calls.is_empty() {
+                    "Not in a subroutine".to_string()
+                } else {
+                    calls.join(", ")
+                };
+                Ok(None)
+            }
+            Some("run") => {
+                self.run_until = match (words.next(), words.next(), words.next(), words.next()) {
+                    (None, ..) => None,
+                    (Some("until"), Some("draw"), None, _) => Some(RunUntil::Draw),
+                    (Some("until"), Some("key"), None, _) => {
+                        Some(RunUntil::Key(chip8.input().pressed_keys()))
+                    }
+                    (Some("until"), Some("return"), None, _) => {
+                        Some(RunUntil::Depth(caller_depth(chip8)?))
+                    }
+                    (Some("until"), Some("frame"), Some(frame), None) => {
+                        let frame: u64 = frame
+                            .parse()
+                            .map_err(|_| format!("Invalid frame: {}", frame))?;
+                        if frame <= chip8.frame() {
+                            return Err(format!("Already at frame {}", chip8.frame()));
+                        }
+                        Some(RunUntil::Frame(frame))
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Expected run [until draw|key|return|frame N]: {}",
+                            input
+                        ))
+                    }
+                };
+                Ok(Some(DebuggerCommand::Continue))
+            }
+            // `run-until-draw` and the like are other names for `run until draw`
+            Some(command) if command.starts_with("run-until-") => {
+                let arguments: Vec<&str> = words.collect();
+                self.run_input(
+                    &format!(
+                        "run until {} {}",
+                        &command["run-until-".len()..],
+                        arguments.join(" ")
+                    ),
+                    chip8,
+                )
+            }
+            // Without an address or condition, lists the breakpoints
+            Some("b" | "break") => {
+                match words.next() {
+                    Some("if") => {
+                        let condition = parse_condition(&words.collect::<Vec<_>>().join(" "))?;
+                        self.conditions.push((condition, condition.is_met(chip8)));
+                    }
+                    Some(address) => {
+                        self.breakpoints.insert(self.resolve_address(address)?);
+                    }
+                    None => {}
+                }
+                let breakpoints: Vec<String> = self
+                    .breakpoints
+                    .iter()
+                    .map(|&address| self.location(address))
+                    .chain(
+                        self.conditions
+                            .iter()
+                            .map(|(condition, _)| format!("if {}", condition)),
+                    )
+                    .collect();
+                self.message = format!("Breakpoints: {}", breakpoints.join(", "));
+                Ok(None)
+            }
+            Some("d" | "delete") => {
+                self.message = match words.next().ok_or("Missing breakpoint address")? {
+                    "if" => {
+                        let condition = parse_condition(&words.collect::<Vec<_>>().join(" "))?;
+                        let len = self.conditions.len();
+                        self.conditions
+                            .retain(|&(existing_condition, _)| existing_condition != condition);
+                        if self.conditions.len() < len {
+                            format!("Deleted breakpoint if {}", condition)
+                        } else {
+                            format!("No breakpoint if {}", condition)
+                        }
+                    }
+                    address => {
+                        let address = self.resolve_address(address)?;
+                        if self.breakpoints.remove(&address) {
+                            format!("Deleted breakpoint at {}", self.location(address))
+                        } else {
+                            format!("No breakpoint at {}", self.location(address))
+                        }
+                    }
+                };
+                Ok(None)
+            }
+            // Without a location, lists the watchpoints
+            Some(command @ ("w" | "watch" | "rw" | "rwatch")) => {
+                if let Some(location) = words.next() {
+                    let access = match command {
+                        "w" | "watch" => Access::Write,
+                        _ => Access::Read,
+                    };
+                    self.watchpoints.insert((parse_location(location)?, access));
+                }
+                let mut message = "Watchpoints:".to_string();
+                for (location, access) in &self.watchpoints {
+                    let kind = match access {
+                        Access::Read => "read",
+                        Access::Write => "write",
+                    };
+                    write!(message, " {} ({})", location, kind).unwrap();
+                }
+                self.message = message;
+                Ok(None)
+            }
+            Some("uw" | "unwatch") => {
+                let location = parse_location(words.next().ok_or("Missing watched location")?)?;
+                let len = self.watchpoints.len();
+                self.watchpoints
+                    .retain(|&(watched_location, _)| watched_location != location);
+                self.message = if self.watchpoints.len() < len {
+                    format!("Deleted watchpoints on {}", location)
+                } else {
+                    format!("No watchpoint on {}", location)
+                };
+                Ok(None)
+            }
+            // Without an address, closes the memory view
+            Some("m" | "mem") => {
+                self.memory_view = match words.next() {
+                    Some(address) => {
+                        let start = self.resolve_address(address)?;
+                        let len = match words.next() {
+                            Some(len) => parse_number(len)
+                                .ok_or_else(|| format!("Invalid length: {}", len))?
+                                as usize,
+                            None => constants::RAM_LEN,
+                        };
+                        Some(MemoryView::Hex { start, len })
+                    }
+                    None => None,
+                };
+                Ok(None)
+            }
+            // Shows memory as sprites, to find graphics in a ROM
+            Some("sprites") => {
+                let start = self.resolve_address(words.next().ok_or("Missing sprite address")?)?;
+                let mut number = |name: &str| {
+                    words
+                        .next()
+                        .map(|value| {
+                            parse_number(value)
+                                .map(|number| number as usize)
+                                .ok_or_else(|| format!("Invalid {}: {}", name, value))
+                        })
+                        .transpose()
+                };
+                let len = number("length")?.unwrap_or(constants::RAM_LEN);
+                let height = number("height")?.unwrap_or(constants::DEBUGGER_SPRITE_HEIGHT);
+                if !(1..=15).contains(&height) {
+                    return Err(format!("Invalid height, expected 1 to 15: {}", height));
+                }
+                self.memory_view = Some(MemoryView::Sprites { start, len, height });
+                Ok(None)
+            }
+            // Steps over subroutine calls by running until they return
+            Some("n" | "next") => {
+                let program_counter = chip8.program_counter();
+                if chip8.ram()[program_counter] >> 4 != 0x2 {
+                    self.run_until = None;
+                    return Ok(Some(DebuggerCommand::Step(1)));
+                }
+                self.run_until = Some(RunUntil::Depth(chip8.stack_pointer()));
+                Ok(Some(DebuggerCommand::Continue))
+            }
+            Some("f" | "finish") => {
+                self.run_until = Some(RunUntil::Depth(caller_depth(chip8)?));
+                Ok(Some(DebuggerCommand::Continue))
+            }
+            _ => {
+                self.run_until = None;
+                parse_command(input).map(Some)
+            }
+        }
+    }
+
+    // Moves the memory view by whole rows, or the sprite view by whole sprites, keeping its length
+    fn scroll_memory_view(&mut self, rows: isize) {
+        let (start, row_len) = match &mut self.memory_view {
+            Some(MemoryView::Hex { start, .. }) => (start, constants::DEBUGGER_HEX_ROW_LEN),
+            Some(MemoryView::Sprites { start, height, .. }) => (start, *height),
+            None => return,
+        };
+        *start = start
+            .saturating_add_signed(rows * row_len as isize)
+            .min(constants::RAM_LEN - 1);
+    }
+
+    // Called before each instruction, the oldest delta is dropped once the history is full
+    pub fn record(&mut self, chip8: &Chip8) {
+        match &mut self.latest_state {
+            Some(state) => {
+                if self.history.len() == constants::DEBUGGER_HISTORY_LEN {
+                    self.history.pop_front();
+                }
+                self.history.push_back(state.update(chip8));
+            }
+            None => self.latest_state = Some(chip8.save_state()),
+        }
+        let ram = chip8.ram();
+        let program_counter = chip8.program_counter();
+        self.next_instruction = (ram[program_counter] as u16) << 8
+            | ram[(program_counter + 1) % constants::RAM_LEN] as u16;
+    }
+
+    // The state the given number of instructions back, or as far back as the history goes. The
+    // next `record` continues the history from there.
+    pub fn rewind(&mut self, count: u32) -> Option<MachineState> {
+        let available = self.history.len() + self.latest_state.is_some() as usize;
+        let count = (count as usize).min(available);
+        if count == 0 {
+            self.message = "No earlier states".to_string();
+            return None;
+        }
+        self.message = format!("Stepped back {} instructions", count);
+        let mut state = self.latest_state.take()?;
+        for delta in self.history.drain(self.history.len() - (count - 1)..).rev() {
+            state.undo(&delta);
+        }
+        Some(state)
+    }
+
+    // Watched locations the next instruction accesses, to be passed to `check_stop` after it runs.
+    // Fills a buffer kept by the caller, as this runs before every instruction.
+    pub fn watched_accesses(&self, chip8: &Chip8, watched_accesses: &mut Vec<WatchedAccess>) {
+        watched_accesses.clear();
+        if self.watchpoints.is_empty() {
+            return;
+        }
+        watched_accesses.extend(
+            next_accesses(chip8)
+                .iter()
+                .filter(|watchpoint| self.watchpoints.contains(watchpoint))
+                .map(|(location, access)| WatchedAccess {
+                    location,
+                    access,
+                    address: chip8.program_counter(),
+                    old_value: location.value(chip8),
+                }),
+        );
+    }
+
+    // Reports a watchpoint hit by the last instruction, a breakpoint at the next one or a
+    // condition that became true, returning whether execution should stop
+    pub fn check_stop(&mut self, chip8: &Chip8, watched_accesses: &[WatchedAccess]) -> bool {
+        let should_stop = self.check_stop_reason(chip8, watched_accesses);
+        if should_stop {
+            self.run_until = None;
+        }
+        should_stop
+    }
+
+    fn check_stop_reason(&mut self, chip8: &Chip8, watched_accesses: &[WatchedAccess]) -> bool {
+        if let Some(watched) = watched_accesses.first() {
+            let ram = chip8.ram();
+            let instruction = (ram[watched.address] as u16) << 8 | ram[watched.address + 1] as u16;
+            let responsible = format!(
+                "{:04X} {} at {}",
+                instruction,
+                disassemble_with_symbols(instruction, &self.symbols),
+                self.symbols.label(watched.address)
+            );
+            self.message = match watched.access {
+                Access::Read => format!("{} read by {}", watched.location, responsible),
+                Access::Write => format!(
+                    "{} written by {}: {:X} -> {:X}",
+                    watched.location,
+                    responsible,
+                    watched.old_value,
+                    watched.location.value(chip8)
+                ),
+            };
+            return true;
+        }
+
+        let program_counter = chip8.program_counter();
+        if self.breakpoints.contains(&program_counter) {
+            self.message = format!("Breakpoint at {}", self.location(program_counter));
+            return true;
+        }
+
+        let mut met_condition = None;
+        for (condition, was_met) in &mut self.conditions {
+            let is_met = condition.is_met(chip8);
+            if is_met && !*was_met && met_condition.is_none() {
+                met_condition = Some(*condition);
+            }
+            *was_met = is_met;
+        }
+        if let Some(condition) = met_condition {
+            self.message = format!("{} at {:03X}", condition, program_counter);
+            return true;
+        }
+
+        match self.run_until {
+            Some(RunUntil::Depth(depth)) if chip8.stack_pointer() <= depth => {
+                self.message = format!("Returned to {:03X}", program_counter);
+                true
+            }
+            Some(RunUntil::Draw) if is_draw(self.next_instruction) => {
+                self.message = format!(
+                    "Drew with {} before {:03X}",
+                    disassemble_with_symbols(self.next_instruction, &self.symbols),
+                    program_counter
+                );
+                true
+            }
+            Some(RunUntil::Key(pressed_keys)) => {
+                let now_pressed_keys = chip8.input().pressed_keys();
+                self.run_until = Some(RunUntil::Key(now_pressed_keys));
+                let newly_pressed_keys = now_pressed_keys & !pressed_keys;
+                if newly_pressed_keys == 0 {
+                    return false;
+                }
+                self.message = format!(
+                    "Key {:X} pressed before {:03X}",
+                    newly_pressed_keys.trailing_zeros(),
+                    program_counter
+                );
+                true
+            }
+            Some(RunUntil::Frame(frame)) if chip8.frame() >= frame => {
+                self.message = format!("Reached frame {} at {:03X}", frame, program_counter);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Shows new messages and each stop on stdout, for commands read from stdin
+    fn print(&mut self, chip8: &Chip8) {
+        if !self.message.is_empty() {
+            println!("{}", std::mem::take(&mut self.message));
+        }
+        let program_counter = chip8.program_counter();
+        if !self.is_stepping {
+            self.shown_program_counter = None;
+            return;
+        }
+        if self.shown_program_counter == Some(program_counter) {
+            return;
+        }
+        self.shown_program_counter = Some(program_counter);
+        let ram = chip8.ram();
+        let instruction = (ram[program_counter] as u16) << 8
+            | ram[(program_counter + 1) % constants::RAM_LEN] as u16;
+        let source = self
+            .source_map
+            .line(program_counter)
+            .map(|line| {
+                format!(
+                    "  {}:{}: {}",
+                    self.source_map.file_name(),
+                    line,
+                    self.source_map.source_line(line).unwrap_or_default().trim()
+                )
+            })
+            .unwrap_or_default();
+        println!(
+            "{:03X}  {:04X}  {}{}",
+            program_counter,
+            instruction,
+            disassemble_with_symbols(instruction, &self.symbols),
+            source
+        );
+    }
+
+    pub fn render(&mut self, chip8: &Chip8) {
+        if let Console::Stdin(_) = self.console {
+            self.print(chip8);
+            return;
+        }
+
+        let command_lines = vec![
+            Line::from(self.message.clone()).dim(),
+            Line::from(format!("> {}", self.input)),
+        ];
+
+        let Console::Terminal(terminal) = &mut self.console else {
+            return;
+        };
+        terminal
+            .draw(|frame| {
+                let rows = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(0), Constraint::Length(4)])
+                    .split(frame.size());
+                let columns = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Length(34), Constraint::Min(0)])
+                    .split(rows[0]);
+                let left = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(9), Constraint::Min(0)])
+                    .split(columns[0]);
+                let right = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Min(0),
+                        Constraint::Length(constants::DEBUGGER_MEMORY_ROWS as u16 + 2),
+                    ])
+                    .split(columns[1]);
+
+                frame.render_widget(pane("Registers", register_lines(chip8)), left[0]);
+                frame.render_widget(
+                    pane("Call stack", stack_lines(chip8, &self.symbols)),
+                    left[1],
+                );
+                match self.memory_view {
+                    Some(MemoryView::Hex { start, len }) => frame.render_widget(
+                        pane(
+                            "Memory, I reversed, PC underlined",
+                            hex_lines(chip8, start, len),
+                        ),
+                        columns[1],
+                    ),
+                    Some(MemoryView::Sprites { start, len, height }) => frame.render_widget(
+                        pane(
+                            &format!("Sprites of {} rows, I reversed", height),
+                            sprite_lines(
+                                chip8,
+                                start,
+                                len,
+                                height,
+                                columns[1].width.saturating_sub(2) as usize,
+                            ),
+                        ),
+                        columns[1],
+                    ),
+                    None => {
+                        let code = pane(
+                            "Disassembly",
+                            code_lines(chip8, &self.breakpoints, &self.symbols),
+                        );
+                        if self.source_map.is_empty() {
+                            frame.render_widget(code, right[0]);
+                        } else {
+                            let source_and_code = Layout::default()
+                                .direction(Direction::Vertical)
+                                .constraints([Constraint::Percentage(50), Constraint::Min(0)])
+                                .split(right[0]);
+                            frame.render_widget(
+                                pane(
+                                    &self.source_map.file_name(),
+                                    source_lines(chip8, &self.breakpoints, &self.source_map),
+                                ),
+                                source_and_code[0],
+                            );
+                            frame.render_widget(code, source_and_code[1]);
+                        }
+                        frame.render_widget(pane("Memory at I", memory_lines(chip8)), right[1]);
+                    }
+                }
+                frame.render_widget(pane("Command", command_lines), rows[1]);
+            })
+            .unwrap();
+    }
+}
+
+impl Drop for Debugger {
+    fn drop(&mut self) {
+        if let Console::Terminal(_) = self.console {
+            execute!(io::stdout(), terminal::LeaveAlternateScreen).unwrap();
+            terminal::disable_raw_mode().unwrap();
+        }
+    }
+}
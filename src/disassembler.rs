@@ -0,0 +1,84 @@
+use crate::constants;
+use crate::instruction::ParsedInstruction;
+use crate::symbols::Symbols;
+
+// Mnemonic in the common Cowgod notation, unknown instructions are shown as data words
+pub fn disassemble(instruction: u16) -> String {
+    disassemble_with_symbols(instruction, &Symbols::new())
+}
+
+// Addresses with a symbol are shown by name
+pub fn disassemble_with_symbols(instruction: u16, symbols: &Symbols) -> String {
+    let ParsedInstruction {
+        opcode,
+        x,
+        y,
+        n,
+        nn,
+        nnn,
+    } = ParsedInstruction::build(instruction);
+    let address = symbols.label(nnn as usize);
+
+    match (opcode, nn, n) {
+        (0x0, 0xE0, _) => "CLS".to_string(),
+        (0x0, 0xEE, _) => "RET".to_string(),
+        (0x1, _, _) => format!("JP {}", address),
+        (0x2, _, _) => format!("CALL {}", address),
+        (0x3, _, _) => format!("SE V{:X}, {:02X}", x, nn),
+        (0x4, _, _) => format!("SNE V{:X}, {:02X}", x, nn),
+        (0x5, _, 0x0) => format!("SE V{:X}, V{:X}", x, y),
+        (0x6, _, _) => format!("LD V{:X}, {:02X}", x, nn),
+        (0x7, _, _) => format!("ADD V{:X}, {:02X}", x, nn),
+        (0x8, _, 0x0) => format!("LD V{:X}, V{:X}", x, y),
+        (0x8, _, 0x1) => format!("OR V{:X}, V{:X}", x, y),
+        (0x8, _, 0x2) => format!("AND V{:X}, V{:X}", x, y),
+        (0x8, _, 0x3) => format!("XOR V{:X}, V{:X}", x, y),
+        (0x8, _, 0x4) => format!("ADD V{:X}, V{:X}", x, y),
+        (0x8, _, 0x5) => format!("SUB V{:X}, V{:X}", x, y),
+        (0x8, _, 0x6) => format!("SHR V{:X}, V{:X}", x, y),
+        (0x8, _, 0x7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (0x8, _, 0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (0x9, _, 0x0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, _, _) => format!("LD I, {}", address),
+        (0xB, _, _) => format!("JP V0, {}", address),
+        (0xC, _, _) => format!("RND V{:X}, {:02X}", x, nn),
+        (0xD, _, _) => format!("DRW V{:X}, V{:X}, {:X}", x, y, n),
+        (0xE, 0x9E, _) => format!("SKP V{:X}", x),
+        (0xE, 0xA1, _) => format!("SKNP V{:X}", x),
+        (0xF, 0x07, _) => format!("LD V{:X}, DT", x),
+        (0xF, 0x0A, _) => format!("LD V{:X}, K", x),
+        (0xF, 0x15, _) => format!("LD DT, V{:X}", x),
+        (0xF, 0x18, _) => format!("LD ST, V{:X}", x),
+        (0xF, 0x1E, _) => format!("ADD I, V{:X}", x),
+        (0xF, 0x29, _) => format!("LD F, V{:X}", x),
+        (0xF, 0x33, _) => format!("LD B, V{:X}", x),
+        (0xF, 0x55, _) => format!("LD [I], V{:X}", x),
+        (0xF, 0x65, _) => format!("LD V{:X}, [I]", x),
+        _ => format!("DW {:04X}", instruction),
+    }
+}
+
+// A listing of a ROM as loaded at the program start, with a line naming each named address
+pub fn disassemble_rom(rom: &[u8], symbols: &Symbols) -> String {
+    let mut listing = String::new();
+    for (index, bytes) in rom.chunks(2).enumerate() {
+        let address = constants::PROGRAM_START + index * 2;
+        if let Some(name) = symbols.name(address) {
+            listing.push_str(&format!("{}:\n", name));
+        }
+        listing.push_str(&match *bytes {
+            [first, second] => {
+                let instruction = (first as u16) << 8 | second as u16;
+                format!(
+                    "{:03X}  {:04X}  {}\n",
+                    address,
+                    instruction,
+                    disassemble_with_symbols(instruction, symbols)
+                )
+            }
+            // A trailing odd byte
+            _ => format!("{:03X}  {:02X}    DB {:02X}\n", address, bytes[0], bytes[0]),
+        });
+    }
+    listing
+}
@@ -1,76 +1,591 @@
-use sdl2::{pixels::Color, render::Canvas, video::Window, Sdl};
+use sdl2::{
+    pixels::{Color, PixelFormatEnum},
+    rect::{Point, Rect},
+    render::{BlendMode, Canvas, Texture},
+    video::{FullscreenType, Window},
+    Sdl,
+};
+
+use std::path::Path;
 
 use crate::constants;
+use crate::display_options::{DisplayOptions, Filter, Mirror, Rotation, SoundFlash};
+use crate::image::{load_png, save_png, scale_pixels};
+use crate::overlay;
+use crate::renderer::Renderer;
+
+fn build_scanlines((width, height): (u32, u32), (scale_x, scale_y): (u32, u32)) -> Vec<Rect> {
+    let thickness = scale_y / 3;
+    if thickness == 0 {
+        return Vec::new();
+    }
+
+    (0..height)
+        .map(|y| {
+            Rect::new(
+                0,
+                ((y + 1) * scale_y - thickness) as i32,
+                width * scale_x,
+                thickness,
+            )
+        })
+        .collect()
+}
+
+fn build_grid_lines((width, height): (u32, u32), (scale_x, scale_y): (u32, u32)) -> Vec<Rect> {
+    if scale_x.min(scale_y) < 4 {
+        return Vec::new();
+    }
+
+    let columns = (1..width).map(|x| Rect::new((x * scale_x) as i32, 0, 1, height * scale_y));
+    let rows = (1..height).map(|y| Rect::new(0, (y * scale_y) as i32, width * scale_x, 1));
+    columns.chain(rows).collect()
+}
+
+fn build_pixel_corners((width, height): (u32, u32), (scale_x, scale_y): (u32, u32)) -> Vec<Point> {
+    if scale_x.min(scale_y) < 4 {
+        return Vec::new();
+    }
+
+    let mut corners = Vec::with_capacity(constants::DISPLAY_LEN * 4);
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let left = x * scale_x as i32;
+            let top = y * scale_y as i32;
+            let right = left + scale_x as i32 - 1;
+            let bottom = top + scale_y as i32 - 1;
+            corners.extend_from_slice(&[
+                Point::new(left, top),
+                Point::new(right, top),
+                Point::new(left, bottom),
+                Point::new(right, bottom),
+            ]);
+        }
+    }
+    corners
+}
 
 pub struct Display {
     canvas: Canvas<Window>,
-    scale: u32,
+    texture: Texture,
+    texture_rect: Rect,
+    border_texture: Option<Texture>,
+    game_rect: Rect,
+    pixel_size: (u32, u32),
+    idle_step: Option<u64>,
+    sound_flash: Option<SoundFlash>,
+    is_flashing: bool,
+    pixels: [u8; constants::DISPLAY_LEN * 3],
+    intensities: [f32; constants::DISPLAY_LEN],
+    phosphor_decay: f32,
+    frame_blend: bool,
+    previous_buffer: [bool; constants::DISPLAY_LEN],
+    is_blending: bool,
+    rotation: Rotation,
+    mirror: Option<Mirror>,
+    filter: Filter,
+    scanlines: Vec<Rect>,
+    grid_lines: Vec<Rect>,
+    pixel_corners: Vec<Point>,
     background_color: Color,
     foreground_color: Color,
+    overlay: String,
+    // Glyph pixels of the overlay and keypad, reused between frames
+    text_rects: Vec<Rect>,
+    keypad: Option<(u16, u16)>,
 }
 
 impl Display {
-    pub fn build(
-        sdl: &Sdl,
-        scale: u32,
-        background_color: (u8, u8, u8),
-        foreground_color: (u8, u8, u8),
-    ) -> Self {
+    pub fn build(sdl: &Sdl, options: DisplayOptions) -> Self {
+        let (scale_x, scale_y) = options.pixel_size();
+        let output_size = options.rotation.output_size();
+        let (output_width, output_height) = (output_size.0 * scale_x, output_size.1 * scale_y);
+        let border = options
+            .border
+            .as_deref()
+            .map(|path| load_png(Path::new(path)));
+
+        // With a screen rectangle the border is stretched until that rectangle fits the game area.
+        // Otherwise a border larger than the game area extends the window, centering the game.
+        let (window_width, window_height, game_x, game_y) = match (&border, options.border_rect) {
+            (Some((width, height, _)), Some((x, y, rect_width, rect_height))) => {
+                if x + rect_width > *width || y + rect_height > *height {
+                    panic!(
+                        "Border rectangle exceeds the {}x{} border image",
+                        width, height
+                    );
+                }
+                (
+                    width * output_width / rect_width,
+                    height * output_height / rect_height,
+                    x * output_width / rect_width,
+                    y * output_height / rect_height,
+                )
+            }
+            (Some((width, height, _)), None) => {
+                let (window_width, window_height) =
+                    (output_width.max(*width), output_height.max(*height));
+                (
+                    window_width,
+                    window_height,
+                    (window_width - output_width) / 2,
+                    (window_height - output_height) / 2,
+                )
+            }
+            (None, _) => (output_width, output_height, 0, 0),
+        };
+        let game_rect = Rect::new(game_x as i32, game_y as i32, output_width, output_height);
+        let background_color = Color::RGB(
+            options.background_color.0,
+            options.background_color.1,
+            options.background_color.2,
+        );
+        let foreground_color = Color::RGB(
+            options.foreground_color.0,
+            options.foreground_color.1,
+            options.foreground_color.2,
+        );
+
+        // Once DPI aware, Windows sizes windows in physical pixels, so scale them up to match the
+        // desktop. macOS keeps sizes in points and only needs high-DPI drawables to stay sharp.
+        sdl2::hint::set("SDL_WINDOWS_DPI_AWARENESS", "permonitorv2");
         let video_subsystem = sdl.video().unwrap();
+        let dpi_scale = if cfg!(target_os = "windows") {
+            video_subsystem
+                .display_dpi(0)
+                .map_or(1.0, |(_, horizontal_dpi, _)| {
+                    horizontal_dpi / constants::DEFAULT_DPI
+                })
+        } else {
+            1.0
+        };
         let window = video_subsystem
             .window(
                 constants::WINDOW_TITLE,
-                constants::DISPLAY_WIDTH as u32 * scale,
-                constants::DISPLAY_HEIGHT as u32 * scale,
+                (window_width as f32 * dpi_scale).round() as u32,
+                (window_height as f32 * dpi_scale).round() as u32,
             )
             .position_centered()
+            .allow_highdpi()
             .build()
             .unwrap();
 
-        let mut canvas = window.into_canvas().build().unwrap();
-        canvas.set_draw_color(Color::RGB(
-            background_color.0,
-            background_color.1,
-            background_color.2,
-        ));
+        let mut canvas_builder = window.into_canvas();
+        if options.vsync {
+            canvas_builder = canvas_builder.present_vsync();
+        }
+        let mut canvas = canvas_builder.build().unwrap();
+        canvas
+            .set_logical_size(window_width, window_height)
+            .unwrap();
+        canvas.set_blend_mode(BlendMode::Blend);
+        if options.fullscreen {
+            canvas
+                .window_mut()
+                .set_fullscreen(FullscreenType::Desktop)
+                .unwrap();
+        }
+        canvas.set_draw_color(background_color);
         canvas.clear();
         canvas.present();
 
+        let texture = canvas
+            .texture_creator()
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                constants::DISPLAY_WIDTH as u32,
+                constants::DISPLAY_HEIGHT as u32,
+            )
+            .unwrap();
+
+        let border_texture = border.map(|(width, height, rgba)| {
+            let mut border_texture = canvas
+                .texture_creator()
+                .create_texture_static(PixelFormatEnum::RGBA32, width, height)
+                .unwrap();
+            border_texture
+                .update(None, &rgba, width as usize * 4)
+                .unwrap();
+            border_texture
+        });
+
+        // The unrotated texture is centered on the output so rotating it about its center fills it
+        let (texture_width, texture_height) = match options.rotation {
+            Rotation::Rotate0 | Rotation::Rotate180 => (output_width, output_height),
+            Rotation::Rotate90 | Rotation::Rotate270 => (output_height, output_width),
+        };
+        let texture_rect = Rect::new(
+            (output_width as i32 - texture_width as i32) / 2,
+            (output_height as i32 - texture_height as i32) / 2,
+            texture_width,
+            texture_height,
+        );
+
         Display {
             canvas,
-            scale,
-            background_color: Color::RGB(
-                background_color.0,
-                background_color.1,
-                background_color.2,
-            ),
-            foreground_color: Color::RGB(
-                foreground_color.0,
-                foreground_color.1,
-                foreground_color.2,
-            ),
-        }
-    }
-
-    pub fn render_buffer(&mut self, buffer: [bool; constants::DISPLAY_LEN]) {
-        for x in 0..constants::DISPLAY_WIDTH {
-            for y in 0..constants::DISPLAY_HEIGHT {
-                if buffer[x + y * constants::DISPLAY_WIDTH] {
-                    self.canvas.set_draw_color(self.foreground_color);
-                } else {
-                    self.canvas.set_draw_color(self.background_color);
-                }
+            texture,
+            texture_rect,
+            border_texture,
+            game_rect,
+            pixel_size: (scale_x, scale_y),
+            idle_step: None,
+            sound_flash: options.sound_flash,
+            is_flashing: false,
+            pixels: [0; constants::DISPLAY_LEN * 3],
+            intensities: [0.0; constants::DISPLAY_LEN],
+            phosphor_decay: 1.0 / options.phosphor_frames.max(1) as f32,
+            frame_blend: options.frame_blend,
+            previous_buffer: [false; constants::DISPLAY_LEN],
+            is_blending: false,
+            rotation: options.rotation,
+            mirror: options.mirror,
+            filter: options.filter,
+            scanlines: build_scanlines(output_size, (scale_x, scale_y)),
+            grid_lines: if options.grid || options.filter == Filter::Lcd {
+                build_grid_lines(output_size, (scale_x, scale_y))
+            } else {
+                Vec::new()
+            },
+            pixel_corners: build_pixel_corners(output_size, (scale_x, scale_y)),
+            background_color,
+            foreground_color,
+            overlay: String::new(),
+            text_rects: Vec::new(),
+            keypad: None,
+        }
+    }
+
+    // Maps a logical window position to the emulated pixel under it, undoing rotation and mirroring
+    pub fn to_display_coordinates(&self, x: i32, y: i32) -> Option<(u8, u8)> {
+        if !self.game_rect.contains_point(Point::new(x, y)) {
+            return None;
+        }
+
+        let output_x = (x - self.game_rect.x()) as usize / self.pixel_size.0 as usize;
+        let output_y = (y - self.game_rect.y()) as usize / self.pixel_size.1 as usize;
+        let (width, height) = (constants::DISPLAY_WIDTH, constants::DISPLAY_HEIGHT);
+        let (mut display_x, mut display_y) = match self.rotation {
+            Rotation::Rotate0 => (output_x, output_y),
+            Rotation::Rotate90 => (output_y, height - 1 - output_x),
+            Rotation::Rotate180 => (width - 1 - output_x, height - 1 - output_y),
+            Rotation::Rotate270 => (width - 1 - output_y, output_x),
+        };
+        match self.mirror {
+            Some(Mirror::Horizontal) => display_x = width - 1 - display_x,
+            Some(Mirror::Vertical) => display_y = height - 1 - display_y,
+            None => {}
+        }
+        Some((display_x as u8, display_y as u8))
+    }
+
+    // Dims and shifts the image to protect against burn-in, returns whether it changed
+    pub fn set_idle_step(&mut self, idle_step: Option<u64>) -> bool {
+        let is_changed = self.idle_step != idle_step;
+        self.idle_step = idle_step;
+        is_changed
+    }
+
+    // Flashes the game area while the sound timer is active, returns whether it changed
+    pub fn set_flashing(&mut self, is_flashing: bool) -> bool {
+        let is_flashing = is_flashing && self.sound_flash.is_some();
+        let is_changed = self.is_flashing != is_flashing;
+        self.is_flashing = is_flashing;
+        is_changed
+    }
+
+    // Last rendered frame as unscaled RGB24, without rotation or filters
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
 
-                let x = x as u32 * self.scale;
-                let y = y as u32 * self.scale;
+    pub fn pixel_size(&self) -> (u32, u32) {
+        self.pixel_size
+    }
 
-                self.canvas
-                    .fill_rect(sdl2::rect::Rect::new(
-                        x as i32, y as i32, self.scale, self.scale,
-                    ))
-                    .unwrap();
+    pub fn save_screenshot(&self, path: &Path) -> Result<(), png::EncodingError> {
+        let width = constants::DISPLAY_WIDTH as u32 * self.pixel_size.0;
+        let height = constants::DISPLAY_HEIGHT as u32 * self.pixel_size.1;
+        save_png(
+            path,
+            width,
+            height,
+            &scale_pixels(&self.pixels, self.pixel_size),
+        )
+    }
+
+    // Lines of text drawn over the top left of the game area, hidden when empty. Written in place
+    // so the stats updating every frame do not allocate.
+    pub fn overlay_mut(&mut self) -> &mut String {
+        &mut self.overlay
+    }
+
+    // Pressed and polled key masks shown on a keypad in the bottom right, hidden when None
+    pub fn set_keypad(&mut self, keypad: Option<(u16, u16)>) {
+        self.keypad = keypad;
+    }
+
+    // Takes effect with the next rendered frame, including screenshots and recordings
+    pub fn set_colors(&mut self, background_color: (u8, u8, u8), foreground_color: (u8, u8, u8)) {
+        self.background_color = Color::from(background_color);
+        self.foreground_color = Color::from(foreground_color);
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.canvas.window_mut().set_title(title).unwrap();
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        let fullscreen_type = match self.canvas.window().fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        self.canvas
+            .window_mut()
+            .set_fullscreen(fullscreen_type)
+            .unwrap();
+    }
+
+    // Fades out unset pixels by one frame, returns whether the buffer needs to be rendered again
+    pub fn advance_frame(&mut self, buffer: &[bool; constants::DISPLAY_LEN]) -> bool {
+        if self.phosphor_decay >= 1.0 {
+            return std::mem::take(&mut self.is_blending);
+        }
+
+        let mut is_fading = std::mem::take(&mut self.is_blending);
+        for (intensity, &is_set) in self.intensities.iter_mut().zip(buffer.iter()) {
+            if !is_set && *intensity > 0.0 {
+                *intensity = (*intensity - self.phosphor_decay).max(0.0);
+                is_fading = true;
+            }
+        }
+        is_fading
+    }
+
+    fn draw_overlay(&mut self) {
+        if self.overlay.is_empty() {
+            return;
+        }
+
+        let pixel_size = self.overlay_pixel_size();
+        let (width, height) = overlay::text_size(&self.overlay, pixel_size);
+        let position = (
+            self.game_rect.x() + pixel_size as i32,
+            self.game_rect.y() + pixel_size as i32,
+        );
+        self.canvas
+            .set_draw_color(Color::RGBA(0, 0, 0, constants::OVERLAY_BACKGROUND_ALPHA));
+        self.canvas
+            .fill_rect(Rect::new(
+                self.game_rect.x(),
+                self.game_rect.y(),
+                width + pixel_size,
+                height + pixel_size,
+            ))
+            .unwrap();
+        self.canvas.set_draw_color(Color::WHITE);
+        overlay::text_rects(&self.overlay, position, pixel_size, &mut self.text_rects);
+        self.canvas.fill_rects(&self.text_rects).unwrap();
+    }
+
+    // The panel in the bottom right of the game area and the cell of each key in it
+    fn keypad_layout(&self) -> (Rect, [(Rect, u8); constants::KEY_COUNT]) {
+        let pixel_size = self.overlay_pixel_size();
+        let (cell_width, cell_height) = (
+            constants::KEYPAD_CELL_SIZE.0 * pixel_size,
+            constants::KEYPAD_CELL_SIZE.1 * pixel_size,
+        );
+        let width = 4 * cell_width + 5 * pixel_size;
+        let height = 4 * cell_height + 5 * pixel_size;
+        let left = self.game_rect.right() - width as i32;
+        let top = self.game_rect.bottom() - height as i32;
+
+        let cells = std::array::from_fn(|index| {
+            let (row, column) = (index / 4, index % 4);
+            let cell = Rect::new(
+                left + (pixel_size + column as u32 * (cell_width + pixel_size)) as i32,
+                top + (pixel_size + row as u32 * (cell_height + pixel_size)) as i32,
+                cell_width,
+                cell_height,
+            );
+            (cell, constants::KEYPAD_LAYOUT[row][column])
+        });
+        (Rect::new(left, top, width, height), cells)
+    }
+
+    // The key of the shown keypad under a window position, for clicking or tapping it
+    pub fn keypad_key_at(&self, x: i32, y: i32) -> Option<u8> {
+        self.keypad?;
+        let (_, cells) = self.keypad_layout();
+        cells
+            .into_iter()
+            .find(|(cell, _)| cell.contains_point(Point::new(x, y)))
+            .map(|(_, key)| key)
+    }
+
+    fn draw_keypad(&mut self) {
+        let Some((pressed_keys, polled_keys)) = self.keypad else {
+            return;
+        };
+
+        let pixel_size = self.overlay_pixel_size();
+        let (panel, cells) = self.keypad_layout();
+        self.canvas
+            .set_draw_color(Color::RGBA(0, 0, 0, constants::OVERLAY_BACKGROUND_ALPHA));
+        self.canvas.fill_rect(panel).unwrap();
+
+        for (cell, key) in cells {
+            let is_pressed = pressed_keys & (1 << key) != 0;
+
+            // Polled keys get a frame drawn into the gap around the cell
+            if polled_keys & (1 << key) != 0 {
+                let mut frame = cell;
+                frame.offset(-(pixel_size as i32), -(pixel_size as i32));
+                frame.resize(
+                    cell.width() + 2 * pixel_size,
+                    cell.height() + 2 * pixel_size,
+                );
+                self.canvas.set_draw_color(Color::YELLOW);
+                self.canvas.fill_rect(frame).unwrap();
+            }
+            self.canvas.set_draw_color(if is_pressed {
+                Color::WHITE
+            } else {
+                Color::RGB(64, 64, 64)
+            });
+            self.canvas.fill_rect(cell).unwrap();
+
+            let mut label = [0; 4];
+            let label = char::from_digit(key as u32, 16)
+                .unwrap()
+                .to_ascii_uppercase()
+                .encode_utf8(&mut label);
+            let position = (cell.x() + pixel_size as i32, cell.y() + pixel_size as i32);
+            self.canvas.set_draw_color(if is_pressed {
+                Color::BLACK
+            } else {
+                Color::WHITE
+            });
+            overlay::text_rects(label, position, pixel_size, &mut self.text_rects);
+            self.canvas.fill_rects(&self.text_rects).unwrap();
+        }
+    }
+
+    fn overlay_pixel_size(&self) -> u32 {
+        (self.pixel_size.1 / constants::OVERLAY_TEXT_SCALE_DIVISOR).max(1)
+    }
+
+    fn apply_filter(&mut self) {
+        // The LCD filter only needs the grid lines drawn with the game
+        if matches!(self.filter, Filter::None | Filter::Lcd) {
+            return;
+        }
+
+        self.canvas
+            .set_draw_color(Color::RGBA(0, 0, 0, constants::SCANLINE_ALPHA));
+        self.canvas.fill_rects(&self.scanlines).unwrap();
+
+        if self.filter == Filter::Crt {
+            self.canvas
+                .set_draw_color(Color::RGBA(0, 0, 0, constants::PIXEL_CORNER_ALPHA));
+            self.canvas.draw_points(&self.pixel_corners[..]).unwrap();
+        }
+    }
+}
+
+impl Renderer for Display {
+    fn render_buffer(&mut self, buffer: &[bool; constants::DISPLAY_LEN]) {
+        for (index, pixel) in self.pixels.chunks_exact_mut(3).enumerate() {
+            let intensity = &mut self.intensities[index];
+            *intensity = if buffer[index] {
+                1.0
+            } else {
+                intensity.min(1.0 - self.phosphor_decay)
+            };
+
+            let mut shade = *intensity;
+            if self.frame_blend {
+                shade = (shade + self.previous_buffer[index] as u8 as f32) / 2.0;
             }
+
+            let blend = |background: u8, foreground: u8| {
+                (background as f32 + (foreground as f32 - background as f32) * shade) as u8
+            };
+            pixel.copy_from_slice(&[
+                blend(self.background_color.r, self.foreground_color.r),
+                blend(self.background_color.g, self.foreground_color.g),
+                blend(self.background_color.b, self.foreground_color.b),
+            ]);
+        }
+
+        self.is_blending = self.frame_blend && self.previous_buffer != *buffer;
+        self.previous_buffer = *buffer;
+
+        // Inverting only the texture keeps the flash out of screenshots and recordings
+        if self.is_flashing && self.sound_flash == Some(SoundFlash::Invert) {
+            let color_sums = [
+                self.background_color.r as u16 + self.foreground_color.r as u16,
+                self.background_color.g as u16 + self.foreground_color.g as u16,
+                self.background_color.b as u16 + self.foreground_color.b as u16,
+            ];
+            let inverted: Vec<u8> = self
+                .pixels
+                .iter()
+                .zip(color_sums.iter().cycle())
+                .map(|(&channel, &color_sum)| (color_sum - channel as u16) as u8)
+                .collect();
+            self.texture
+                .update(None, &inverted, constants::DISPLAY_WIDTH * 3)
+                .unwrap();
+        } else {
+            self.texture
+                .update(None, &self.pixels, constants::DISPLAY_WIDTH * 3)
+                .unwrap();
+        }
+
+        if let Some(border_texture) = &self.border_texture {
+            self.canvas.set_draw_color(self.background_color);
+            self.canvas.clear();
+            self.canvas.copy(border_texture, None, None).unwrap();
+        }
+
+        let mut game_rect = self.game_rect;
+        if let Some(idle_step) = self.idle_step {
+            let offset = constants::IDLE_SHIFT_OFFSETS
+                [idle_step as usize % constants::IDLE_SHIFT_OFFSETS.len()];
+            game_rect.offset(offset.0, offset.1);
+        }
+        self.canvas.set_viewport(game_rect);
+        self.canvas
+            .copy_ex(
+                &self.texture,
+                None,
+                self.texture_rect,
+                self.rotation.degrees(),
+                None,
+                self.mirror == Some(Mirror::Horizontal),
+                self.mirror == Some(Mirror::Vertical),
+            )
+            .unwrap();
+        if !self.grid_lines.is_empty() {
+            self.canvas.set_draw_color(self.background_color);
+            self.canvas.fill_rects(&self.grid_lines).unwrap();
+        }
+        self.apply_filter();
+        if self.is_flashing && self.sound_flash == Some(SoundFlash::Tint) {
+            let (red, green, blue, alpha) = constants::SOUND_FLASH_TINT;
+            self.canvas
+                .set_draw_color(Color::RGBA(red, green, blue, alpha));
+            self.canvas.fill_rect(None).unwrap();
+        }
+        self.canvas.set_viewport(None);
+
+        if self.idle_step.is_some() {
+            self.canvas
+                .set_draw_color(Color::RGBA(0, 0, 0, constants::IDLE_DIM_ALPHA));
+            self.canvas.fill_rect(None).unwrap();
         }
+        self.draw_overlay();
+        self.draw_keypad();
         self.canvas.present();
     }
 }
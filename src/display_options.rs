@@ -0,0 +1,105 @@
+use clap::ValueEnum;
+
+use crate::constants;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Filter {
+    None,
+    Scanlines,
+    Crt,
+    // Gaps between pixels like a monochrome LCD, best combined with the lcd theme
+    Lcd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Rotation {
+    #[value(name = "0")]
+    Rotate0,
+    #[value(name = "90")]
+    Rotate90,
+    #[value(name = "180")]
+    Rotate180,
+    #[value(name = "270")]
+    Rotate270,
+}
+
+impl Rotation {
+    pub fn degrees(self) -> f64 {
+        match self {
+            Rotation::Rotate0 => 0.0,
+            Rotation::Rotate90 => 90.0,
+            Rotation::Rotate180 => 180.0,
+            Rotation::Rotate270 => 270.0,
+        }
+    }
+
+    // Size of the rotated display in emulated pixels
+    pub fn output_size(self) -> (u32, u32) {
+        let (width, height) = (
+            constants::DISPLAY_WIDTH as u32,
+            constants::DISPLAY_HEIGHT as u32,
+        );
+        match self {
+            Rotation::Rotate0 | Rotation::Rotate180 => (width, height),
+            Rotation::Rotate90 | Rotation::Rotate270 => (height, width),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Mirror {
+    #[value(name = "h")]
+    Horizontal,
+    #[value(name = "v")]
+    Vertical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SoundFlash {
+    // Swaps the foreground and background colors
+    Invert,
+    // Draws a translucent color over the game area
+    Tint,
+}
+
+// Parses `x,y,width,height` into a rectangle with a non-zero size
+pub fn parse_rect(value: &str) -> Result<(u32, u32, u32, u32), String> {
+    let parts: Vec<u32> = value
+        .split(',')
+        .map(|part| part.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid rectangle: {}", value))?;
+    match parts[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok((x, y, width, height)),
+        _ => Err(format!(
+            "Invalid rectangle, expected x,y,width,height: {}",
+            value
+        )),
+    }
+}
+
+pub struct DisplayOptions {
+    pub scale: u32,
+    pub pixel_aspect: f32,
+    pub background_color: (u8, u8, u8),
+    pub foreground_color: (u8, u8, u8),
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub filter: Filter,
+    pub rotation: Rotation,
+    pub mirror: Option<Mirror>,
+    pub phosphor_frames: u8,
+    pub frame_blend: bool,
+    pub border: Option<String>,
+    pub border_rect: Option<(u32, u32, u32, u32)>,
+    pub grid: bool,
+    pub sound_flash: Option<SoundFlash>,
+}
+
+impl DisplayOptions {
+    // Pixels may be non-square, with the aspect being their on-screen width over height
+    pub fn pixel_size(&self) -> (u32, u32) {
+        let scale_x = ((self.scale as f32 * self.pixel_aspect).round() as u32).max(1);
+        (scale_x, self.scale)
+    }
+}
@@ -0,0 +1,522 @@
+use std::{collections::BTreeSet, io, net::TcpStream};
+
+use crate::chip_8::Chip8;
+use crate::condition::Operand;
+use crate::constants;
+use crate::debugger::DebuggerCommand;
+use crate::remote_debugger::{accept, read_available, write_all, RemoteDebugger};
+
+// Registers in the order of `g` packets: V0-VF, I, PC, SP, DT, ST
+const REGISTER_COUNT: usize = constants::REGISTER_COUNT + 5;
+
+// Describes the registers, as gdb knows no CHIP-8 architecture of its own
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// The stop reply for every stop, a SIGTRAP
+const STOP_REPLY: &str = "S05";
+
+fn register_operand(register: usize) -> Option<Operand> {
+    match register {
+        0..=0xF => Some(Operand::Register(register)),
+        0x10 => Some(Operand::Index),
+        0x11 => Some(Operand::ProgramCounter),
+        0x13 => Some(Operand::DelayTimer),
+        0x14 => Some(Operand::SoundTimer),
+        // The stack pointer is read-only
+        _ => None,
+    }
+}
+
+// Value and size in bytes of a register
+fn register_value(chip8: &Chip8, register: usize) -> (u16, usize) {
+    match register {
+        0..=0xF => (chip8.registers()[register] as u16, 1),
+        0x10 => (chip8.index_register(), 2),
+        0x11 => (chip8.program_counter() as u16, 2),
+        0x12 => (chip8.stack_pointer() as u16, 1),
+        0x13 => (chip8.delay_timer() as u16, 1),
+        _ => (chip8.sound_timer() as u16, 1),
+    }
+}
+
+fn encode_register(chip8: &Chip8, register: usize) -> String {
+    let (value, size) = register_value(chip8, register);
+    // Little endian
+    (0..size)
+        .map(|byte| format!("{:02x}", (value >> (byte * 8)) as u8))
+        .collect()
+}
+
+fn decode_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|start| u8::from_str_radix(hex.get(start..start + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(value: &str) -> Option<usize> {
+    usize::from_str_radix(value, 16).ok()
+}
+
+// `addr,length` as used by memory and breakpoint packets, checked against memory
+fn parse_range(value: &str) -> Option<(usize, usize)> {
+    let (address, len) = value.split_once(',')?;
+    let (address, len) = (parse_hex(address)?, parse_hex(len)?);
+    (address.checked_add(len)? <= constants::RAM_LEN).then_some((address, len))
+}
+
+// A minimal GDB Remote Serial Protocol server for a single connection, mapping the registers
+// (see `TARGET_XML`) and memory and supporting stepping and software breakpoints
+pub struct GdbStub {
+    stream: Option<TcpStream>,
+    input: Vec<u8>,
+    breakpoints: BTreeSet<usize>,
+    // gdb continued or stepped and expects a stop reply once the machine stops
+    is_waiting: bool,
+}
+
+impl GdbStub {
+    // Blocks until gdb connects
+    pub fn listen(port: u16) -> io::Result<Self> {
+        Ok(GdbStub {
+            stream: Some(accept(port, "gdb")?),
+            input: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            is_waiting: false,
+        })
+    }
+
+    // Removes the next packet from the input, an interrupt (Ctrl+C) counts as the packet `\x03`
+    fn take_packet(&mut self) -> Option<String> {
+        loop {
+            match *self.input.first()? {
+                // Acknowledgements of our replies, which are never resent
+                b'+' | b'-' => {
+                    self.input.remove(0);
+                }
+                0x03 => {
+                    self.input.remove(0);
+                    return Some("\x03".to_string());
+                }
+                b'$' => break,
+                _ => {
+                    self.input.remove(0);
+                }
+            }
+        }
+        let end = self.input.iter().position(|&byte| byte == b'#')?;
+        // The checksum follows as two hex digits
+        if self.input.len() < end + 3 {
+            return None;
+        }
+        let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+        self.send_raw(b"+");
+        Some(String::from_utf8_lossy(&packet[1..end]).into_owned())
+    }
+
+    fn handle_packet(&mut self, packet: &str, chip8: &mut Chip8) -> Option<DebuggerCommand> {
+        let (kind, arguments) = (packet.get(..1).unwrap_or(""), packet.get(1..).unwrap_or(""));
+        let reply = match kind {
+            "\x03" => {
+                self.is_waiting = true;
+                return Some(DebuggerCommand::Pause);
+            }
+            "?" => STOP_REPLY.to_string(),
+            "c" => {
+                self.is_waiting = true;
+                return Some(DebuggerCommand::Continue);
+            }
+            "s" => {
+                self.is_waiting = true;
+                return Some(DebuggerCommand::Step(1));
+            }
+            "D" => {
+                self.send("OK");
+                self.stream = None;
+                return Some(DebuggerCommand::Continue);
+            }
+            "k" => return Some(DebuggerCommand::Quit),
+            "g" => (0..REGISTER_COUNT)
+                .map(|register| encode_register(chip8, register))
+                .collect(),
+            "G" => self.write_registers(arguments, chip8),
+            "p" => parse_hex(arguments)
+                .filter(|&register| register < REGISTER_COUNT)
+                .map_or_else(
+                    || "E01".to_string(),
+                    |register| encode_register(chip8, register),
+                ),
+            "P" => self.write_register(arguments, chip8),
+            "m" => match parse_range(arguments) {
+                Some((address, len)) => chip8.ram()[address..address + len]
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect(),
+                None => "E01".to_string(),
+            },
+            "M" => self.write_memory(arguments, chip8),
+            "Z" | "z" => self.change_breakpoint(kind == "Z", arguments),
+            "H" => "OK".to_string(),
+            "q" => self.query(packet),
+            // Unsupported packets get an empty reply
+            _ => String::new(),
+        };
+        self.send(&reply);
+        None
+    }
+
+    fn query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!(
+                "PacketSize={:x};qXfer:features:read+",
+                constants::GDB_PACKET_SIZE
+            );
+        }
+        if packet == "qAttached" {
+            return "1".to_string();
+        }
+        // qXfer:features:read:target.xml:offset,length, answered in chunks
+        let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") else {
+            return String::new();
+        };
+        let Some((offset, end)) = range.split_once(',').and_then(|(offset, len)| {
+            let offset = parse_hex(offset)?;
+            Some((offset, offset.checked_add(parse_hex(len)?)?))
+        }) else {
+            return "E01".to_string();
+        };
+        let start = offset.min(TARGET_XML.len());
+        let end = end.min(TARGET_XML.len());
+        let marker = if end == TARGET_XML.len() { "l" } else { "m" };
+        format!("{}{}", marker, &TARGET_XML[start..end])
+    }
+
+    fn write_registers(&self, arguments: &str, chip8: &mut Chip8) -> String {
+        let Some(bytes) = decode_bytes(arguments) else {
+            return "E01".to_string();
+        };
+        let mut bytes = bytes.into_iter();
+        for register in 0..REGISTER_COUNT {
+            let size = register_value(chip8, register).1;
+            let mut value = 0;
+            for byte in 0..size {
+                let Some(next) = bytes.next() else {
+                    return "E01".to_string();
+                };
+                value |= (next as u16) << (byte * 8);
+            }
+            if let Some(operand) = register_operand(register) {
+                if operand.check_value(value).is_ok() {
+                    operand.set(chip8, value);
+                }
+            }
+        }
+        "OK".to_string()
+    }
+
+    fn write_register(&self, arguments: &str, chip8: &mut Chip8) -> String {
+        let Some((register, bytes)) = arguments
+            .split_once('=')
+            .and_then(|(register, value)| Some((parse_hex(register)?, decode_bytes(value)?)))
+        else {
+            return "E01".to_string();
+        };
+        let value = bytes
+            .iter()
+            .rev()
+            .fold(0u32, |value, &byte| value << 8 | byte as u32);
+        match register_operand(register) {
+            Some(operand)
+                if value <= u16::MAX as u32 && operand.check_value(value as u16).is_ok() =>
+            {
+                operand.set(chip8, value as u16);
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&self, arguments: &str, chip8: &mut Chip8) -> String {
+        let Some((range, data)) = arguments.split_once(':') else {
+            return "E01".to_string();
+        };
+        match (parse_range(range), decode_bytes(data)) {
+            (Some((address, len)), Some(bytes)) if bytes.len() == len => {
+                for (offset, byte) in bytes.into_iter().enumerate() {
+                    chip8.poke(address + offset, byte);
+                }
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    // Software (0) and hardware (1) breakpoints behave the same, watchpoints are not supported
+    fn change_breakpoint(&mut self, is_insert: bool, arguments: &str) -> String {
+        let mut fields = arguments.splitn(3, ',');
+        let (Some("0" | "1"), Some(address)) = (fields.next(), fields.next()) else {
+            return String::new();
+        };
+        match parse_hex(address).filter(|&address| address < constants::RAM_LEN) {
+            Some(address) => {
+                if is_insert {
+                    self.breakpoints.insert(address);
+                } else {
+                    self.breakpoints.remove(&address);
+                }
+                "OK".to_string()
+            }
+            None => "E01".to_string(),
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        self.send_raw(format!("${}#{:02x}", data, checksum).as_bytes());
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) {
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+        if let Err(error) = write_all(stream, bytes) {
+            eprintln!("Lost gdb connection: {:?}", error);
+            self.stream = None;
+        }
+    }
+}
+
+impl RemoteDebugger for GdbStub {
+    // The machine runs again once gdb disconnects
+    fn poll_command(&mut self, chip8: &mut Chip8) -> Option<DebuggerCommand> {
+        let stream = self.stream.as_mut()?;
+        match read_available(stream, &mut self.input) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.stream = None;
+                return Some(DebuggerCommand::Continue);
+            }
+            Err(error) => {
+                eprintln!("Lost gdb connection: {:?}", error);
+                self.stream = None;
+                return Some(DebuggerCommand::Continue);
+            }
+        }
+
+        let mut command = None;
+        while let Some(packet) = self.take_packet() {
+            if let Some(packet_command) = self.handle_packet(&packet, chip8) {
+                command = Some(packet_command);
+            }
+        }
+        command
+    }
+
+    fn check_stop(&mut self, chip8: &Chip8) -> bool {
+        self.breakpoints.contains(&chip8.program_counter())
+    }
+
+    fn report_stop(&mut self) {
+        if self.is_waiting {
+            self.is_waiting = false;
+            self.send(STOP_REPLY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip_8::{Platform, Quirks};
+    use crate::clock::VirtualClock;
+    use crate::rng::Pcg32;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    fn build() -> Chip8 {
+        // V0 = 1, V1 = 2, then loop
+        Chip8::build(
+            &[0x60, 0x01, 0x61, 0x02, 0x12, 0x04],
+            1_000,
+            false,
+            Quirks::new(Platform::Chip8),
+            Box::new(Pcg32::new(0)),
+            Box::new(VirtualClock::new()),
+        )
+    }
+
+    // A stub talking to a client over loopback, as `listen` would after gdb connects
+    fn connect() -> (GdbStub, TcpStream) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.set_nonblocking(true).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let stub = GdbStub {
+            stream: Some(stream),
+            input: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            is_waiting: false,
+        };
+        (stub, client)
+    }
+
+    fn checksum(data: &str) -> u8 {
+        data.bytes().fold(0, |sum, byte| sum.wrapping_add(byte))
+    }
+
+    // Sends a packet and returns the data of the acknowledged reply, polling until it arrives
+    fn request(
+        stub: &mut GdbStub,
+        client: &mut TcpStream,
+        chip8: &mut Chip8,
+        packet: &str,
+    ) -> String {
+        write!(client, "${}#{:02x}", packet, checksum(packet)).unwrap();
+        let mut reply = Vec::new();
+        while reply.len() < 3 || reply[reply.len() - 3] != b'#' {
+            assert!(stub.poll_command(chip8).is_none(), "{}", packet);
+            assert!(read_available(client, &mut reply).unwrap(), "{}", packet);
+        }
+        let reply = String::from_utf8(reply).unwrap();
+        let (data, sum) = reply
+            .strip_prefix("+$")
+            .and_then(|reply| reply.split_once('#'))
+            .unwrap();
+        assert_eq!(sum, format!("{:02x}", checksum(data)), "{}", packet);
+        data.to_string()
+    }
+
+    #[test]
+    fn memory_is_written_and_read_back() {
+        let (mut stub, mut client) = connect();
+        let mut chip8 = build();
+        for (packet, reply) in [
+            ("M300,3:abcdef", "OK"),
+            ("m2ff,5", "00abcdef00"),
+            ("m200,2", "6001"),
+            // Past the end of memory, or with fewer bytes than announced
+            ("mffe,4", "E01"),
+            ("Mffe,4:00000000", "E01"),
+            ("M300,2:ab", "E01"),
+        ] {
+            assert_eq!(
+                request(&mut stub, &mut client, &mut chip8, packet),
+                reply,
+                "{}",
+                packet
+            );
+        }
+        assert_eq!(chip8.ram()[0x300..0x303], [0xAB, 0xCD, 0xEF]);
+    }
+
+    #[test]
+    fn registers_are_little_endian_and_checked() {
+        let (mut stub, mut client) = connect();
+        let mut chip8 = build();
+        for (packet, reply) in [
+            ("p11", "0002"),
+            ("P11=0602", "OK"),
+            ("p11", "0602"),
+            ("P3=7f", "OK"),
+            ("p3", "7f"),
+            // Past the end of memory, the read-only stack pointer and an unknown register
+            ("P11=ff0f", "E01"),
+            ("P12=01", "E01"),
+            ("p15", "E01"),
+        ] {
+            assert_eq!(
+                request(&mut stub, &mut client, &mut chip8, packet),
+                reply,
+                "{}",
+                packet
+            );
+        }
+        assert_eq!(chip8.program_counter(), 0x206);
+        assert_eq!(chip8.registers()[3], 0x7F);
+    }
+
+    #[test]
+    fn breakpoints_stop_at_their_address() {
+        let (mut stub, mut client) = connect();
+        let mut chip8 = build();
+        assert_eq!(
+            request(&mut stub, &mut client, &mut chip8, "Z0,204,2"),
+            "OK"
+        );
+        // Watchpoints are not supported
+        assert_eq!(request(&mut stub, &mut client, &mut chip8, "Z2,300,1"), "");
+        assert_eq!(
+            request(&mut stub, &mut client, &mut chip8, "Z0,1000,2"),
+            "E01"
+        );
+
+        chip8.cycle();
+        assert!(!stub.check_stop(&chip8));
+        chip8.cycle();
+        assert!(stub.check_stop(&chip8));
+
+        assert_eq!(
+            request(&mut stub, &mut client, &mut chip8, "z0,204,2"),
+            "OK"
+        );
+        assert!(!stub.check_stop(&chip8));
+    }
+
+    #[test]
+    fn target_description_is_read_in_chunks() {
+        let (mut stub, mut client) = connect();
+        let mut chip8 = build();
+        let mut target_xml = String::new();
+        loop {
+            let packet = format!("qXfer:features:read:target.xml:{:x},100", target_xml.len());
+            let reply = request(&mut stub, &mut client, &mut chip8, &packet);
+            let (marker, chunk) = reply.split_at(1);
+            target_xml.push_str(chunk);
+            if marker == "l" {
+                break;
+            }
+            assert_eq!((marker, chunk.len()), ("m", 0x100));
+        }
+        assert_eq!(target_xml, TARGET_XML);
+        assert_eq!(
+            request(
+                &mut stub,
+                &mut client,
+                &mut chip8,
+                "qXfer:features:read:target.xml:x"
+            ),
+            "E01"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use clap::ValueEnum;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::chip_8::Chip8;
+use crate::instruction::ParsedInstruction;
+
+// What happens once a ROM halts, a message is printed in any case
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HaltAction {
+    Notify,
+    Pause,
+    Exit,
+}
+
+// Whether an instruction does something a loop could be waiting on or showing, so a loop running
+// it is not halted: reading keys or the delay timer, drawing or drawing random numbers
+fn is_activity(instruction: u16) -> bool {
+    let ParsedInstruction { opcode, nn, .. } = ParsedInstruction::build(instruction);
+    matches!(
+        (opcode, nn),
+        (0x0, 0xE0) | (0xC, _) | (0xD, _) | (0xE, _) | (0xF, 0x07 | 0x0A)
+    )
+}
+
+// Everything a loop without activity could change
+fn fingerprint(chip8: &Chip8) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    chip8.registers().hash(&mut hasher);
+    chip8.index_register().hash(&mut hasher);
+    chip8.stack().hash(&mut hasher);
+    chip8.ram().hash(&mut hasher);
+    hasher.finish()
+}
+
+// Detects ROMs halting, usually with a `1NNN` jump to itself: a loop is halted once it jumps back
+// twice in a row from the same address to the same machine state, without any activity in
+// between, as it will then never get anywhere else. Only the innermost loop is watched.
+pub struct HaltDetector {
+    action: HaltAction,
+    // The address of the latest backwards jump and the machine state after it
+    last_jump: Option<(usize, u64)>,
+    has_activity: bool,
+    is_halted: bool,
+}
+
+impl HaltDetector {
+    pub fn new(action: HaltAction) -> Self {
+        HaltDetector {
+            action,
+            last_jump: None,
+            has_activity: false,
+            is_halted: false,
+        }
+    }
+
+    pub fn action(&self) -> HaltAction {
+        self.action
+    }
+
+    // Takes the machine after running the instruction, returns true only when the ROM halts and
+    // not again until it leaves the loop
+    pub fn record(&mut self, chip8: &Chip8, program_counter: usize, instruction: u16) -> bool {
+        self.has_activity |= is_activity(instruction);
+        let ParsedInstruction { opcode, nnn, .. } = ParsedInstruction::build(instruction);
+        if opcode != 0x1 || nnn as usize > program_counter {
+            return false;
+        }
+
+        let jump = (program_counter, fingerprint(chip8));
+        let is_halted = !self.has_activity && self.last_jump == Some(jump);
+        let halts = is_halted && !self.is_halted;
+        self.last_jump = Some(jump);
+        self.has_activity = false;
+        self.is_halted = is_halted;
+        halts
+    }
+}
@@ -0,0 +1,91 @@
+use std::{fs, path::Path};
+
+use crate::chip_8::Chip8;
+use crate::constants;
+use crate::image::{save_png, scale_pixels};
+use crate::renderer::{self, Renderer};
+
+// Stops the run after a number of frames, without presenting any
+struct FrameLimit(u64);
+
+impl Renderer for FrameLimit {
+    fn render_buffer(&mut self, _buffer: &[bool; constants::DISPLAY_LEN]) {}
+
+    fn is_open(&self, frame: u64) -> bool {
+        frame < self.0
+    }
+}
+
+// Runs without a window, audio or input, meant for a core driven by a `VirtualClock`
+pub struct HeadlessFrontend {
+    chip8: Chip8,
+}
+
+impl HeadlessFrontend {
+    pub fn build(chip8: Chip8) -> Self {
+        HeadlessFrontend { chip8 }
+    }
+
+    pub fn into_chip8(self) -> Chip8 {
+        self.chip8
+    }
+
+    pub fn run(&mut self, frames: u64) {
+        renderer::run(&mut self.chip8, &mut FrameLimit(frames));
+    }
+
+    // One line per row with `#` for set and `.` for unset pixels
+    pub fn display_text(&self) -> String {
+        self.chip8
+            .display_buffer()
+            .chunks_exact(constants::DISPLAY_WIDTH)
+            .map(|row| {
+                let mut line: String = row
+                    .iter()
+                    .map(|&is_set| if is_set { '#' } else { '.' })
+                    .collect();
+                line.push('\n');
+                line
+            })
+            .collect()
+    }
+
+    // Writes the display as a PNG if the path ends in .png, as text otherwise, `-` prints the text
+    pub fn dump_display(
+        &self,
+        path: &Path,
+        scale: u32,
+        (background_color, foreground_color): ((u8, u8, u8), (u8, u8, u8)),
+    ) {
+        if path == Path::new("-") {
+            print!("{}", self.display_text());
+            return;
+        }
+
+        if path.extension().is_some_and(|extension| extension == "png") {
+            let pixels: Vec<u8> = self
+                .chip8
+                .display_buffer()
+                .iter()
+                .flat_map(|&is_set| {
+                    let (red, green, blue) = if is_set {
+                        foreground_color
+                    } else {
+                        background_color
+                    };
+                    [red, green, blue]
+                })
+                .collect();
+            save_png(
+                path,
+                constants::DISPLAY_WIDTH as u32 * scale,
+                constants::DISPLAY_HEIGHT as u32 * scale,
+                &scale_pixels(&pixels, (scale, scale)),
+            )
+            .unwrap_or_else(|error| panic!("Failed to write dump: {:?}", error));
+        } else {
+            fs::write(path, self.display_text())
+                .unwrap_or_else(|error| panic!("Failed to write dump: {:?}", error));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::Serialize;
+
+use crate::constants;
+use crate::disassembler::disassemble;
+use crate::instruction::ParsedInstruction;
+
+// Groups instructions by what they do, e.g. every `7XNN` is an add of a value
+fn opcode_pattern(instruction: u16) -> String {
+    let ParsedInstruction { opcode, n, nn, .. } = ParsedInstruction::build(instruction);
+    match opcode {
+        0x0 => format!("{:04X}", instruction),
+        0x1 | 0x2 | 0xA | 0xB => format!("{:X}NNN", opcode),
+        0x3 | 0x4 | 0x6 | 0x7 | 0xC => format!("{:X}XNN", opcode),
+        0x5 | 0x8 | 0x9 => format!("{:X}XY{:X}", opcode, n),
+        0xD => "DXYN".to_string(),
+        _ => format!("{:X}X{:02X}", opcode, nn),
+    }
+}
+
+#[derive(Serialize)]
+struct AddressReport {
+    address: usize,
+    count: u64,
+    instruction: String,
+}
+
+#[derive(Serialize)]
+struct Report {
+    instructions: u64,
+    addresses: Vec<AddressReport>,
+    opcodes: BTreeMap<String, u64>,
+}
+
+// Counts how often every address and kind of instruction is executed
+pub struct HotSpots {
+    address_counts: Vec<u64>,
+    // The instruction last executed at each address, self-modifying code can change it
+    instructions: Vec<u16>,
+    opcode_counts: BTreeMap<String, u64>,
+    total: u64,
+}
+
+impl HotSpots {
+    pub fn new() -> Self {
+        HotSpots {
+            address_counts: vec![0; constants::RAM_LEN],
+            instructions: vec![0; constants::RAM_LEN],
+            opcode_counts: BTreeMap::new(),
+            total: 0,
+        }
+    }
+
+    pub fn record(&mut self, program_counter: usize, instruction: u16) {
+        self.address_counts[program_counter] += 1;
+        self.instructions[program_counter] = instruction;
+        *self
+            .opcode_counts
+            .entry(opcode_pattern(instruction))
+            .or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    // Executed addresses, most executed first
+    fn report(&self) -> Report {
+        let mut addresses: Vec<AddressReport> = self
+            .address_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(address, &count)| AddressReport {
+                address,
+                count,
+                instruction: disassemble(self.instructions[address]),
+            })
+            .collect();
+        addresses.sort_by(|a, b| b.count.cmp(&a.count).then(a.address.cmp(&b.address)));
+        Report {
+            instructions: self.total,
+            addresses,
+            opcodes: self.opcode_counts.clone(),
+        }
+    }
+
+    fn share(&self, count: u64) -> f64 {
+        count as f64 * 100.0 / self.total.max(1) as f64
+    }
+
+    // The hottest addresses and every kind of instruction executed, as a table
+    pub fn text_report(&self) -> String {
+        let report = self.report();
+        let mut text = format!("Hot spots over {} instructions\n", report.instructions);
+        writeln!(text, "Address  Count         Share  Instruction").unwrap();
+        for address in report.addresses.iter().take(constants::HOT_SPOT_COUNT) {
+            writeln!(
+                text,
+                "{:#05X}    {:<12} {:>5.1}%  {}",
+                address.address,
+                address.count,
+                self.share(address.count),
+                address.instruction
+            )
+            .unwrap();
+        }
+
+        let mut opcodes: Vec<(&String, &u64)> = report.opcodes.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1));
+        writeln!(text, "\nOpcode   Count         Share").unwrap();
+        for (pattern, &count) in opcodes {
+            writeln!(
+                text,
+                "{:<8} {:<12} {:>5.1}%",
+                pattern,
+                count,
+                self.share(count)
+            )
+            .unwrap();
+        }
+        text
+    }
+
+    // Every executed address rather than only the hottest ones
+    pub fn json_report(&self) -> String {
+        serde_json::to_string_pretty(&self.report()).unwrap()
+    }
+}
+
+impl Default for HotSpots {
+    fn default() -> Self {
+        Self::new()
+    }
+}
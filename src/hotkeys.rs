@@ -0,0 +1,162 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Quit,
+    Pause,
+    FrameAdvance,
+    SlowMotion,
+    TakeOverReplay,
+    Reset,
+    SaveState,
+    LoadState,
+    SpeedDown,
+    SpeedUp,
+    // Runs faster while held
+    FastForward,
+    VolumeDown,
+    VolumeUp,
+    Mute,
+    Screenshot,
+    Record,
+    Fullscreen,
+    Stats,
+    Keypad,
+    Theme,
+    // Asks for a key for each keypad key in turn and saves them as the keymap
+    BindKeys,
+    // Runs the next instruction in debug mode
+    Step,
+}
+
+const DEFAULT_BINDINGS: [(Action, &str); 23] = [
+    (Action::Quit, "Escape"),
+    (Action::Pause, "F6"),
+    (Action::FrameAdvance, "F7"),
+    (Action::SlowMotion, "F8"),
+    (Action::TakeOverReplay, "F9"),
+    (Action::Reset, "Backspace"),
+    (Action::SaveState, "F1"),
+    (Action::LoadState, "F2"),
+    (Action::SpeedDown, "PageDown"),
+    (Action::SpeedUp, "PageUp"),
+    (Action::FastForward, "Tab"),
+    (Action::VolumeDown, "-"),
+    (Action::VolumeUp, "="),
+    (Action::Mute, "M"),
+    (Action::Screenshot, "F12"),
+    (Action::Record, "F10"),
+    (Action::Fullscreen, "F11"),
+    (Action::Fullscreen, "Alt+Return"),
+    (Action::Stats, "F3"),
+    (Action::Keypad, "F4"),
+    (Action::Theme, "F5"),
+    (Action::BindKeys, "Insert"),
+    (Action::Step, "Return"),
+];
+
+// An SDL key name with the modifiers that have to be held, e.g. `Ctrl+Shift+S`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotkey {
+    pub key: String,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+pub fn parse_hotkey(value: &str) -> Result<Hotkey, String> {
+    let mut hotkey = Hotkey {
+        key: String::new(),
+        ctrl: false,
+        alt: false,
+        shift: false,
+    };
+    // Only known prefixes are modifiers, so keys like `Keypad +` keep their plus
+    let mut rest = value.trim();
+    loop {
+        if let Some(key) = rest.strip_prefix("Ctrl+") {
+            hotkey.ctrl = true;
+            rest = key;
+        } else if let Some(key) = rest.strip_prefix("Alt+") {
+            hotkey.alt = true;
+            rest = key;
+        } else if let Some(key) = rest.strip_prefix("Shift+") {
+            hotkey.shift = true;
+            rest = key;
+        } else {
+            break;
+        }
+    }
+
+    if rest.is_empty() {
+        return Err(format!("Invalid hotkey, expected a key name: {}", value));
+    }
+    hotkey.key = rest.to_string();
+    Ok(hotkey)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyNames {
+    One(String),
+    Many(Vec<String>),
+}
+
+// Keys triggering frontend actions instead of pressing CHIP-8 keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotkeys {
+    bindings: Vec<(Hotkey, Action)>,
+}
+
+impl Hotkeys {
+    pub fn new() -> Self {
+        Hotkeys {
+            bindings: DEFAULT_BINDINGS
+                .iter()
+                .map(|&(action, name)| (parse_hotkey(name).unwrap(), action))
+                .collect(),
+        }
+    }
+
+    // Reads a TOML file binding actions to keys, e.g. `pause = "P"` or
+    // `fullscreen = ["F11", "Alt+Return"]`. Actions left out keep their default keys and an
+    // empty list unbinds one.
+    pub fn load(path: &Path) -> Self {
+        let contents = fs::read_to_string(path)
+            .unwrap_or_else(|error| panic!("Failed to read hotkeys: {:?}", error));
+        let overrides: HashMap<Action, KeyNames> = toml::from_str(&contents)
+            .unwrap_or_else(|error| panic!("Failed to parse hotkeys: {}", error));
+
+        let mut hotkeys = Self::new();
+        hotkeys
+            .bindings
+            .retain(|(_, action)| !overrides.contains_key(action));
+        for (action, names) in overrides {
+            let names = match names {
+                KeyNames::One(name) => vec![name],
+                KeyNames::Many(names) => names,
+            };
+            for name in names {
+                let hotkey = parse_hotkey(&name)
+                    .unwrap_or_else(|error| panic!("Failed to parse hotkeys: {}", error));
+                hotkeys.bindings.push((hotkey, action));
+            }
+        }
+        hotkeys
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = (&Hotkey, Action)> {
+        self.bindings
+            .iter()
+            .map(|(hotkey, action)| (hotkey, *action))
+    }
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
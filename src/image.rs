@@ -0,0 +1,67 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use crate::constants;
+
+// Decodes a PNG into RGBA32 pixels, returning its width and height alongside
+pub fn load_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let file = File::open(path).unwrap_or_else(|error| panic!("Failed to open image: {:?}", error));
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .unwrap_or_else(|error| panic!("Failed to decode image: {:?}", error));
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .unwrap_or_else(|error| panic!("Failed to decode image: {:?}", error));
+    buffer.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xFF])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        png::ColorType::Grayscale => buffer
+            .iter()
+            .flat_map(|&value| [value, value, value, 0xFF])
+            .collect(),
+        png::ColorType::Indexed => unreachable!("Palette images are expanded while decoding"),
+    };
+    (info.width, info.height, rgba)
+}
+
+// Repeats each pixel of an unscaled RGB24 display frame to the given pixel size
+pub fn scale_pixels(pixels: &[u8], (scale_x, scale_y): (u32, u32)) -> Vec<u8> {
+    let (scale_x, scale_y) = (scale_x as usize, scale_y as usize);
+    let (width, height) = (
+        constants::DISPLAY_WIDTH * scale_x,
+        constants::DISPLAY_HEIGHT * scale_y,
+    );
+    let mut scaled = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y / scale_y) * constants::DISPLAY_WIDTH + x / scale_x;
+            scaled.extend_from_slice(&pixels[index * 3..index * 3 + 3]);
+        }
+    }
+    scaled
+}
+
+// Encodes RGB24 pixels as a PNG
+pub fn save_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgb: &[u8],
+) -> Result<(), png::EncodingError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(rgb)
+}
@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+use crate::constants;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEventKind {
+    Press,
+    Release,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub key: u8,
+    pub kind: InputEventKind,
+    pub frame: u64,
+}
+
+pub struct InputState {
+    pressed_keys: u16,
+    events: VecDeque<InputEvent>,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        InputState {
+            pressed_keys: 0,
+            events: VecDeque::with_capacity(constants::KEY_COUNT),
+        }
+    }
+
+    pub fn pressed_keys(&self) -> u16 {
+        self.pressed_keys
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        (key as usize) < constants::KEY_COUNT && self.pressed_keys & (1 << key) != 0
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = &InputEvent> {
+        self.events.iter()
+    }
+
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn set_pressed_keys(&mut self, pressed_keys: u16, frame: u64) {
+        let changed_keys = self.pressed_keys ^ pressed_keys;
+        for key in 0..constants::KEY_COUNT as u8 {
+            if changed_keys & (1 << key) == 0 {
+                continue;
+            }
+
+            let kind = if pressed_keys & (1 << key) != 0 {
+                InputEventKind::Press
+            } else {
+                InputEventKind::Release
+            };
+            self.events.push_back(InputEvent { key, kind, frame });
+        }
+        self.pressed_keys = pressed_keys;
+    }
+}
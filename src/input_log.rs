@@ -0,0 +1,140 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+const HEADER: &str = "c8in 2";
+// Logs without timer ticks, replayed with the timers running on the clock
+const HEADER_WITHOUT_TICKS: &str = "c8in 1";
+
+// Writes every keypad state change as `<instruction> <frame> <keys>`, with the keys as a hex
+// bit mask, and every timer tick as `<instruction> <frame> tick`, after a header holding the RNG
+// seed so a replay draws the same random numbers. Both take effect before the given instruction
+// is executed, in the order they are written.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path, seed: u64) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+        writeln!(writer, "seed {}", seed)?;
+        Ok(InputRecorder { writer })
+    }
+
+    pub fn record(&mut self, instruction: u64, frame: u64, pressed_keys: u16) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{} {} {:04X}",
+            instruction, frame, pressed_keys
+        )
+    }
+
+    // The frame is the one the tick starts
+    pub fn record_tick(&mut self, instruction: u64, frame: u64) -> io::Result<()> {
+        writeln!(self.writer, "{} {} tick", instruction, frame)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputEvent {
+    Keys(u16),
+    Tick,
+}
+
+fn parse_event(line: &str) -> Option<(u64, InputEvent)> {
+    let mut fields = line.split_whitespace();
+    let instruction = fields.next()?.parse().ok()?;
+    // The frame is informational, replays go by instruction
+    fields.next()?.parse::<u64>().ok()?;
+    let event = match fields.next()? {
+        "tick" => InputEvent::Tick,
+        keys => InputEvent::Keys(u16::from_str_radix(keys, 16).ok()?),
+    };
+    fields.next().is_none().then_some((instruction, event))
+}
+
+// Replays a log written by `InputRecorder`. Timer ticks are replayed at the instructions they
+// were recorded at, so a replay runs the same whatever the clock, until the recorded ticks run
+// out and the timers follow the clock again.
+#[derive(Clone)]
+pub struct InputPlayer {
+    seed: u64,
+    events: VecDeque<(u64, InputEvent)>,
+    ticks: usize,
+}
+
+impl InputPlayer {
+    pub fn load(path: &Path) -> Self {
+        let contents = fs::read_to_string(path)
+            .unwrap_or_else(|error| panic!("Failed to read input log: {:?}", error));
+        let mut lines = contents.lines();
+
+        if !matches!(lines.next(), Some(HEADER | HEADER_WITHOUT_TICKS)) {
+            panic!("Not an input log: {:?}", path);
+        }
+        let seed = lines
+            .next()
+            .and_then(|line| line.strip_prefix("seed "))
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(|| panic!("Input log without a seed: {:?}", path));
+        let events: VecDeque<(u64, InputEvent)> = lines
+            .map(|line| {
+                parse_event(line).unwrap_or_else(|| panic!("Invalid line in input log: {:?}", line))
+            })
+            .collect();
+        let ticks = events
+            .iter()
+            .filter(|(_, event)| *event == InputEvent::Tick)
+            .count();
+
+        InputPlayer {
+            seed,
+            events,
+            ticks,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Whether the timers tick as recorded rather than on the clock
+    pub fn replays_ticks(&self) -> bool {
+        self.ticks > 0
+    }
+
+    // The keys recorded to take effect before the given instruction, if they changed. Keys
+    // recorded after a timer tick wait for it.
+    pub fn take_keys(&mut self, instruction: u64) -> Option<u16> {
+        let mut pressed_keys = None;
+        while let Some(&(event_instruction, InputEvent::Keys(keys))) = self.events.front() {
+            if event_instruction > instruction {
+                break;
+            }
+            pressed_keys = Some(keys);
+            self.events.pop_front();
+        }
+        pressed_keys
+    }
+
+    // Whether a tick was recorded before the given instruction, keys recorded before the tick
+    // are taken first
+    pub fn is_tick_due(&self, instruction: u64) -> bool {
+        self.events
+            .iter()
+            .take_while(|&&(event_instruction, _)| event_instruction <= instruction)
+            .any(|&(_, event)| event == InputEvent::Tick)
+    }
+
+    // Removes the next tick, after the keys before it were taken with `take_keys`
+    pub fn take_tick(&mut self) {
+        if let Some((_, InputEvent::Tick)) = self.events.front() {
+            self.events.pop_front();
+            self.ticks -= 1;
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread, time,
+};
+
+use serde_json::{json, Value};
+
+use crate::chip_8::Chip8;
+use crate::condition::parse_number;
+use crate::constants;
+
+// What requests are answered from, copied from the machine once per frame
+struct Snapshot {
+    registers: [u8; constants::REGISTER_COUNT],
+    index_register: u16,
+    program_counter: usize,
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    frame: u64,
+    instructions: u64,
+    ram: Vec<u8>,
+    display: Vec<bool>,
+}
+
+impl Snapshot {
+    fn new(chip8: &Chip8) -> Self {
+        Snapshot {
+            registers: *chip8.registers(),
+            index_register: chip8.index_register(),
+            program_counter: chip8.program_counter(),
+            stack: chip8.stack().to_vec(),
+            delay_timer: chip8.delay_timer(),
+            sound_timer: chip8.sound_timer(),
+            frame: chip8.frame(),
+            instructions: chip8.instruction_count(),
+            ram: chip8.ram().to_vec(),
+            display: chip8.display_buffer().to_vec(),
+        }
+    }
+
+    fn registers(&self) -> Value {
+        json!({
+            "v": self.registers,
+            "i": self.index_register,
+            "pc": self.program_counter,
+            "stack": self.stack,
+            "dt": self.delay_timer,
+            "st": self.sound_timer,
+            "frame": self.frame,
+            "instructions": self.instructions,
+        })
+    }
+
+    // `start` and `length` default to all of memory
+    fn memory(&self, query: &str) -> Result<Value, String> {
+        let mut start = 0;
+        let mut length = constants::RAM_LEN;
+        for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+            let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+            let number = parse_number(value)
+                .map(|number| number as usize)
+                .ok_or_else(|| format!("Invalid {}: {}", name, value))?;
+            match name {
+                "start" => start = number,
+                "length" => length = number,
+                _ => return Err(format!("Unknown parameter: {}", name)),
+            }
+        }
+        if start + length > constants::RAM_LEN {
+            return Err(format!(
+                "Range outside of memory: {:#05X} + {}",
+                start, length
+            ));
+        }
+        Ok(json!({ "start": start, "bytes": self.ram[start..start + length] }))
+    }
+
+    fn framebuffer(&self) -> Value {
+        json!({
+            "width": constants::DISPLAY_WIDTH,
+            "height": constants::DISPLAY_HEIGHT,
+            "rows": display_rows(&self.display),
+        })
+    }
+}
+
+// A string per row with `#` for set and `.` for unset pixels, like `--dump`
+pub fn display_rows(display: &[bool]) -> Vec<String> {
+    display
+        .chunks_exact(constants::DISPLAY_WIDTH)
+        .map(|row| {
+            row.iter()
+                .map(|&is_set| if is_set { '#' } else { '.' })
+                .collect()
+        })
+        .collect()
+}
+
+// Answers a request line such as `GET /memory?start=0x200&length=16 HTTP/1.1` with a status
+// and a JSON body
+fn respond(snapshot: Option<&Snapshot>, request_line: &str) -> (&'static str, Value) {
+    let mut words = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (words.next(), words.next()) else {
+        return ("400 Bad Request", json!({ "error": "Invalid request" }));
+    };
+    if method != "GET" {
+        return (
+            "405 Method Not Allowed",
+            json!({ "error": "Only GET is supported" }),
+        );
+    }
+    let Some(snapshot) = snapshot else {
+        return (
+            "503 Service Unavailable",
+            json!({ "error": "The machine has not started yet" }),
+        );
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    match path {
+        "/registers" => ("200 OK", snapshot.registers()),
+        "/memory" => match snapshot.memory(query) {
+            Ok(memory) => ("200 OK", memory),
+            Err(error) => ("400 Bad Request", json!({ "error": error })),
+        },
+        "/framebuffer" => ("200 OK", snapshot.framebuffer()),
+        _ => (
+            "404 Not Found",
+            json!({ "error": "Unknown path, try /registers, /memory or /framebuffer" }),
+        ),
+    }
+}
+
+fn handle(stream: TcpStream, snapshot: &Mutex<Option<Snapshot>>) -> io::Result<()> {
+    stream.set_read_timeout(Some(time::Duration::from_secs(
+        constants::INSPECT_TIMEOUT_SECONDS,
+    )))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers are not needed, but are read so the client sees the whole request consumed
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+
+    let (status, body) = respond(snapshot.lock().unwrap().as_ref(), &request_line);
+    let body = body.to_string();
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// Read-only JSON over HTTP for dashboards and scripts watching a running machine. Requests are
+// answered on a thread of their own from the state at the latest frame, so they never slow the
+// machine down and are answered even while it is paused.
+pub struct InspectServer {
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+}
+
+impl InspectServer {
+    pub fn start(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("Serving the machine state on http://127.0.0.1:{}", port);
+        let snapshot = Arc::new(Mutex::new(None));
+        let served_snapshot = Arc::clone(&snapshot);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(error) = handle(stream, &served_snapshot) {
+                    eprintln!("Failed to answer inspection request: {:?}", error);
+                }
+            }
+        });
+        Ok(InspectServer { snapshot })
+    }
+
+    pub fn update(&self, chip8: &Chip8) {
+        *self.snapshot.lock().unwrap() = Some(Snapshot::new(chip8));
+    }
+}
@@ -0,0 +1,29 @@
+/// A 16-bit CHIP-8 instruction split into the fields its opcodes are encoded with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParsedInstruction {
+    /// The first nibble, selecting the instruction group (0x0-0xF)
+    pub opcode: u8,
+    /// The second nibble, usually the first register operand
+    pub x: u8,
+    /// The third nibble, usually the second register operand
+    pub y: u8,
+    /// The fourth nibble
+    pub n: u8,
+    /// The second byte
+    pub nn: u8,
+    /// The lowest 12 bits, usually an address
+    pub nnn: u16,
+}
+
+impl ParsedInstruction {
+    pub fn build(instruction: u16) -> ParsedInstruction {
+        ParsedInstruction {
+            opcode: ((instruction & 0xF000) >> 12) as u8,
+            x: ((instruction & 0x0F00) >> 8) as u8,
+            y: ((instruction & 0x00F0) >> 4) as u8,
+            n: (instruction & 0x000F) as u8,
+            nn: (instruction & 0x00FF) as u8,
+            nnn: instruction & 0x0FFF,
+        }
+    }
+}
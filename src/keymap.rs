@@ -0,0 +1,109 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use clap::ValueEnum;
+
+use crate::constants;
+
+// Built-in keypad bindings. Bindings follow key positions, so the letter layouts all use the
+// 4x4 block under 1234, the names just spare users from wondering whether theirs is covered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Layout {
+    Qwerty,
+    Azerty,
+    Colemak,
+    // The digits on their own keys and A-F around them, for keyboards with a number pad
+    Numpad,
+}
+
+impl Layout {
+    pub fn bindings(self) -> &'static [(&'static str, u8); constants::KEY_COUNT] {
+        match self {
+            Layout::Qwerty | Layout::Azerty | Layout::Colemak => &constants::DEFAULT_KEYMAP,
+            Layout::Numpad => &constants::NUMPAD_KEYMAP,
+        }
+    }
+}
+
+// Keypad bindings by SDL scancode name, so they follow key positions rather than labels, or by
+// SDL game controller button name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<String, u8>,
+}
+
+impl Keymap {
+    // The usual QWERTY layout, with 1234/QWER/ASDF/ZXCV mirroring the keypad
+    pub fn new() -> Self {
+        Self::from_layout(Layout::Qwerty)
+    }
+
+    pub fn from_layout(layout: Layout) -> Self {
+        Keymap {
+            bindings: layout
+                .bindings()
+                .iter()
+                .map(|&(name, key)| (name.to_string(), key))
+                .collect(),
+        }
+    }
+
+    // The d-pad moves like WASD on the keyboard, A and B press E and Q
+    pub fn controller() -> Self {
+        Keymap {
+            bindings: constants::DEFAULT_CONTROLLER_MAP
+                .iter()
+                .map(|&(name, key)| (name.to_string(), key))
+                .collect(),
+        }
+    }
+
+    // Reads a TOML file mapping names to keys, e.g. `X = 0x0`, `"Keypad 7" = 0x1` or `dpup = 0x5`.
+    // Keys left out stay unbound and several scancodes may share a key.
+    pub fn load(path: &Path) -> Self {
+        let contents = fs::read_to_string(path)
+            .unwrap_or_else(|error| panic!("Failed to read keymap: {:?}", error));
+        let bindings: HashMap<String, u8> = toml::from_str(&contents)
+            .unwrap_or_else(|error| panic!("Failed to parse keymap: {}", error));
+
+        if let Some((name, key)) = bindings
+            .iter()
+            .find(|(_, &key)| key as usize >= constants::KEY_COUNT)
+        {
+            panic!(
+                "Invalid key in keymap, expected 0x0 to 0xF: {} = {}",
+                name, key
+            );
+        }
+        Keymap { bindings }
+    }
+
+    pub fn from_bindings(bindings: &[(String, u8)]) -> Self {
+        Keymap {
+            bindings: bindings.iter().cloned().collect(),
+        }
+    }
+
+    // Writes the bindings in the format read by `load`, sorted by key
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut bindings: Vec<(&str, u8)> = self.bindings().collect();
+        bindings.sort_by_key(|&(name, key)| (key, name));
+        // Debug formatting quotes and escapes names the same way TOML does
+        let contents: String = bindings
+            .into_iter()
+            .map(|(name, key)| format!("{:?} = 0x{:X}\n", name, key))
+            .collect();
+        fs::write(path, contents)
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = (&str, u8)> {
+        self.bindings
+            .iter()
+            .map(|(name, &key)| (name.as_str(), key))
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
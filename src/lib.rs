@@ -0,0 +1,67 @@
+pub mod access;
+pub mod analysis;
+pub mod assembler;
+pub mod audio_options;
+pub mod audio_sink;
+#[cfg(feature = "sdl")]
+pub mod beep;
+pub mod bisect;
+pub mod chip_8;
+pub mod clock;
+pub mod color;
+pub mod condition;
+pub mod constants;
+pub mod coverage;
+#[cfg(feature = "cpal")]
+pub mod cpal_beep;
+pub mod crash_dump;
+#[cfg(feature = "sdl")]
+pub mod dap_server;
+#[cfg(feature = "sdl")]
+pub mod debugger;
+pub mod disassembler;
+#[cfg(feature = "sdl")]
+pub mod display;
+pub mod display_options;
+#[cfg(feature = "sdl")]
+pub mod gdb_stub;
+pub mod halt;
+pub mod headless_frontend;
+pub mod hot_spots;
+pub mod hotkeys;
+pub mod image;
+pub mod input;
+pub mod input_log;
+pub mod inspect_server;
+pub mod instruction;
+pub mod keymap;
+#[cfg(feature = "minifb")]
+pub mod minifb_frontend;
+#[cfg(feature = "sdl")]
+pub mod overlay;
+#[cfg(feature = "pixels")]
+pub mod pixels_frontend;
+pub mod profile;
+pub mod recorder;
+#[cfg(feature = "sdl")]
+pub mod remote_debugger;
+pub mod renderer;
+pub mod rng;
+pub mod rom_config;
+pub mod run_stats;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "sdl")]
+pub mod sdl_frontend;
+pub mod source_map;
+pub mod symbols;
+pub mod terminal_frontend;
+pub mod tone;
+pub mod touch;
+pub mod trace;
+pub mod trace_diff;
+pub mod version;
+pub mod video_recorder;
+pub mod wav;
+#[cfg(feature = "sdl")]
+pub mod websocket_server;
@@ -1,18 +1,112 @@
-mod beep;
-mod chip_8;
-mod constants;
-mod display;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{
+    ops::RangeInclusive,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    time,
+};
 
-use clap::Parser;
-
-use chip_8::{Chip8, Platform, Quirks};
+use chip_8_interpreter::analysis::Analysis;
+use chip_8_interpreter::assembler::assemble_with_debug_info;
+#[cfg(feature = "sdl")]
+use chip_8_interpreter::audio_options::AudioBackend;
+#[cfg(any(feature = "sdl", feature = "cpal"))]
+use chip_8_interpreter::audio_options::AudioOptions;
+use chip_8_interpreter::audio_sink::AudioSink;
+use chip_8_interpreter::bisect::bisect;
+use chip_8_interpreter::chip_8::{Chip8, Platform, Quirks};
+use chip_8_interpreter::clock::{parse_seconds, Clock, SystemClock, VirtualClock};
+use chip_8_interpreter::color::{load_palette, parse_color, Theme};
+#[cfg(feature = "sdl")]
+use chip_8_interpreter::condition::parse_address;
+use chip_8_interpreter::constants;
+use chip_8_interpreter::coverage::Coverage;
+#[cfg(feature = "cpal")]
+use chip_8_interpreter::cpal_beep::CpalBeep;
+use chip_8_interpreter::crash_dump::{install_panic_hook, write_crash_dump};
+use chip_8_interpreter::disassembler::disassemble_rom;
+use chip_8_interpreter::display_options::{
+    parse_rect, DisplayOptions, Filter, Mirror, Rotation, SoundFlash,
+};
+use chip_8_interpreter::halt::HaltAction;
+use chip_8_interpreter::headless_frontend::HeadlessFrontend;
+#[cfg(feature = "sdl")]
+use chip_8_interpreter::hotkeys::Hotkeys;
+use chip_8_interpreter::input_log::{InputPlayer, InputRecorder};
+use chip_8_interpreter::inspect_server::InspectServer;
+#[cfg(feature = "sdl")]
+use chip_8_interpreter::keymap::Keymap;
+use chip_8_interpreter::keymap::Layout;
+#[cfg(feature = "minifb")]
+use chip_8_interpreter::minifb_frontend::MinifbFrontend;
+#[cfg(feature = "pixels")]
+use chip_8_interpreter::pixels_frontend::PixelsFrontend;
+use chip_8_interpreter::profile::Profile;
+use chip_8_interpreter::rng::Pcg32;
+use chip_8_interpreter::rom_config::{load_rom_config, DisplayOverrides};
+use chip_8_interpreter::run_stats::RunStats;
+#[cfg(feature = "scripting")]
+use chip_8_interpreter::script::Script;
+#[cfg(feature = "sdl")]
+use chip_8_interpreter::sdl_frontend::{FrontendOptions, SdlFrontend};
+use chip_8_interpreter::source_map::source_map_path;
+#[cfg(feature = "sdl")]
+use chip_8_interpreter::source_map::SourceMap;
+use chip_8_interpreter::symbols::{symbols_path, Symbols};
+use chip_8_interpreter::terminal_frontend::{TerminalBell, TerminalFrontend, TerminalMode};
+use chip_8_interpreter::tone::{parse_duty_cycle, ToneOptions, Waveform};
+#[cfg(feature = "sdl")]
+use chip_8_interpreter::touch::TouchControls;
+use chip_8_interpreter::trace::{parse_range, Tracer};
+use chip_8_interpreter::trace_diff::{diff_traces, Trace};
+use chip_8_interpreter::version::verbose_version;
 
 /// A CHIP-8 interpreter written in Rust
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, disable_version_flag = true)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+// Window options are accepted but unused when built without any window backend
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the ROM file to load
-    rom_file: String,
+    #[arg(required_unless_present = "version")]
+    rom_file: Option<String>,
+
+    /// Print version
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// With --version, also print build and runtime details for bug reports
+    #[arg(long, requires = "version")]
+    verbose: bool,
+
+    /// Where to display the game, minifb and pixels only support scale and colors, the terminal neither
+    #[clap(value_enum, long, default_value_t = DEFAULT_FRONTEND)]
+    frontend: Frontend,
+
+    /// How the terminal frontend draws pixels, braille fits the display in 32x8 characters
+    #[clap(value_enum, long, default_value_t = TerminalMode::HalfBlocks)]
+    terminal_mode: TerminalMode,
+
+    /// Play the beep through cpal in the terminal frontend instead of ringing the terminal bell
+    #[cfg(feature = "cpal")]
+    #[arg(long, default_value_t = false)]
+    terminal_tone: bool,
+
+    /// Run without window, audio or input as fast as possible, e.g. for CI
+    #[arg(long, default_value_t = false, conflicts_with = "frontend")]
+    headless: bool,
+
+    /// Number of frames to run in headless mode
+    #[arg(long, default_value_t = 600, requires = "headless")]
+    frames: u64,
+
+    /// Write the final display in headless mode as PNG (.png) or text (any other path, - for stdout)
+    #[arg(long, requires = "headless")]
+    dump: Option<PathBuf>,
 
     /// Platform to emulate
     #[clap(value_enum, short, long, default_value_t = Platform::Chip8)]
@@ -22,32 +116,838 @@ struct Args {
     #[arg(short, long, default_value_t = 140_000)]
     instruction_time: u128,
 
-    /// The display scale
+    /// The display scale, in desktop pixels per emulated pixel regardless of DPI
     #[arg(short, long, default_value_t = 10)]
     scale: u32,
 
-    /// Debug mode (displays registers and waits each cycle)
+    /// Width-to-height ratio of each emulated pixel (e.g. 1.2 approximates a CRT TV)
+    #[arg(long, default_value_t = 1.0)]
+    pixel_aspect: f32,
+
+    /// Color theme providing the default colors, classic unless the lcd filter is used
+    #[clap(value_enum, long)]
+    theme: Option<Theme>,
+
+    /// Palette file (GIMP .gpl or one color per line, background first), overrides the theme
+    #[arg(long)]
+    palette: Option<String>,
+
+    /// Foreground color as a hex code (e.g. #33FF66) or CSS color name, overrides the palette
+    #[arg(long, value_parser = parse_color)]
+    fg: Option<(u8, u8, u8)>,
+
+    /// Background color as a hex code (e.g. #000000) or CSS color name, overrides the palette
+    #[arg(long, value_parser = parse_color)]
+    bg: Option<(u8, u8, u8)>,
+
+    /// Start in desktop fullscreen (toggle with F11 or Alt+Enter)
+    #[arg(short, long, default_value_t = false)]
+    fullscreen: bool,
+
+    /// Present frames in sync with the monitor refresh rate
+    #[arg(long, default_value_t = false)]
+    vsync: bool,
+
+    /// Timing profile trading power usage for accuracy (battery also enables vsync)
+    #[clap(value_enum, long, default_value_t = Profile::Balanced)]
+    profile: Profile,
+
+    /// Post-processing filter applied when rendering
+    #[clap(value_enum, long, default_value_t = Filter::None)]
+    filter: Filter,
+
+    /// Rotate the display clockwise by the given degrees
+    #[clap(value_enum, long, default_value_t = Rotation::Rotate0)]
+    rotate: Rotation,
+
+    /// Mirror the display horizontally or vertically
+    #[clap(value_enum, long)]
+    mirror: Option<Mirror>,
+
+    /// Draw thin lines between pixels at scales of 4 and above
+    #[arg(long, default_value_t = false)]
+    grid: bool,
+
+    /// Fade pixels out over the given number of frames to reduce flicker
+    #[arg(long, default_value_t = 0)]
+    phosphor_frames: u8,
+
+    /// Average each frame with the previous one to reduce flicker
+    #[arg(long, default_value_t = false)]
+    frame_blend: bool,
+
+    /// Flash the game area while a sound plays, for playing without audio
+    #[clap(value_enum, long)]
+    sound_flash: Option<SoundFlash>,
+
+    /// PNG image drawn as a border around the game area
+    #[arg(long)]
+    border: Option<String>,
+
+    /// Rectangle of the border image the game is drawn into as x,y,width,height in image pixels
+    #[arg(long, value_parser = parse_rect, requires = "border")]
+    border_rect: Option<(u32, u32, u32, u32)>,
+
+    /// Experimental: expose the mouse position and buttons at 0x1F0-0x1F2 each frame
+    #[arg(long, default_value_t = false)]
+    mmio_mouse: bool,
+
+    /// Dim and periodically shift the image after this many seconds without input
+    #[arg(long)]
+    idle_timeout: Option<u64>,
+
+    /// Directory that screenshots (F12) and GIF recordings (F10) are saved to
+    #[arg(long, default_value = ".")]
+    screenshot_dir: PathBuf,
+
+    /// Frequency of the beep in Hz
+    #[arg(long, default_value_t = constants::BEEP_FREQUENCY)]
+    beep_freq: f32,
+
+    /// Shape of the beep, softer than the default square wave from sine over triangle to sawtooth
+    #[clap(value_enum, long, default_value_t = Waveform::Square)]
+    waveform: Waveform,
+
+    /// Fraction of each beep period the square wave is high, lower values sound thinner
+    #[arg(long, value_parser = parse_duty_cycle, default_value_t = constants::BEEP_DUTY_CYCLE)]
+    duty_cycle: f32,
+
+    /// Beep volume in percent (change with - and =, mute with M)
+    #[arg(long, default_value_t = constants::DEFAULT_VOLUME, value_parser = clap::value_parser!(u8).range(0..=100))]
+    volume: u8,
+
+    /// Library playing the beep of the SDL frontend
+    #[cfg(feature = "sdl")]
+    #[clap(value_enum, long, default_value_t = AudioBackend::Sdl)]
+    audio_backend: AudioBackend,
+
+    /// Audio sample rate in Hz
+    #[arg(long, default_value_t = constants::AUDIO_SAMPLE_RATE, value_parser = clap::value_parser!(i32).range(1..))]
+    sample_rate: i32,
+
+    /// Audio buffer size in samples, larger buffers add latency but can stop crackling
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    audio_buffer: Option<u16>,
+
+    /// Record the session to a video file through ffmpeg (e.g. out.mp4)
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Log every keypad state change with the RNG seed to a replayable file (e.g. out.c8in)
+    #[arg(long)]
+    record_input: Option<PathBuf>,
+
+    /// Replay a keypad log from --record-input instead of reading the keyboard
+    #[arg(long)]
+    play_input: Option<PathBuf>,
+
+    /// Seed for the random numbers of CXNN, so runs are reproducible, random by default
+    #[arg(long, conflicts_with = "play_input")]
+    seed: Option<u64>,
+
+    /// Symbol file naming addresses (`:const draw_player 0x2A4` per line), defaults to the ROM's .sym file
+    #[arg(long)]
+    symbols: Option<PathBuf>,
+
+    /// Append a line per executed instruction with its changes to the registers to this file
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Only trace instructions in this range of hex addresses, e.g. 0x200-0x2FF
+    #[arg(long, value_parser = parse_range, requires = "trace")]
+    trace_range: Option<RangeInclusive<usize>>,
+
+    /// Count how often each address and kind of instruction runs and print the hottest on exit
+    #[arg(long, default_value_t = false)]
+    hot_spots: bool,
+
+    /// Write the execution counts of every address and kind of instruction as JSON on exit
+    #[arg(long)]
+    hot_spots_json: Option<PathBuf>,
+
+    /// Print instructions, wall-clock time, instructions per second, draws, presented frames and timer ticks on exit
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
+    /// Write a map of the ROM bytes executed or read as data on exit, as HTML if the path ends in .html, - prints it
+    #[arg(long)]
+    coverage: Option<PathBuf>,
+
+    /// Rhai script defining on_cycle, on_breakpoint or on_draw hooks that read and change the machine
+    #[cfg(feature = "scripting")]
+    #[arg(long)]
+    script: Option<PathBuf>,
+
+    /// When the ROM halts in a loop it cannot leave, print where and optionally pause or exit with status 3
+    #[arg(long, value_enum, conflicts_with = "exit_on_halt")]
+    on_halt: Option<HaltAction>,
+
+    /// Exit with status 3 when the ROM halts, same as --on-halt exit
+    #[arg(long, default_value_t = false)]
+    exit_on_halt: bool,
+
+    /// Exit after this many instructions
+    #[arg(long)]
+    max_instructions: Option<u64>,
+
+    /// Exit after this many seconds of emulated time, counted in frames so runs are reproducible
+    #[arg(long = "exit-after-seconds", value_parser = parse_seconds)]
+    exit_after_frames: Option<u64>,
+
+    /// Serve read-only JSON of /registers, /memory?start=0x200&length=16 and /framebuffer over HTTP on this port
+    #[arg(long)]
+    inspect_port: Option<u16>,
+
+    /// Where to write registers, stack, the latest instructions and memory if the emulator crashes [default: crash-dump.txt]
+    #[arg(long)]
+    crash_dump: Option<PathBuf>,
+
+    /// TOML file binding keys to the keypad by SDL scancode name, e.g. `X = 0x0`
+    #[arg(long)]
+    keymap: Option<PathBuf>,
+
+    /// Built-in key bindings, the letter layouts bind the same keys by position
+    #[clap(value_enum, long, default_value_t = Layout::Qwerty, conflicts_with = "keymap")]
+    layout: Layout,
+
+    /// Second set of key bindings active at the same time, for two players sharing the keypad
+    #[arg(long)]
+    player2_keymap: Option<PathBuf>,
+
+    /// Built-in key bindings for a second player, e.g. numpad next to the letter keys
+    #[clap(value_enum, long, conflicts_with = "player2_keymap")]
+    player2_layout: Option<Layout>,
+
+    /// TOML file binding game controller buttons to the keypad by SDL name, e.g. `dpup = 0x5`
+    #[arg(long)]
+    controller_map: Option<PathBuf>,
+
+    /// TOML file binding hotkeys by action, e.g. `pause = "P"` or `fullscreen = ["F11", "Alt+Return"]`
+    #[arg(long)]
+    hotkeys: Option<PathBuf>,
+
+    /// Ask to press the quit hotkey (Escape) a second time before quitting
+    #[arg(long, default_value_t = false)]
+    confirm_exit: bool,
+
+    /// Speed multiplier while the fast forward hotkey (Tab) is held
+    #[arg(long, default_value_t = constants::FAST_FORWARD_FACTOR, value_parser = clap::value_parser!(u32).range(1..))]
+    fast_forward_factor: u32,
+
+    /// Show a keypad highlighting pressed and polled keys (toggle with F4)
+    #[arg(long, default_value_t = false)]
+    keypad: bool,
+
+    /// Debug mode (opens a debugger in the terminal and waits each cycle)
     #[arg(short, long, default_value_t = false)]
     debug: bool,
+
+    /// Open the debugger and pause at these addresses, e.g. 0x230,0x4A0
+    #[cfg(feature = "sdl")]
+    #[arg(long = "break", value_delimiter = ',', value_parser = parse_address)]
+    breakpoints: Vec<usize>,
+
+    /// Wait for gdb to attach on this port (`target remote :1234`) before running
+    #[cfg(feature = "sdl")]
+    #[arg(long)]
+    gdb: Option<u16>,
+
+    /// Wait for a Debug Adapter Protocol client such as VS Code (`"debugServer": 4711`) on this port
+    #[cfg(feature = "sdl")]
+    #[arg(long, conflicts_with = "gdb")]
+    dap: Option<u16>,
+
+    /// Wait for a browser debugger to connect over WebSocket on this port
+    #[cfg(feature = "sdl")]
+    #[arg(long, conflicts_with_all = ["gdb", "dap"])]
+    websocket: Option<u16>,
+
+    /// Source map from `asm` for showing and breaking on source lines, defaults to the ROM's .map file
+    #[cfg(feature = "sdl")]
+    #[arg(long)]
+    source_map: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Frontend {
+    #[cfg(feature = "sdl")]
+    Sdl,
+    #[cfg(feature = "minifb")]
+    Minifb,
+    #[cfg(feature = "pixels")]
+    Pixels,
+    Terminal,
+}
+
+// The most capable window backend compiled in, falling back to the terminal
+#[cfg(feature = "sdl")]
+const DEFAULT_FRONTEND: Frontend = Frontend::Sdl;
+#[cfg(all(not(feature = "sdl"), feature = "minifb"))]
+const DEFAULT_FRONTEND: Frontend = Frontend::Minifb;
+#[cfg(all(not(any(feature = "sdl", feature = "minifb")), feature = "pixels"))]
+const DEFAULT_FRONTEND: Frontend = Frontend::Pixels;
+#[cfg(not(any(feature = "sdl", feature = "minifb", feature = "pixels")))]
+const DEFAULT_FRONTEND: Frontend = Frontend::Terminal;
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Find the smallest quirk difference that changes how a ROM behaves
+    Bisect {
+        /// Path to the ROM file to load
+        #[arg(long)]
+        rom: String,
+
+        /// Platform whose quirks the ROM works with
+        #[clap(value_enum, long)]
+        good_quirks: Platform,
+
+        /// Platform whose quirks the ROM breaks with
+        #[clap(value_enum, long)]
+        bad_quirks: Platform,
+
+        /// Number of frames to compare
+        #[arg(long, default_value_t = 600)]
+        frames: usize,
+
+        /// The instruction time in nanoseconds
+        #[arg(short, long, default_value_t = 140_000)]
+        instruction_time: u128,
+
+        /// Keypad log from --record-input to replay in every run, for ROMs that need input to diverge
+        #[arg(long)]
+        input: Option<PathBuf>,
+    },
+    /// Assemble an Octo source file into a ROM, without macros, :calc, :next, :unpack or XO-CHIP instructions
+    Asm {
+        /// Path to the source file
+        source: String,
+
+        /// Path to write the ROM to
+        #[arg(short, long)]
+        output: String,
+
+        /// Path to write the label addresses to, defaults to the ROM path with a .sym extension
+        #[arg(long)]
+        symbols: Option<PathBuf>,
+
+        /// Path to write the source line of each instruction to, defaults to the ROM path with a .map extension
+        #[arg(long)]
+        source_map: Option<PathBuf>,
+    },
+    /// Print the instructions of a ROM, naming addresses from a symbol file
+    Disasm {
+        /// Path to the ROM file to load
+        rom: String,
+
+        /// Symbol file, defaults to the ROM's .sym file
+        #[arg(long)]
+        symbols: Option<PathBuf>,
+    },
+    /// Walk the paths through a ROM without running it, reporting unreachable bytes, suspicious
+    /// instructions and the quirks it likely depends on
+    Analyze {
+        /// Path to the ROM file to load
+        rom: String,
+
+        /// Symbol file, defaults to the ROM's .sym file
+        #[arg(long)]
+        symbols: Option<PathBuf>,
+    },
+    /// Compare two instruction traces and show where they first diverge, exits with 1 if they do
+    TraceDiff {
+        /// Trace written with --trace
+        a: String,
+
+        /// Trace to compare it with, e.g. of a reference emulator in the same format
+        b: String,
+    },
+}
+
+fn read_rom(rom_file: &str) -> Vec<u8> {
+    std::fs::read(rom_file).unwrap_or_else(|error| panic!("Failed to read file: {:?}", error))
+}
+
+fn read_trace(trace_file: &str) -> String {
+    std::fs::read_to_string(trace_file)
+        .unwrap_or_else(|error| panic!("Failed to read trace: {:?}", error))
 }
 
 fn main() {
     let args = Args::parse();
 
-    let foreground_color = (255, 255, 255);
-    let background_color = (0, 0, 0);
+    if args.version {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        if args.verbose {
+            println!("{}", verbose_version());
+        }
+        return;
+    }
 
+    match args.command {
+        Some(Command::Bisect {
+            rom,
+            good_quirks,
+            bad_quirks,
+            frames,
+            instruction_time,
+            input,
+        }) => bisect(
+            &read_rom(&rom),
+            instruction_time,
+            Quirks::new(good_quirks),
+            Quirks::new(bad_quirks),
+            frames,
+            input.as_deref().map(InputPlayer::load).as_ref(),
+        ),
+        Some(Command::Asm {
+            source,
+            output,
+            symbols,
+            source_map,
+        }) => {
+            let contents = std::fs::read_to_string(&source)
+                .unwrap_or_else(|error| panic!("Failed to read source: {:?}", error));
+            let assembly = assemble_with_debug_info(&contents)
+                .unwrap_or_else(|error| panic!("Failed to assemble: {}", error));
+            std::fs::write(&output, assembly.rom)
+                .unwrap_or_else(|error| panic!("Failed to write ROM: {:?}", error));
+            let symbols = symbols.unwrap_or_else(|| symbols_path(Path::new(&output)));
+            std::fs::write(symbols, assembly.symbols.to_source())
+                .unwrap_or_else(|error| panic!("Failed to write symbols: {:?}", error));
+            // The map names the source by its absolute path, so it can be moved with the ROM
+            let source_path = std::fs::canonicalize(&source)
+                .unwrap_or_else(|error| panic!("Failed to read source: {:?}", error));
+            let source_map = source_map.unwrap_or_else(|| source_map_path(Path::new(&output)));
+            std::fs::write(source_map, assembly.source_map.to_text(&source_path))
+                .unwrap_or_else(|error| panic!("Failed to write source map: {:?}", error));
+        }
+        Some(Command::Disasm { rom, symbols }) => {
+            let symbols = load_symbols(symbols.as_deref(), &rom);
+            print!("{}", disassemble_rom(&read_rom(&rom), &symbols));
+        }
+        Some(Command::Analyze { rom, symbols }) => {
+            let symbols = load_symbols(symbols.as_deref(), &rom);
+            print!(
+                "{}",
+                Analysis::build(&read_rom(&rom), &symbols).text_report()
+            );
+        }
+        Some(Command::TraceDiff { a, b }) => {
+            let (a_contents, b_contents) = (read_trace(&a), read_trace(&b));
+            match diff_traces(
+                &Trace::parse(&a, &a_contents),
+                &Trace::parse(&b, &b_contents),
+            ) {
+                Ok(text) => print!("{}", text),
+                Err(text) => {
+                    print!("{}", text);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => run(args),
+    }
+}
+
+fn load_symbols(path: Option<&Path>, rom_file: &str) -> Symbols {
+    path.map_or_else(|| Symbols::for_rom(Path::new(rom_file)), Symbols::load)
+}
+
+fn run(args: Args) {
+    install_panic_hook();
     let quirks = Quirks::new(args.platform);
+    let input_player = args.play_input.as_deref().map(InputPlayer::load);
+    // A replay needs the random numbers of the recording
+    let seed = input_player
+        .as_ref()
+        .map(InputPlayer::seed)
+        .or(args.seed)
+        .unwrap_or_else(|| {
+            time::SystemTime::now()
+                .duration_since(time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as u64
+        });
+    let rng = Box::new(Pcg32::new(seed));
 
-    let mut chip8 = Chip8::build(
-        &args.rom_file,
-        args.instruction_time,
-        args.scale,
-        background_color,
-        foreground_color,
-        args.debug,
-        quirks,
+    let rom_file = args.rom_file.clone().unwrap();
+    let rom = read_rom(&rom_file);
+    #[cfg_attr(
+        not(any(feature = "sdl", feature = "minifb", feature = "pixels")),
+        allow(unused)
+    )]
+    let title = format!(
+        "{} - {} [{}]",
+        constants::WINDOW_TITLE,
+        Path::new(&rom_file).file_name().map_or_else(
+            || rom_file.clone(),
+            |name| name.to_string_lossy().into_owned()
+        ),
+        args.platform.to_possible_value().unwrap().get_name()
     );
+    let theme = args.theme.unwrap_or(match args.filter {
+        Filter::Lcd => Theme::Lcd,
+        _ => Theme::Classic,
+    });
+    let palette = args
+        .palette
+        .as_deref()
+        .map(load_palette)
+        .unwrap_or_else(|| vec![theme.background_color(), theme.foreground_color()]);
+    let background_color = args.bg.unwrap_or(palette[0]);
+    let foreground_color = args.fg.unwrap_or(palette[1]);
+    let rom_config = load_rom_config(Path::new(&rom_file));
+    let symbols = load_symbols(args.symbols.as_deref(), &rom_file);
+    let tone = rom_config.audio.apply(tone_options(&args));
+
+    // Headless runs are not tied to wall time and go as fast as possible
+    let clock: Box<dyn Clock> = if args.headless {
+        Box::new(VirtualClock::new())
+    } else {
+        Box::new(SystemClock::with_sleep_granularity(
+            args.profile.sleep_granularity(),
+        ))
+    };
+    let mut chip8 = Chip8::build(&rom, args.instruction_time, args.debug, quirks, rng, clock);
+    if let Some(path) = &args.record_input {
+        let recorder = InputRecorder::create(path, seed)
+            .unwrap_or_else(|error| panic!("Failed to create input log: {:?}", error));
+        chip8.record_input(recorder);
+    }
+    if let Some(input_player) = input_player {
+        chip8.play_input(input_player);
+    }
+    if let Some(path) = &args.trace {
+        let tracer = Tracer::create(path, args.trace_range.clone(), symbols.clone())
+            .unwrap_or_else(|error| panic!("Failed to open trace: {:?}", error));
+        chip8.trace(tracer);
+    }
+    if args.hot_spots || args.hot_spots_json.is_some() {
+        chip8.profile_hot_spots();
+    }
+    if args.coverage.is_some() {
+        chip8.track_coverage(Coverage::new(rom.len()));
+    }
+    if let Some(action) = args
+        .on_halt
+        .or(args.exit_on_halt.then_some(HaltAction::Exit))
+    {
+        chip8.detect_halts(action);
+    }
+    if let Some(port) = args.inspect_port {
+        let inspect_server = InspectServer::start(port)
+            .unwrap_or_else(|error| panic!("Failed to start inspection server: {:?}", error));
+        chip8.serve_inspection(inspect_server);
+    }
+    if let Some(max_instructions) = args.max_instructions {
+        chip8.limit_instructions(max_instructions);
+    }
+    if let Some(frames) = args.exit_after_frames {
+        chip8.limit_frames(frames);
+    }
+    #[cfg(feature = "scripting")]
+    if let Some(path) = &args.script {
+        chip8.hook_cycles(Box::new(Script::load(path, symbols.clone())));
+    }
+    let reports = RunReports {
+        hot_spots_json: args.hot_spots_json.clone(),
+        coverage: args.coverage.clone(),
+        symbols: symbols.clone(),
+        stats: args.stats,
+        start: time::Instant::now(),
+    };
+    let crash_dump = args
+        .crash_dump
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(constants::CRASH_DUMP_FILE));
+
+    if args.headless {
+        let (chip8, presented_frames) = run_frontend(
+            HeadlessFrontend::build(chip8),
+            |frontend| {
+                frontend.run(args.frames);
+                if let Some(dump) = &args.dump {
+                    frontend.dump_display(dump, args.scale, (background_color, foreground_color));
+                }
+                None
+            },
+            HeadlessFrontend::into_chip8,
+            &crash_dump,
+        );
+        finish_run(chip8, presented_frames, &reports);
+        return;
+    }
 
-    chip8.run();
+    match args.frontend {
+        #[cfg(feature = "sdl")]
+        Frontend::Sdl => {
+            let display_options = display_options(
+                &args,
+                (background_color, foreground_color),
+                &rom_config.display,
+            );
+            let rom_name = Path::new(&rom_file).file_stem().map_or_else(
+                || "screenshot".to_string(),
+                |stem| stem.to_string_lossy().into_owned(),
+            );
+            let audio = AudioOptions {
+                backend: args.audio_backend,
+                ..audio_options(&args)
+            };
+            let keymap = args
+                .keymap
+                .as_deref()
+                .map_or_else(|| Keymap::from_layout(args.layout), Keymap::load);
+            let frontend_options = FrontendOptions {
+                mmio_mouse: args.mmio_mouse,
+                idle_timeout: args.idle_timeout,
+                screenshot_directory: args.screenshot_dir,
+                rom_name,
+                title,
+                theme,
+                tone,
+                audio,
+                keymap,
+                keymap_path: args
+                    .keymap
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from(constants::KEYMAP_FILE)),
+                player2_keymap: args
+                    .player2_keymap
+                    .as_deref()
+                    .map(Keymap::load)
+                    .or(args.player2_layout.map(Keymap::from_layout)),
+                hotkeys: args
+                    .hotkeys
+                    .as_deref()
+                    .map_or_else(Hotkeys::new, Hotkeys::load),
+                fast_forward_factor: args.fast_forward_factor,
+                confirm_exit: args.confirm_exit,
+                controller_map: args
+                    .controller_map
+                    .as_deref()
+                    .map_or_else(Keymap::controller, Keymap::load),
+                touch_controls: rom_config.touch.or_else(|| {
+                    cfg!(any(target_os = "android", target_os = "ios")).then(TouchControls::new)
+                }),
+                record: args.record,
+                pacing: args.profile.pacing(),
+                show_stats: args.profile.show_stats(),
+                show_keypad: args.keypad,
+                debug: args.debug,
+                breakpoints: args.breakpoints,
+                gdb_port: args.gdb,
+                dap_port: args.dap,
+                websocket_port: args.websocket,
+                symbols,
+                source_map: args
+                    .source_map
+                    .as_deref()
+                    .map_or_else(|| SourceMap::for_rom(Path::new(&rom_file)), SourceMap::load),
+            };
+            let (chip8, presented_frames) = run_frontend(
+                SdlFrontend::build(chip8, display_options, frontend_options),
+                |frontend| {
+                    frontend.run();
+                    Some(frontend.presented_frames())
+                },
+                SdlFrontend::into_chip8,
+                &crash_dump,
+            );
+            finish_run(chip8, presented_frames, &reports);
+        }
+        #[cfg(feature = "minifb")]
+        Frontend::Minifb => {
+            let display_options = display_options(
+                &args,
+                (background_color, foreground_color),
+                &rom_config.display,
+            );
+            let (chip8, presented_frames) = run_frontend(
+                MinifbFrontend::build(chip8, &display_options, &title),
+                |frontend| {
+                    frontend.run();
+                    Some(frontend.presented_frames())
+                },
+                MinifbFrontend::into_chip8,
+                &crash_dump,
+            );
+            finish_run(chip8, presented_frames, &reports);
+        }
+        #[cfg(feature = "pixels")]
+        Frontend::Pixels => {
+            let display_options = display_options(
+                &args,
+                (background_color, foreground_color),
+                &rom_config.display,
+            );
+            let (chip8, presented_frames) = run_frontend(
+                PixelsFrontend::build(chip8, &display_options, &title),
+                |frontend| {
+                    frontend.run();
+                    Some(frontend.presented_frames())
+                },
+                PixelsFrontend::into_chip8,
+                &crash_dump,
+            );
+            finish_run(chip8, presented_frames, &reports);
+        }
+        Frontend::Terminal => {
+            let (chip8, presented_frames) = run_frontend(
+                TerminalFrontend::build(chip8, args.terminal_mode, terminal_audio(&args, tone)),
+                |frontend| {
+                    frontend.run();
+                    Some(frontend.presented_frames())
+                },
+                TerminalFrontend::into_chip8,
+                &crash_dump,
+            );
+            finish_run(chip8, presented_frames, &reports);
+        }
+    }
+}
+
+// Runs a frontend and returns its machine, writing the machine state to a file first if it
+// panics, so crash reports come with what is needed to understand them
+fn run_frontend<F, T>(
+    mut frontend: F,
+    run: impl FnOnce(&mut F) -> T,
+    into_chip8: fn(F) -> Chip8,
+    crash_dump: &Path,
+) -> (Chip8, T) {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| run(&mut frontend)));
+    // Taking the machine drops the rest of the frontend, which restores the terminal and closes
+    // windows, so the notice below is readable
+    let chip8 = into_chip8(frontend);
+    match result {
+        Ok(value) => (chip8, value),
+        Err(payload) => {
+            match write_crash_dump(&chip8, crash_dump) {
+                Ok(()) => eprintln!(
+                    "Wrote the machine state to {:?}, please attach it to bug reports",
+                    crash_dump
+                ),
+                Err(error) => eprintln!("Failed to write crash dump: {:?}", error),
+            }
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+// Where the reports on a run go once it ends
+struct RunReports {
+    hot_spots_json: Option<PathBuf>,
+    coverage: Option<PathBuf>,
+    symbols: Symbols,
+    stats: bool,
+    start: time::Instant,
+}
+
+// Exits with the core's exit code, if it ended the run
+fn finish_run(chip8: Chip8, presented_frames: Option<u64>, reports: &RunReports) {
+    if reports.stats {
+        let stats = RunStats::build(&chip8, reports.start.elapsed(), presented_frames);
+        print!("{}", stats.text_report());
+    }
+    report_hot_spots(&chip8, reports.hot_spots_json.as_deref());
+    if let Some(path) = &reports.coverage {
+        report_coverage(&chip8, path, &reports.symbols);
+    }
+    let exit_code = chip8.exit_code();
+    // Exiting skips destructors, so the trace and input log are flushed by dropping the core first
+    drop(chip8);
+    if let Some(code) = exit_code {
+        std::process::exit(code);
+    }
+}
+
+// Prints the report of a run with hot spot profiling, or writes it as JSON if given a path
+fn report_hot_spots(chip8: &Chip8, json_path: Option<&Path>) {
+    let Some(hot_spots) = chip8.hot_spots() else {
+        return;
+    };
+    match json_path {
+        Some(path) => std::fs::write(path, hot_spots.json_report())
+            .unwrap_or_else(|error| panic!("Failed to write hot spots: {:?}", error)),
+        None => print!("{}", hot_spots.text_report()),
+    }
+}
+
+// Writes HTML if the path ends in .html, text otherwise, `-` prints the text
+fn report_coverage(chip8: &Chip8, path: &Path, symbols: &Symbols) {
+    let Some(coverage) = chip8.coverage() else {
+        return;
+    };
+    if path == Path::new("-") {
+        print!("{}", coverage.text_report(symbols));
+        return;
+    }
+    let report = if path
+        .extension()
+        .is_some_and(|extension| extension == "html")
+    {
+        coverage.html_report(chip8.ram(), symbols)
+    } else {
+        coverage.text_report(symbols)
+    };
+    std::fs::write(path, report)
+        .unwrap_or_else(|error| panic!("Failed to write coverage: {:?}", error));
+}
+
+fn tone_options(args: &Args) -> ToneOptions {
+    ToneOptions {
+        frequency: args.beep_freq,
+        waveform: args.waveform,
+        duty_cycle: args.duty_cycle,
+        volume: args.volume,
+    }
+}
+
+#[cfg(any(feature = "sdl", feature = "cpal"))]
+fn audio_options(args: &Args) -> AudioOptions {
+    AudioOptions {
+        sample_rate: args.sample_rate,
+        buffer_samples: args.audio_buffer,
+        ..AudioOptions::new()
+    }
+}
+
+#[cfg_attr(not(feature = "cpal"), allow(unused))]
+fn terminal_audio(args: &Args, tone: ToneOptions) -> Box<dyn AudioSink> {
+    #[cfg(feature = "cpal")]
+    if args.terminal_tone {
+        return Box::new(CpalBeep::build(tone, audio_options(args)));
+    }
+
+    let mut bell = TerminalBell::new();
+    bell.set_volume(tone.volume);
+    Box::new(bell)
+}
+
+#[cfg_attr(
+    not(any(feature = "sdl", feature = "minifb", feature = "pixels")),
+    allow(unused)
+)]
+fn display_options(
+    args: &Args,
+    (background_color, foreground_color): ((u8, u8, u8), (u8, u8, u8)),
+    overrides: &DisplayOverrides,
+) -> DisplayOptions {
+    overrides.apply(DisplayOptions {
+        scale: args.scale,
+        pixel_aspect: args.pixel_aspect,
+        background_color,
+        foreground_color,
+        fullscreen: args.fullscreen,
+        vsync: args.vsync || args.profile.vsync(),
+        filter: args.filter,
+        rotation: args.rotate,
+        mirror: args.mirror,
+        phosphor_frames: args.phosphor_frames,
+        frame_blend: args.frame_blend,
+        border: args.border.clone(),
+        border_rect: args.border_rect,
+        grid: args.grid,
+        sound_flash: args.sound_flash,
+    })
 }
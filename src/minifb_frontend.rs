@@ -0,0 +1,136 @@
+use minifb::{Key, ScaleMode, Window, WindowOptions};
+
+use crate::chip_8::Chip8;
+use crate::constants;
+use crate::display_options::DisplayOptions;
+use crate::renderer::{self, Renderer};
+
+fn map_key_to_value(key: Key) -> Option<u8> {
+    match key {
+        Key::X => Some(0x00),
+        Key::Key1 => Some(0x01),
+        Key::Key2 => Some(0x02),
+        Key::Key3 => Some(0x03),
+        Key::Q => Some(0x04),
+        Key::W => Some(0x05),
+        Key::E => Some(0x06),
+        Key::A => Some(0x07),
+        Key::S => Some(0x08),
+        Key::D => Some(0x09),
+        Key::Z => Some(0x0A),
+        Key::C => Some(0x0B),
+        Key::Key4 => Some(0x0C),
+        Key::R => Some(0x0D),
+        Key::F => Some(0x0E),
+        Key::V => Some(0x0F),
+        _ => None,
+    }
+}
+
+fn to_rgb(color: (u8, u8, u8)) -> u32 {
+    (color.0 as u32) << 16 | (color.1 as u32) << 8 | color.2 as u32
+}
+
+// Only honors the scale, pixel aspect and colors of the display options
+pub struct MinifbDisplay {
+    window: Window,
+    buffer: Vec<u32>,
+    background_color: u32,
+    foreground_color: u32,
+    presented_frames: u64,
+}
+
+impl MinifbDisplay {
+    pub fn build(options: &DisplayOptions, title: &str) -> Self {
+        let (scale_x, scale_y) = options.pixel_size();
+        let mut window = Window::new(
+            title,
+            constants::DISPLAY_WIDTH * scale_x as usize,
+            constants::DISPLAY_HEIGHT * scale_y as usize,
+            WindowOptions {
+                scale_mode: ScaleMode::Stretch,
+                ..WindowOptions::default()
+            },
+        )
+        .unwrap_or_else(|error| panic!("Failed to create window: {:?}", error));
+        // The core paces itself, minifb would otherwise sleep in every update
+        window.set_target_fps(0);
+
+        MinifbDisplay {
+            window,
+            buffer: vec![0; constants::DISPLAY_LEN],
+            background_color: to_rgb(options.background_color),
+            foreground_color: to_rgb(options.foreground_color),
+            presented_frames: 0,
+        }
+    }
+}
+
+impl Renderer for MinifbDisplay {
+    fn render_buffer(&mut self, buffer: &[bool; constants::DISPLAY_LEN]) {
+        self.presented_frames += 1;
+        for (pixel, &is_set) in self.buffer.iter_mut().zip(buffer.iter()) {
+            *pixel = if is_set {
+                self.foreground_color
+            } else {
+                self.background_color
+            };
+        }
+        self.window
+            .update_with_buffer(
+                &self.buffer,
+                constants::DISPLAY_WIDTH,
+                constants::DISPLAY_HEIGHT,
+            )
+            .unwrap();
+    }
+
+    // Window events are only processed when updating, so update once per frame
+    fn skip_frame(&mut self) {
+        self.window.update();
+    }
+
+    fn is_open(&self, _frame: u64) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+
+    fn pressed_keys(&mut self, _frame: u64) -> Option<u16> {
+        let pressed_keys = self
+            .window
+            .get_keys()
+            .into_iter()
+            .filter_map(map_key_to_value)
+            .fold(0u16, |mask, key| mask | (1 << key));
+        Some(pressed_keys)
+    }
+}
+
+// A window without audio, hotkeys or overlays for systems without the SDL2 libraries
+pub struct MinifbFrontend {
+    chip8: Chip8,
+
+    display: MinifbDisplay,
+}
+
+impl MinifbFrontend {
+    pub fn build(chip8: Chip8, display_options: &DisplayOptions, title: &str) -> Self {
+        MinifbFrontend {
+            chip8,
+
+            display: MinifbDisplay::build(display_options, title),
+        }
+    }
+
+    pub fn into_chip8(self) -> Chip8 {
+        self.chip8
+    }
+
+    // Frames shown since the start, for the statistics of a run
+    pub fn presented_frames(&self) -> u64 {
+        self.display.presented_frames
+    }
+
+    pub fn run(&mut self) {
+        renderer::run(&mut self.chip8, &mut self.display);
+    }
+}
@@ -0,0 +1,92 @@
+use sdl2::rect::Rect;
+
+use crate::constants;
+
+// 3x5 glyphs, one row per byte with the leftmost pixel in the third bit
+fn glyph(character: char) -> [u8; 5] {
+    match character.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        ' ' => [0b000; 5],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+// Size in window pixels of lines of text drawn at the given pixel size
+pub fn text_size(text: &str, pixel_size: u32) -> (u32, u32) {
+    let columns = text
+        .lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0) as u32;
+    (
+        columns * constants::OVERLAY_GLYPH_ADVANCE.0 * pixel_size,
+        text.lines().count() as u32 * constants::OVERLAY_GLYPH_ADVANCE.1 * pixel_size,
+    )
+}
+
+// One rectangle per set glyph pixel, with the text's top left corner at the given position.
+// Replaces the contents of `rects`, which is kept between frames so drawing does not allocate.
+pub fn text_rects(text: &str, (x, y): (i32, i32), pixel_size: u32, rects: &mut Vec<Rect>) {
+    let (advance_x, advance_y) = constants::OVERLAY_GLYPH_ADVANCE;
+    rects.clear();
+    for (line_index, line) in text.lines().enumerate() {
+        for (column, character) in line.chars().enumerate() {
+            let left = x + (column as u32 * advance_x * pixel_size) as i32;
+            let top = y + (line_index as u32 * advance_y * pixel_size) as i32;
+            for (row, bits) in glyph(character).iter().enumerate() {
+                for bit in 0..3 {
+                    if bits & (0b100 >> bit) != 0 {
+                        rects.push(Rect::new(
+                            left + (bit * pixel_size) as i32,
+                            top + (row as u32 * pixel_size) as i32,
+                            pixel_size,
+                            pixel_size,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use pixels::{Pixels, SurfaceTexture};
+use winit::{
+    dpi::{LogicalSize, PhysicalSize},
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event_loop::EventLoop,
+    platform::run_return::EventLoopExtRunReturn,
+    window::{Window, WindowBuilder},
+};
+
+use crate::chip_8::Chip8;
+use crate::constants;
+use crate::display_options::DisplayOptions;
+use crate::renderer::{self, Renderer};
+
+fn map_virtual_keycode_to_value(keycode: VirtualKeyCode) -> Option<u8> {
+    match keycode {
+        VirtualKeyCode::X => Some(0x00),
+        VirtualKeyCode::Key1 => Some(0x01),
+        VirtualKeyCode::Key2 => Some(0x02),
+        VirtualKeyCode::Key3 => Some(0x03),
+        VirtualKeyCode::Q => Some(0x04),
+        VirtualKeyCode::W => Some(0x05),
+        VirtualKeyCode::E => Some(0x06),
+        VirtualKeyCode::A => Some(0x07),
+        VirtualKeyCode::S => Some(0x08),
+        VirtualKeyCode::D => Some(0x09),
+        VirtualKeyCode::Z => Some(0x0A),
+        VirtualKeyCode::C => Some(0x0B),
+        VirtualKeyCode::Key4 => Some(0x0C),
+        VirtualKeyCode::R => Some(0x0D),
+        VirtualKeyCode::F => Some(0x0E),
+        VirtualKeyCode::V => Some(0x0F),
+        _ => None,
+    }
+}
+
+// Only honors the scale, pixel aspect and colors of the display options
+pub struct PixelsDisplay {
+    // Declared before the window it draws to, so it is dropped first
+    pixels: Pixels,
+    window: Window,
+    background_color: [u8; 4],
+    foreground_color: [u8; 4],
+    // Kept up to date from window events, which winit delivers to the frontend
+    pressed_keys: u16,
+    presented_frames: u64,
+}
+
+impl PixelsDisplay {
+    pub fn build(event_loop: &EventLoop<()>, options: &DisplayOptions, title: &str) -> Self {
+        let (scale_x, scale_y) = options.pixel_size();
+        let window = WindowBuilder::new()
+            .with_title(title)
+            .with_inner_size(LogicalSize::new(
+                constants::DISPLAY_WIDTH as u32 * scale_x,
+                constants::DISPLAY_HEIGHT as u32 * scale_y,
+            ))
+            .build(event_loop)
+            .unwrap_or_else(|error| panic!("Failed to create window: {:?}", error));
+
+        let window_size = window.inner_size();
+        let pixels = Pixels::new(
+            constants::DISPLAY_WIDTH as u32,
+            constants::DISPLAY_HEIGHT as u32,
+            SurfaceTexture::new(window_size.width, window_size.height, &window),
+        )
+        .unwrap_or_else(|error| panic!("Failed to create surface: {:?}", error));
+
+        let (red, green, blue) = options.background_color;
+        let background_color = [red, green, blue, 0xFF];
+        let (red, green, blue) = options.foreground_color;
+        let foreground_color = [red, green, blue, 0xFF];
+
+        PixelsDisplay {
+            pixels,
+            window,
+            background_color,
+            foreground_color,
+            pressed_keys: 0,
+            presented_frames: 0,
+        }
+    }
+
+    // Shows the latest buffer, when winit asks to redraw
+    pub fn present(&mut self) {
+        self.presented_frames += 1;
+        self.pixels.render().unwrap();
+    }
+
+    pub fn set_key(&mut self, key: u8, is_pressed: bool) {
+        if is_pressed {
+            self.pressed_keys |= 1 << key;
+        } else {
+            self.pressed_keys &= !(1 << key);
+        }
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        // Minimized windows report a size of zero, which the surface cannot have
+        if size.width > 0 && size.height > 0 {
+            self.pixels.resize_surface(size.width, size.height).unwrap();
+        }
+    }
+}
+
+// Rendering only fills the surface, winit is asked to redraw the window with it
+impl Renderer for PixelsDisplay {
+    fn render_buffer(&mut self, buffer: &[bool; constants::DISPLAY_LEN]) {
+        for (pixel, &is_set) in self
+            .pixels
+            .frame_mut()
+            .chunks_exact_mut(4)
+            .zip(buffer.iter())
+        {
+            pixel.copy_from_slice(if is_set {
+                &self.foreground_color
+            } else {
+                &self.background_color
+            });
+        }
+        self.window.request_redraw();
+    }
+
+    fn pressed_keys(&mut self, _frame: u64) -> Option<u16> {
+        Some(self.pressed_keys)
+    }
+}
+
+// A GPU surface through wgpu without audio, hotkeys or overlays, for systems without SDL2
+pub struct PixelsFrontend {
+    chip8: Chip8,
+
+    event_loop: EventLoop<()>,
+    display: PixelsDisplay,
+}
+
+impl PixelsFrontend {
+    pub fn build(chip8: Chip8, display_options: &DisplayOptions, title: &str) -> Self {
+        let event_loop = EventLoop::new();
+        let display = PixelsDisplay::build(&event_loop, display_options, title);
+
+        PixelsFrontend {
+            chip8,
+
+            event_loop,
+            display,
+        }
+    }
+
+    pub fn into_chip8(self) -> Chip8 {
+        self.chip8
+    }
+
+    // Frames shown since the start, for the statistics of a run
+    pub fn presented_frames(&self) -> u64 {
+        self.display.presented_frames
+    }
+
+    pub fn run(&mut self) {
+        self.display.render_buffer(self.chip8.display_buffer());
+        self.event_loop.run_return(|event, _, control_flow| {
+            control_flow.set_poll();
+
+            match event {
+                Event::WindowEvent { event, .. } => match event {
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => control_flow.set_exit(),
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(keycode),
+                                state,
+                                ..
+                            },
+                        ..
+                    } => {
+                        if let Some(key) = map_virtual_keycode_to_value(keycode) {
+                            self.display.set_key(key, state == ElementState::Pressed);
+                        }
+                    }
+                    WindowEvent::Resized(size) => self.display.resize(size),
+                    _ => {}
+                },
+                Event::RedrawRequested(_) => self.display.present(),
+                // Runs the core between event batches, as winit owns the loop
+                Event::MainEventsCleared => {
+                    let is_running = renderer::run_pass(&mut self.chip8, &mut self.display);
+                    if !is_running {
+                        control_flow.set_exit();
+                    }
+                }
+                _ => {}
+            }
+        });
+    }
+}
@@ -0,0 +1,45 @@
+use clap::ValueEnum;
+
+use crate::constants;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pacing {
+    // Wake up for every instruction, spreading them evenly over the frame
+    Instruction,
+    // Wake up once per frame and run the frame's instructions in a burst
+    Frame,
+}
+
+// Bundles the timing trade-offs between power usage and timing accuracy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Profile {
+    Battery,
+    Balanced,
+    Performance,
+}
+
+impl Profile {
+    pub fn pacing(self) -> Pacing {
+        match self {
+            Profile::Battery => Pacing::Frame,
+            Profile::Balanced | Profile::Performance => Pacing::Instruction,
+        }
+    }
+
+    pub fn vsync(self) -> bool {
+        self == Profile::Battery
+    }
+
+    // Nanoseconds left to spinning instead of sleeping, see `SystemClock::with_sleep_granularity`
+    pub fn sleep_granularity(self) -> u128 {
+        match self {
+            Profile::Battery => 0,
+            Profile::Balanced => constants::SLEEP_GRANULARITY,
+            Profile::Performance => constants::TIMER_DECREMENT_TIME,
+        }
+    }
+
+    pub fn show_stats(self) -> bool {
+        self == Profile::Performance
+    }
+}
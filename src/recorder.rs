@@ -0,0 +1,90 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use crate::constants;
+use crate::image::scale_pixels;
+use crate::tone::ToneOptions;
+use crate::wav::{beep_samples, save_wav};
+
+// Converts a frame number to the GIF time base of hundredths of a second
+fn frame_to_centiseconds(frame: u64) -> u64 {
+    frame * 100 / constants::FRAME_RATE
+}
+
+// Buffers unscaled RGB24 frames at the frame rate and writes them as an animated GIF,
+// with the beep in a WAV file next to it since GIFs have no sound
+pub struct Recorder {
+    frames: Vec<(Vec<u8>, u64)>,
+    frame: u64,
+    beeping_frames: Vec<bool>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder {
+            frames: Vec::new(),
+            frame: 0,
+            beeping_frames: Vec::new(),
+        }
+    }
+
+    pub fn push_frame(&mut self, pixels: &[u8], is_beeping: bool) {
+        self.beeping_frames.push(is_beeping);
+        match self.frames.last_mut() {
+            Some((last_pixels, _)) if last_pixels == pixels => {}
+            // Viewers slow down delays below 2/100 s, so a change that quick replaces the frame
+            Some((last_pixels, start_frame))
+                if frame_to_centiseconds(self.frame) - frame_to_centiseconds(*start_frame)
+                    < constants::GIF_MIN_DELAY =>
+            {
+                last_pixels.copy_from_slice(pixels);
+            }
+            _ => self.frames.push((pixels.to_vec(), self.frame)),
+        }
+        self.frame += 1;
+    }
+
+    pub fn save(
+        &self,
+        path: &Path,
+        (scale_x, scale_y): (u32, u32),
+        tone: ToneOptions,
+    ) -> Result<(), gif::EncodingError> {
+        let width = constants::DISPLAY_WIDTH as u32 * scale_x;
+        let height = constants::DISPLAY_HEIGHT as u32 * scale_y;
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        for (index, (pixels, start_frame)) in self.frames.iter().enumerate() {
+            let end_frame = self
+                .frames
+                .get(index + 1)
+                .map_or(self.frame, |(_, frame)| *frame);
+            let mut frame = gif::Frame::from_rgb_speed(
+                width as u16,
+                height as u16,
+                &scale_pixels(pixels, (scale_x, scale_y)),
+                constants::GIF_QUANTIZE_SPEED,
+            );
+            frame.delay =
+                (frame_to_centiseconds(end_frame) - frame_to_centiseconds(*start_frame)) as u16;
+            encoder.write_frame(&frame)?;
+        }
+
+        save_wav(
+            &path.with_extension("wav"),
+            &beep_samples(&self.beeping_frames, tone),
+        )
+        .map_err(|error| io::Error::other(error).into())
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,53 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::chip_8::Chip8;
+use crate::debugger::DebuggerCommand;
+
+// A debugger client attached over the network, see `GdbStub` and `DapServer`
+pub trait RemoteDebugger {
+    // Answers what the client asked so far and returns what the frontend should do about the rest
+    fn poll_command(&mut self, chip8: &mut Chip8) -> Option<DebuggerCommand>;
+    // Called after each instruction, whether to enter step mode here
+    fn check_stop(&mut self, chip8: &Chip8) -> bool;
+    // Called while in step mode, tells the client if it waits for the machine to stop
+    fn report_stop(&mut self);
+}
+
+// Blocks until a client connects
+pub fn accept(port: u16, client: &str) -> io::Result<TcpStream> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Waiting for {} on port {}", client, port);
+    let (stream, _) = listener.accept()?;
+    stream.set_nonblocking(true)?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+// Appends what has arrived without waiting for more, returns false once the client is gone
+pub fn read_available(stream: &mut TcpStream, input: &mut Vec<u8>) -> io::Result<bool> {
+    let mut buffer = [0; 4096];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => return Ok(false),
+            Ok(len) => input.extend_from_slice(&buffer[..len]),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(true),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+// The stream does not block for reads, so writes wait out a full send buffer themselves
+pub fn write_all(stream: &mut TcpStream, mut bytes: &[u8]) -> io::Result<()> {
+    while !bytes.is_empty() {
+        match stream.write(bytes) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(len) => bytes = &bytes[len..],
+            Err(error) if error.kind() == ErrorKind::WouldBlock => std::thread::yield_now(),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use crate::chip_8::Chip8;
+use crate::constants;
+
+// A backend presenting the display buffer and reading the keypad, driven by `run` or `run_pass`
+pub trait Renderer {
+    fn render_buffer(&mut self, buffer: &[bool; constants::DISPLAY_LEN]);
+
+    // Called on frames the display did not change, for backends that only process window events
+    // when presenting
+    fn skip_frame(&mut self) {}
+
+    // Called before each timer tick with the sound timer, for backends that beep
+    fn tick(&mut self, _sound_timer: u8) {}
+
+    // Checked before each pass, the run stops once the user quit
+    fn is_open(&self, _frame: u64) -> bool {
+        true
+    }
+
+    // The pressed keys as a mask, None for backends without a keypad which leave the keys as
+    // they are
+    fn pressed_keys(&mut self, _frame: u64) -> Option<u16> {
+        None
+    }
+}
+
+// One pass of the loop shared by the frontends: ticks the timers and presents the display if it
+// changed, applies the keypad, runs the due instructions and sleeps until more are due. Returns
+// false once the user quit or the ROM exited.
+pub fn run_pass(chip8: &mut Chip8, renderer: &mut impl Renderer) -> bool {
+    if !renderer.is_open(chip8.frame()) || chip8.exit_code().is_some() {
+        return false;
+    }
+
+    if chip8.is_timer_decrement_due() {
+        renderer.tick(chip8.sound_timer());
+        chip8.decrement_timers();
+
+        if chip8.take_update_display() {
+            renderer.render_buffer(chip8.display_buffer());
+        } else {
+            renderer.skip_frame();
+        }
+    }
+
+    if let Some(pressed_keys) = renderer.pressed_keys(chip8.frame()) {
+        chip8.set_pressed_keys(pressed_keys);
+    }
+
+    while chip8.is_cycle_due() {
+        chip8.cycle();
+    }
+
+    chip8.sleep_until_due();
+    true
+}
+
+// Presents the initial display, then runs passes until the user quits or the ROM exits
+pub fn run(chip8: &mut Chip8, renderer: &mut impl Renderer) {
+    renderer.render_buffer(chip8.display_buffer());
+    while run_pass(chip8, renderer) {}
+}
@@ -0,0 +1,42 @@
+pub trait Rng {
+    fn next_u8(&mut self) -> u8;
+}
+
+const PCG_MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+const PCG_DEFAULT_STREAM: u64 = 0xDA3E_39CB_94B9_5BDB;
+
+// PCG32 (XSH RR), see https://www.pcg-random.org
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    pub fn new(seed: u64) -> Self {
+        let mut pcg = Pcg32 {
+            state: 0,
+            increment: (PCG_DEFAULT_STREAM << 1) | 1,
+        };
+        pcg.next_u32();
+        pcg.state = pcg.state.wrapping_add(seed);
+        pcg.next_u32();
+        pcg
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state
+            .wrapping_mul(PCG_MULTIPLIER)
+            .wrapping_add(self.increment);
+
+        let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rotation = (old_state >> 59) as u32;
+        xor_shifted.rotate_right(rotation)
+    }
+}
+
+impl Rng for Pcg32 {
+    fn next_u8(&mut self) -> u8 {
+        (self.next_u32() >> 24) as u8
+    }
+}
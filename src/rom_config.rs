@@ -0,0 +1,106 @@
+use std::{fs, io, path::Path};
+
+use serde::Deserialize;
+
+use crate::constants;
+use crate::display_options::DisplayOptions;
+use crate::tone::{ToneOptions, Waveform};
+use crate::touch::TouchControls;
+
+// Beep settings overriding the command line for a single ROM
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioOverrides {
+    pub frequency: Option<f32>,
+    pub volume: Option<u8>,
+    pub waveform: Option<Waveform>,
+}
+
+impl AudioOverrides {
+    pub fn apply(&self, tone: ToneOptions) -> ToneOptions {
+        ToneOptions {
+            frequency: self.frequency.unwrap_or(tone.frequency),
+            volume: self.volume.unwrap_or(tone.volume),
+            waveform: self.waveform.unwrap_or(tone.waveform),
+            ..tone
+        }
+    }
+}
+
+// Border art overriding the command line for a single ROM
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayOverrides {
+    // Relative to the config file once loaded
+    pub border: Option<String>,
+    pub border_rect: Option<(u32, u32, u32, u32)>,
+}
+
+impl DisplayOverrides {
+    pub fn apply(&self, options: DisplayOptions) -> DisplayOptions {
+        // The rectangle on the command line belongs to its border, not to the one of the ROM
+        let border_rect = match self.border {
+            Some(_) => self.border_rect,
+            None => self.border_rect.or(options.border_rect),
+        };
+        DisplayOptions {
+            border: self.border.clone().or(options.border),
+            border_rect,
+            ..options
+        }
+    }
+}
+
+// Settings read from a TOML file next to the ROM, e.g. `game.toml` for `game.ch8`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RomConfig {
+    pub audio: AudioOverrides,
+    pub display: DisplayOverrides,
+    // Enables touch controls even on platforms without them by default
+    pub touch: Option<TouchControls>,
+}
+
+// A missing config file leaves everything as configured on the command line
+pub fn load_rom_config(rom_path: &Path) -> RomConfig {
+    let path = rom_path.with_extension("toml");
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return RomConfig::default(),
+        Err(error) => panic!("Failed to read ROM config: {:?}", error),
+    };
+
+    let mut config: RomConfig = toml::from_str(&contents)
+        .unwrap_or_else(|error| panic!("Failed to parse ROM config {:?}: {}", path, error));
+    if let Some(frequency) = config.audio.frequency {
+        if frequency <= 0.0 {
+            panic!("Invalid beep frequency in ROM config: {}", frequency);
+        }
+    }
+    if let Some(volume) = config.audio.volume {
+        if volume > 100 {
+            panic!(
+                "Invalid volume in ROM config, expected 0 to 100: {}",
+                volume
+            );
+        }
+    }
+    if let Some(border) = &mut config.display.border {
+        if let Some(directory) = path.parent() {
+            *border = directory.join(&*border).to_string_lossy().into_owned();
+        }
+    }
+    if let Some((_, _, 0, _) | (_, _, _, 0)) = config.display.border_rect {
+        panic!("Invalid border rectangle in ROM config, expected a width and height above 0");
+    }
+    if let Some(touch) = &config.touch {
+        if let Some(&key) = touch
+            .keys
+            .iter()
+            .find(|&&key| key as usize >= constants::KEY_COUNT)
+        {
+            panic!("Invalid touch key in ROM config: {:#X}", key);
+        }
+    }
+    config
+}
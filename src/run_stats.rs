@@ -0,0 +1,56 @@
+use std::{fmt::Write, time};
+
+use crate::chip_8::Chip8;
+
+// Totals over a whole run, for tuning performance and comparing frontends
+pub struct RunStats {
+    instructions: u64,
+    wall_time: time::Duration,
+    draws: u64,
+    // None without a window to present to
+    presented_frames: Option<u64>,
+    timer_ticks: u64,
+}
+
+impl RunStats {
+    pub fn build(chip8: &Chip8, wall_time: time::Duration, presented_frames: Option<u64>) -> Self {
+        RunStats {
+            instructions: chip8.instruction_count(),
+            wall_time,
+            draws: chip8.draw_count(),
+            presented_frames,
+            timer_ticks: chip8.frame(),
+        }
+    }
+
+    fn per_second(&self, count: u64) -> u64 {
+        (count as f64 / self.wall_time.as_secs_f64().max(f64::EPSILON)).round() as u64
+    }
+
+    pub fn text_report(&self) -> String {
+        let mut text = "Run statistics\n".to_string();
+        let mut line = |name: &str, value: String| writeln!(text, "{:<22} {}", name, value);
+        line("Instructions executed", self.instructions.to_string()).unwrap();
+        line(
+            "Wall-clock time",
+            format!("{:.3} s", self.wall_time.as_secs_f64()),
+        )
+        .unwrap();
+        line(
+            "Instructions/second",
+            self.per_second(self.instructions).to_string(),
+        )
+        .unwrap();
+        line("Draw calls", self.draws.to_string()).unwrap();
+        match self.presented_frames {
+            Some(frames) => line(
+                "Frames presented",
+                format!("{} ({} per second)", frames, self.per_second(frames)),
+            ),
+            None => line("Frames presented", "none, headless".to_string()),
+        }
+        .unwrap();
+        line("Timer ticks", self.timer_ticks.to_string()).unwrap();
+        text
+    }
+}
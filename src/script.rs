@@ -0,0 +1,254 @@
+use std::{cell::RefCell, collections::BTreeSet, fs, path::Path, rc::Rc};
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::chip_8::{Chip8, CycleHook};
+use crate::condition::Operand;
+use crate::constants;
+use crate::symbols::Symbols;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+// What scripts see of the machine, copied before a hook runs, with the changes made by the
+// script written back once it returns
+struct Machine {
+    registers: [u8; constants::REGISTER_COUNT],
+    index_register: u16,
+    program_counter: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    ram: Vec<u8>,
+    frame: u64,
+    changes: Vec<(Operand, u16)>,
+}
+
+impl Machine {
+    fn new() -> Self {
+        Machine {
+            registers: [0; constants::REGISTER_COUNT],
+            index_register: 0,
+            program_counter: constants::PROGRAM_START,
+            delay_timer: 0,
+            sound_timer: 0,
+            ram: vec![0; constants::RAM_LEN],
+            frame: 0,
+            changes: Vec::new(),
+        }
+    }
+
+    fn copy_from(&mut self, chip8: &Chip8) {
+        self.registers = *chip8.registers();
+        self.index_register = chip8.index_register();
+        self.program_counter = chip8.program_counter();
+        self.delay_timer = chip8.delay_timer();
+        self.sound_timer = chip8.sound_timer();
+        self.ram.copy_from_slice(chip8.ram());
+        self.frame = chip8.frame();
+    }
+
+    fn value(&self, operand: Operand) -> i64 {
+        (match operand {
+            Operand::Register(register) => self.registers[register] as u16,
+            Operand::Index => self.index_register,
+            Operand::ProgramCounter => self.program_counter as u16,
+            Operand::DelayTimer => self.delay_timer as u16,
+            Operand::SoundTimer => self.sound_timer as u16,
+            Operand::Memory(address) => self.ram[address] as u16,
+            Operand::Value(value) => value,
+        }) as i64
+    }
+
+    // Later reads in the same hook see the new value
+    fn set(&mut self, operand: Operand, value: i64) -> ScriptResult<()> {
+        let value = u16::try_from(value).map_err(|_| format!("Invalid value: {}", value))?;
+        operand.check_value(value)?;
+        match operand {
+            Operand::Register(register) => self.registers[register] = value as u8,
+            Operand::Index => self.index_register = value,
+            Operand::ProgramCounter => self.program_counter = value as usize,
+            Operand::DelayTimer => self.delay_timer = value as u8,
+            Operand::SoundTimer => self.sound_timer = value as u8,
+            Operand::Memory(address) => self.ram[address] = value as u8,
+            Operand::Value(_) => {}
+        }
+        self.changes.push((operand, value));
+        Ok(())
+    }
+}
+
+fn register(x: i64) -> ScriptResult<Operand> {
+    usize::try_from(x)
+        .ok()
+        .filter(|&x| x < constants::REGISTER_COUNT)
+        .map(Operand::Register)
+        .ok_or_else(|| format!("Invalid register: {}", x).into())
+}
+
+fn address(value: i64) -> ScriptResult<usize> {
+    usize::try_from(value)
+        .ok()
+        .filter(|&address| address < constants::RAM_LEN)
+        .ok_or_else(|| format!("Invalid address: {}", value).into())
+}
+
+fn memory(value: i64) -> ScriptResult<Operand> {
+    address(value).map(Operand::Memory)
+}
+
+// Registers the functions scripts use to read and change the machine
+fn register_machine_api(engine: &mut Engine, machine: &Rc<RefCell<Machine>>) {
+    let getters: [(&str, Operand); 4] = [
+        ("i", Operand::Index),
+        ("pc", Operand::ProgramCounter),
+        ("dt", Operand::DelayTimer),
+        ("st", Operand::SoundTimer),
+    ];
+    for (name, operand) in getters {
+        let reader = Rc::clone(machine);
+        engine.register_fn(name, move || reader.borrow().value(operand));
+        let writer = Rc::clone(machine);
+        engine.register_fn(format!("set_{}", name), move |value: i64| {
+            writer.borrow_mut().set(operand, value)
+        });
+    }
+
+    let reader = Rc::clone(machine);
+    engine.register_fn("v", move |x: i64| -> ScriptResult<i64> {
+        Ok(reader.borrow().value(register(x)?))
+    });
+    let writer = Rc::clone(machine);
+    engine.register_fn("set_v", move |x: i64, value: i64| {
+        writer.borrow_mut().set(register(x)?, value)
+    });
+    let reader = Rc::clone(machine);
+    engine.register_fn("peek", move |address: i64| -> ScriptResult<i64> {
+        Ok(reader.borrow().value(memory(address)?))
+    });
+    let writer = Rc::clone(machine);
+    engine.register_fn("poke", move |address: i64, value: i64| {
+        writer.borrow_mut().set(memory(address)?, value)
+    });
+    let reader = Rc::clone(machine);
+    engine.register_fn("frame", move || reader.borrow().frame as i64);
+}
+
+// A Rhai script with hooks into the running machine, any of which it may define:
+// `on_cycle(address, instruction)` after every instruction, `on_breakpoint(address)` when
+// reaching an address passed to `break_at` and `on_draw()` after the screen is drawn to or
+// cleared. Hooks read and change the machine with `v(x)`, `set_v(x, value)`, `i()`, `pc()`,
+// `dt()`, `st()` and their `set_` counterparts, `peek(address)`, `poke(address, value)` and
+// `frame()`, and keep state across calls in `this`, which starts as an empty map. Names from the
+// symbol file resolve with `address(name)`.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Dynamic,
+    machine: Rc<RefCell<Machine>>,
+    breakpoints: Rc<RefCell<BTreeSet<usize>>>,
+    has_on_cycle: bool,
+    has_on_breakpoint: bool,
+    has_on_draw: bool,
+    // Set once a hook fails, the script stops running then
+    is_stopped: bool,
+}
+
+impl Script {
+    // Runs the top level of the script, which usually sets breakpoints
+    pub fn build(source: &str, symbols: Symbols) -> Result<Self, String> {
+        let machine = Rc::new(RefCell::new(Machine::new()));
+        let breakpoints = Rc::new(RefCell::new(BTreeSet::new()));
+
+        let mut engine = Engine::new();
+        register_machine_api(&mut engine, &machine);
+        let added_breakpoints = Rc::clone(&breakpoints);
+        engine.register_fn("break_at", move |value: i64| -> ScriptResult<()> {
+            added_breakpoints.borrow_mut().insert(address(value)?);
+            Ok(())
+        });
+        engine.register_fn("address", move |name: &str| -> ScriptResult<i64> {
+            symbols
+                .address(name)
+                .map(|address| address as i64)
+                .ok_or_else(|| format!("Unknown symbol: {}", name).into())
+        });
+
+        let ast = engine.compile(source).map_err(|error| error.to_string())?;
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|error| error.to_string())?;
+
+        let has_function = |name: &str, params: usize| {
+            ast.iter_functions()
+                .any(|function| function.name == name && function.params.len() == params)
+        };
+        Ok(Script {
+            has_on_cycle: has_function("on_cycle", 2),
+            has_on_breakpoint: has_function("on_breakpoint", 1),
+            has_on_draw: has_function("on_draw", 0),
+            engine,
+            ast,
+            scope,
+            state: Dynamic::from_map(Map::new()),
+            machine,
+            breakpoints,
+            is_stopped: false,
+        })
+    }
+
+    pub fn load(path: &Path, symbols: Symbols) -> Self {
+        let source = fs::read_to_string(path)
+            .unwrap_or_else(|error| panic!("Failed to read script: {:?}", error));
+        Script::build(&source, symbols)
+            .unwrap_or_else(|error| panic!("Failed to run script {:?}: {}", path, error))
+    }
+
+    fn call(&mut self, chip8: &mut Chip8, name: &str, arguments: Vec<Dynamic>) {
+        self.machine.borrow_mut().copy_from(chip8);
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .rewind_scope(false)
+            .bind_this_ptr(&mut self.state);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            name,
+            arguments,
+        );
+        for (operand, value) in self.machine.borrow_mut().changes.drain(..) {
+            operand.set(chip8, value);
+        }
+        if let Err(error) = result {
+            eprintln!("Script error in {}, script stopped: {}", name, error);
+            self.is_stopped = true;
+        }
+    }
+}
+
+impl CycleHook for Script {
+    fn after_cycle(&mut self, chip8: &mut Chip8, program_counter: usize, instruction: u16) {
+        if self.is_stopped {
+            return;
+        }
+        if self.has_on_cycle {
+            let arguments = vec![
+                Dynamic::from_int(program_counter as i64),
+                Dynamic::from_int(instruction as i64),
+            ];
+            self.call(chip8, "on_cycle", arguments);
+        }
+        if self.has_on_draw && (instruction >> 12 == 0xD || instruction == 0x00E0) {
+            self.call(chip8, "on_draw", Vec::new());
+        }
+        let address = chip8.program_counter();
+        if self.has_on_breakpoint && self.breakpoints.borrow().contains(&address) {
+            self.call(
+                chip8,
+                "on_breakpoint",
+                vec![Dynamic::from_int(address as i64)],
+            );
+        }
+    }
+}
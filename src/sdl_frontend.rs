@@ -0,0 +1,981 @@
+use std::{collections::HashMap, fmt::Write, fs, io, path::PathBuf, time};
+
+use clap::ValueEnum;
+use sdl2::{
+    self,
+    controller::{Button, GameController},
+    event::{Event, WindowEvent},
+    keyboard::{Keycode, Mod, Scancode},
+    mouse::MouseButton,
+};
+
+use crate::audio_options::AudioOptions;
+use crate::audio_sink::AudioSink;
+use crate::beep;
+use crate::chip_8::{Chip8, MachineState};
+use crate::color::Theme;
+use crate::constants;
+use crate::dap_server::DapServer;
+use crate::debugger::{Debugger, DebuggerCommand, WatchedAccess};
+use crate::display::Display;
+use crate::display_options::DisplayOptions;
+use crate::gdb_stub::GdbStub;
+use crate::hotkeys::{Action, Hotkeys};
+use crate::keymap::Keymap;
+use crate::profile::Pacing;
+use crate::recorder::Recorder;
+use crate::remote_debugger::RemoteDebugger;
+use crate::renderer::Renderer;
+use crate::source_map::SourceMap;
+use crate::symbols::Symbols;
+use crate::tone::ToneOptions;
+use crate::touch::TouchControls;
+use crate::video_recorder::VideoRecorder;
+use crate::websocket_server::WebSocketServer;
+
+// Panics on names SDL does not know, as a mistyped binding would leave a key unusable
+fn resolve_keymap(keymap: &Keymap) -> HashMap<Scancode, u8> {
+    keymap
+        .bindings()
+        .map(|(name, key)| {
+            let scancode = Scancode::from_name(name)
+                .unwrap_or_else(|| panic!("Unknown key in keymap: {:?}", name));
+            (scancode, key)
+        })
+        .collect()
+}
+
+fn resolve_controller_map(controller_map: &Keymap) -> HashMap<Button, u8> {
+    controller_map
+        .bindings()
+        .map(|(name, key)| {
+            let button = Button::from_string(name)
+                .unwrap_or_else(|| panic!("Unknown button in controller map: {:?}", name));
+            (button, key)
+        })
+        .collect()
+}
+
+// Reduces the modifiers to the ones hotkeys can use, without telling left and right apart
+fn normalize_mod(keymod: Mod) -> Mod {
+    let mut normalized = Mod::NOMOD;
+    if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+        normalized |= Mod::LCTRLMOD;
+    }
+    if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) {
+        normalized |= Mod::LALTMOD;
+    }
+    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+        normalized |= Mod::LSHIFTMOD;
+    }
+    normalized
+}
+
+fn resolve_hotkeys(hotkeys: &Hotkeys) -> HashMap<(Keycode, Mod), Action> {
+    hotkeys
+        .bindings()
+        .map(|(hotkey, action)| {
+            let keycode = Keycode::from_name(&hotkey.key)
+                .unwrap_or_else(|| panic!("Unknown key in hotkeys: {:?}", hotkey.key));
+            let mut keymod = Mod::NOMOD;
+            keymod.set(Mod::LCTRLMOD, hotkey.ctrl);
+            keymod.set(Mod::LALTMOD, hotkey.alt);
+            keymod.set(Mod::LSHIFTMOD, hotkey.shift);
+            ((keycode, keymod), action)
+        })
+        .collect()
+}
+
+// Waits for the client to connect, gdb, DAP and WebSocket exclude each other
+fn remote_debugger(
+    gdb_port: Option<u16>,
+    dap_port: Option<u16>,
+    websocket_port: Option<u16>,
+    symbols: &Symbols,
+    source_map: &SourceMap,
+) -> Option<Box<dyn RemoteDebugger>> {
+    let remote_debugger: Box<dyn RemoteDebugger> = match (gdb_port, dap_port, websocket_port) {
+        (Some(port), _, _) => Box::new(
+            GdbStub::listen(port)
+                .unwrap_or_else(|error| panic!("Failed to start gdb stub: {:?}", error)),
+        ),
+        (None, Some(port), _) => Box::new(
+            DapServer::listen(port, symbols.clone(), source_map.clone())
+                .unwrap_or_else(|error| panic!("Failed to start DAP server: {:?}", error)),
+        ),
+        (None, None, Some(port)) => Box::new(
+            WebSocketServer::listen(port)
+                .unwrap_or_else(|error| panic!("Failed to start WebSocket server: {:?}", error)),
+        ),
+        (None, None, None) => return None,
+    };
+    Some(remote_debugger)
+}
+
+fn map_mouse_button_to_mask(mouse_button: MouseButton) -> u8 {
+    match mouse_button {
+        MouseButton::Left => 0x01,
+        MouseButton::Right => 0x02,
+        MouseButton::Middle => 0x04,
+        _ => 0x00,
+    }
+}
+
+pub struct FrontendOptions {
+    pub mmio_mouse: bool,
+    pub idle_timeout: Option<u64>,
+    pub screenshot_directory: PathBuf,
+    pub rom_name: String,
+    pub title: String,
+    pub theme: Theme,
+    pub tone: ToneOptions,
+    pub audio: AudioOptions,
+    pub keymap: Keymap,
+    // Where keys bound at runtime are saved
+    pub keymap_path: PathBuf,
+    // Active alongside the keymap, winning conflicts, and kept when keys are bound at runtime
+    pub player2_keymap: Option<Keymap>,
+    pub hotkeys: Hotkeys,
+    pub fast_forward_factor: u32,
+    pub confirm_exit: bool,
+    pub controller_map: Keymap,
+    pub touch_controls: Option<TouchControls>,
+    pub record: Option<PathBuf>,
+    pub pacing: Pacing,
+    pub show_stats: bool,
+    pub show_keypad: bool,
+    pub debug: bool,
+    // Opens the debugger without stepping from the start
+    pub breakpoints: Vec<usize>,
+    // Waits for gdb to connect on this port and starts stepping
+    pub gdb_port: Option<u16>,
+    // The same for a Debug Adapter Protocol client such as VS Code
+    pub dap_port: Option<u16>,
+    // The same for a browser debugger speaking JSON over WebSocket
+    pub websocket_port: Option<u16>,
+    pub symbols: Symbols,
+    // Source lines of the instructions, for ROMs assembled from Octo source
+    pub source_map: SourceMap,
+}
+
+pub struct SdlFrontend {
+    chip8: Chip8,
+
+    display: Display,
+    debugger: Option<Debugger>,
+    remote_debugger: Option<Box<dyn RemoteDebugger>>,
+    // Filled before each instruction while debugging, see `Debugger::watched_accesses`
+    watched_accesses: Vec<WatchedAccess>,
+    beep: Box<dyn AudioSink>,
+    tone: ToneOptions,
+    volume: u8,
+    is_muted: bool,
+    sdl_context: sdl2::Sdl,
+    keymap: HashMap<Scancode, u8>,
+    keymap_path: PathBuf,
+    player2_keymap: HashMap<Scancode, u8>,
+    // Scancode names bound so far while binding keys, the next key is their count
+    new_bindings: Option<Vec<(String, u8)>>,
+    // Whether the last key pressed while binding was already bound and has to be replaced
+    is_binding_taken: bool,
+    hotkeys: HashMap<(Keycode, Mod), Action>,
+    game_controller_subsystem: sdl2::GameControllerSubsystem,
+    // Opened as they are plugged in, SDL also reports those connected at startup that way
+    controllers: Vec<GameController>,
+    controller_map: HashMap<Button, u8>,
+    touch_controls: Option<TouchControls>,
+    vsync: bool,
+    mmio_mouse: bool,
+    idle_timeout: Option<u64>,
+    screenshot_directory: PathBuf,
+    rom_name: String,
+    title: String,
+    // The shown window title and a buffer the next one is written to, so updates do not allocate
+    window_title: String,
+    next_window_title: String,
+    theme: Theme,
+    pacing: Pacing,
+    // Waiting for the debugger to step in debug mode
+    is_stepping: bool,
+    is_paused: bool,
+    confirm_exit: bool,
+    is_confirming_exit: bool,
+    is_fast_forwarding: bool,
+    fast_forward_factor: u32,
+    save_state: Option<MachineState>,
+    // Percentage of full speed while not paused, see `SLOW_MOTION_SPEEDS`
+    speed: u32,
+
+    last_activity_frame: u64,
+    mouse_position: Option<(u8, u8)>,
+    mouse_buttons: u8,
+    // Held down on the keypad overlay with the mouse
+    clicked_key: Option<u8>,
+    // Keys held down by each finger with touch controls
+    touched_keys: HashMap<i64, u8>,
+    recorder: Option<Recorder>,
+    video_recorder: Option<VideoRecorder>,
+
+    show_stats: bool,
+    show_keypad: bool,
+    stats_start: time::Instant,
+    rendered_frames: u32,
+    executed_cycles: u32,
+    frames_per_second: u32,
+    instructions_per_second: u32,
+    presented_frames: u64,
+}
+
+impl SdlFrontend {
+    pub fn build(chip8: Chip8, display_options: DisplayOptions, options: FrontendOptions) -> Self {
+        let vsync = display_options.vsync;
+        let sdl_context = sdl2::init().unwrap();
+        let mut display = Display::build(&sdl_context, display_options);
+        display.set_title(&options.title);
+        let game_controller_subsystem = sdl_context.game_controller().unwrap();
+        let beep = beep::build_audio_sink(&sdl_context, options.tone, options.audio);
+        let video_recorder = options.record.map(|path| {
+            VideoRecorder::start(&path, display.pixel_size(), options.tone)
+                .unwrap_or_else(|error| panic!("Failed to start ffmpeg: {:?}", error))
+        });
+
+        let player2_keymap = options
+            .player2_keymap
+            .as_ref()
+            .map(resolve_keymap)
+            .unwrap_or_default();
+        let mut keymap = resolve_keymap(&options.keymap);
+        keymap.extend(&player2_keymap);
+
+        SdlFrontend {
+            chip8,
+
+            sdl_context,
+            keymap,
+            keymap_path: options.keymap_path,
+            player2_keymap,
+            new_bindings: None,
+            is_binding_taken: false,
+            hotkeys: resolve_hotkeys(&options.hotkeys),
+            game_controller_subsystem,
+            controllers: Vec::new(),
+            controller_map: resolve_controller_map(&options.controller_map),
+            touch_controls: options.touch_controls,
+            beep,
+            tone: options.tone,
+            volume: options.tone.volume,
+            is_muted: false,
+            display,
+            debugger: (options.debug || !options.breakpoints.is_empty()).then(|| {
+                Debugger::build(
+                    &options.breakpoints,
+                    options.symbols.clone(),
+                    options.source_map.clone(),
+                )
+            }),
+            remote_debugger: remote_debugger(
+                options.gdb_port,
+                options.dap_port,
+                options.websocket_port,
+                &options.symbols,
+                &options.source_map,
+            ),
+            watched_accesses: Vec::new(),
+            vsync,
+            mmio_mouse: options.mmio_mouse,
+            idle_timeout: options.idle_timeout,
+            screenshot_directory: options.screenshot_directory,
+            rom_name: options.rom_name,
+            title: options.title,
+            window_title: String::new(),
+            next_window_title: String::new(),
+            theme: options.theme,
+            pacing: options.pacing,
+            is_stepping: options.debug
+                || options.gdb_port.is_some()
+                || options.dap_port.is_some()
+                || options.websocket_port.is_some(),
+            is_paused: false,
+            confirm_exit: options.confirm_exit,
+            is_confirming_exit: false,
+            is_fast_forwarding: false,
+            fast_forward_factor: options.fast_forward_factor,
+            save_state: None,
+            speed: 100,
+
+            last_activity_frame: 0,
+            mouse_position: None,
+            mouse_buttons: 0,
+            clicked_key: None,
+            touched_keys: HashMap::new(),
+            recorder: None,
+            video_recorder,
+
+            show_stats: options.show_stats,
+            show_keypad: options.show_keypad,
+            stats_start: time::Instant::now(),
+            rendered_frames: 0,
+            executed_cycles: 0,
+            frames_per_second: 0,
+            instructions_per_second: 0,
+            presented_frames: 0,
+        }
+    }
+
+    pub fn into_chip8(self) -> Chip8 {
+        self.chip8
+    }
+
+    // Frames shown since the start, for the statistics of a run
+    pub fn presented_frames(&self) -> u64 {
+        self.presented_frames
+    }
+
+    pub fn run(&mut self) {
+        let mut event_pump = self.sdl_context.event_pump().unwrap();
+
+        'running: loop {
+            if self.chip8.is_timer_decrement_due() {
+                let is_beeping = self.chip8.sound_timer() > 0;
+                // Frames advanced one at a time would each queue the whole remaining sound
+                self.beep.update(if self.is_paused {
+                    0
+                } else {
+                    self.chip8.sound_timer()
+                });
+                self.chip8.decrement_timers();
+
+                if self.mmio_mouse {
+                    self.chip8
+                        .set_mouse_state(self.mouse_position, self.mouse_buttons);
+                }
+
+                let mut needs_render = self.display.advance_frame(self.chip8.display_buffer());
+                needs_render |= self.display.set_flashing(is_beeping);
+                if let Some(idle_timeout) = self.idle_timeout {
+                    let idle_frames = self.chip8.frame() - self.last_activity_frame;
+                    let idle_step = idle_frames
+                        .checked_sub(idle_timeout * constants::FRAME_RATE)
+                        .map(|frames| frames / constants::IDLE_SHIFT_INTERVAL);
+                    needs_render |= self.display.set_idle_step(idle_step);
+                }
+                if self.update_stats() {
+                    self.update_title();
+                }
+                if self.show_stats {
+                    self.update_overlay();
+                    needs_render = true;
+                }
+                if self.show_keypad {
+                    self.update_keypad();
+                    needs_render = true;
+                }
+                if needs_render {
+                    self.render();
+                }
+                if let Some(debugger) = &mut self.debugger {
+                    debugger.render(&self.chip8);
+                }
+                if let Some(recorder) = &mut self.recorder {
+                    recorder.push_frame(self.display.pixels(), is_beeping);
+                }
+                if let Some(video_recorder) = &mut self.video_recorder {
+                    if let Err(error) = video_recorder.push_frame(self.display.pixels(), is_beeping)
+                    {
+                        eprintln!("Failed to record frame, recording stopped: {:?}", error);
+                        self.video_recorder = None;
+                    }
+                }
+            }
+
+            let pressed_keys = event_pump
+                .keyboard_state()
+                .pressed_scancodes()
+                .filter_map(|scancode| self.keymap.get(&scancode).copied())
+                .chain(self.clicked_key)
+                .chain(self.touched_keys.values().copied())
+                .fold(self.controller_keys(), |mask, key| mask | (1 << key));
+            self.chip8.set_pressed_keys(pressed_keys);
+
+            for event in event_pump.poll_iter() {
+                if matches!(
+                    event,
+                    Event::KeyDown { .. }
+                        | Event::MouseMotion { .. }
+                        | Event::MouseButtonDown { .. }
+                        | Event::ControllerButtonDown { .. }
+                        | Event::FingerDown { .. }
+                ) {
+                    self.last_activity_frame = self.chip8.frame();
+                }
+
+                match event {
+                    // With the debugger open, closing either window quits
+                    Event::Quit { .. }
+                    | Event::Window {
+                        win_event: WindowEvent::Close,
+                        ..
+                    } => break 'running,
+                    Event::KeyDown {
+                        scancode: Some(scancode),
+                        repeat: false,
+                        ..
+                    } if self.new_bindings.is_some() => self.bind_key(scancode),
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        keymod,
+                        ..
+                    } => {
+                        let action = self.hotkeys.get(&(keycode, normalize_mod(keymod)));
+                        if action == Some(&Action::Quit)
+                            && (!self.confirm_exit || self.is_confirming_exit)
+                        {
+                            break 'running;
+                        }
+
+                        // Any other key cancels quitting
+                        if self.is_confirming_exit {
+                            self.set_confirming_exit(false);
+                        } else if let Some(&action) = action {
+                            self.run_action(action);
+                        }
+                    }
+                    // Held keys are released without their modifiers mattering
+                    Event::KeyUp {
+                        keycode: Some(keycode),
+                        ..
+                    } if self.is_fast_forwarding
+                        && self.hotkeys.iter().any(|(&(hotkey_keycode, _), &action)| {
+                            hotkey_keycode == keycode && action == Action::FastForward
+                        }) =>
+                    {
+                        self.is_fast_forwarding = false;
+                        self.apply_speed();
+                    }
+                    // Touch controls take over taps, which SDL also reports as clicks
+                    Event::MouseMotion { which, .. }
+                    | Event::MouseButtonDown { which, .. }
+                    | Event::MouseButtonUp { which, .. }
+                        if which == constants::TOUCH_MOUSE_ID && self.touch_controls.is_some() => {}
+                    Event::MouseMotion { x, y, .. } => {
+                        self.mouse_position = self.display.to_display_coordinates(x, y);
+                    }
+                    Event::MouseButtonDown {
+                        mouse_btn, x, y, ..
+                    } => {
+                        self.mouse_buttons |= map_mouse_button_to_mask(mouse_btn);
+                        // SDL turns taps into left clicks, so this also works on touch screens
+                        if mouse_btn == MouseButton::Left {
+                            self.clicked_key = self.display.keypad_key_at(x, y);
+                        }
+                    }
+                    Event::MouseButtonUp { mouse_btn, .. } => {
+                        self.mouse_buttons &= !map_mouse_button_to_mask(mouse_btn);
+                        if mouse_btn == MouseButton::Left {
+                            self.clicked_key = None;
+                        }
+                    }
+                    Event::FingerDown {
+                        finger_id, x, y, ..
+                    }
+                    | Event::FingerMotion {
+                        finger_id, x, y, ..
+                    } => {
+                        if let Some(touch_controls) = &self.touch_controls {
+                            self.touched_keys
+                                .insert(finger_id, touch_controls.key_at(x, y));
+                        }
+                    }
+                    Event::FingerUp { finger_id, .. } => {
+                        self.touched_keys.remove(&finger_id);
+                    }
+                    Event::ControllerDeviceAdded { which, .. } => self.open_controller(which),
+                    Event::ControllerDeviceRemoved { which, .. } => {
+                        self.controllers
+                            .retain(|controller| controller.instance_id() != which);
+                    }
+                    _ => {}
+                }
+            }
+
+            let remote_command = self
+                .remote_debugger
+                .as_mut()
+                .and_then(|remote_debugger| remote_debugger.poll_command(&mut self.chip8));
+            let console_command = self
+                .debugger
+                .as_mut()
+                .and_then(|debugger| debugger.poll_command(&self.chip8, self.is_stepping));
+            // Both may have a command in the same pass, and a remote client waits for the answer
+            // to its own
+            for command in [console_command, remote_command].into_iter().flatten() {
+                if !self.run_debugger_command(command) {
+                    break 'running;
+                }
+            }
+            if self.is_stepping {
+                if let Some(remote_debugger) = &mut self.remote_debugger {
+                    remote_debugger.report_stop();
+                }
+            }
+
+            while self.chip8.is_cycle_due() {
+                self.cycle();
+            }
+            if self.chip8.exit_code().is_some() {
+                break;
+            }
+
+            // With vsync presenting blocks until the next refresh, so do it at most once per pass
+            if self.vsync && self.chip8.take_update_display() {
+                self.render();
+            }
+
+            match self.pacing {
+                Pacing::Instruction => self.chip8.sleep_until_due(),
+                Pacing::Frame => self.chip8.sleep_until_timer_decrement_due(),
+            }
+        }
+
+        if let Some(video_recorder) = self.video_recorder.take() {
+            if let Err(error) = video_recorder.finish() {
+                eprintln!("Failed to save recording: {:?}", error);
+            }
+        }
+    }
+
+    // Returns false once the debugger quits
+    fn run_debugger_command(&mut self, command: DebuggerCommand) -> bool {
+        match command {
+            DebuggerCommand::Step(count) => {
+                self.set_stepping(true);
+                for _ in 0..count {
+                    if self.cycle() {
+                        break;
+                    }
+                }
+            }
+            DebuggerCommand::StepBack(count) => {
+                self.set_stepping(true);
+                let state = self
+                    .debugger
+                    .as_mut()
+                    .and_then(|debugger| debugger.rewind(count));
+                if let Some(state) = state {
+                    self.chip8.load_state(&state);
+                    self.chip8.take_update_display();
+                    self.render();
+                }
+            }
+            DebuggerCommand::Continue => self.set_stepping(false),
+            DebuggerCommand::Pause => self.set_stepping(true),
+            DebuggerCommand::Set(target, value) => target.set(&mut self.chip8, value),
+            DebuggerCommand::Quit => return false,
+        }
+        true
+    }
+
+    // A controller that fails to open is skipped rather than ending the game
+    fn open_controller(&mut self, joystick_index: u32) {
+        match self.game_controller_subsystem.open(joystick_index) {
+            Ok(controller) => self.controllers.push(controller),
+            Err(error) => eprintln!("Failed to open game controller: {:?}", error),
+        }
+    }
+
+    fn controller_keys(&self) -> u16 {
+        self.controllers
+            .iter()
+            .flat_map(|controller| {
+                self.controller_map
+                    .iter()
+                    .filter(|(&button, _)| controller.button(button))
+            })
+            .fold(0u16, |mask, (_, &key)| mask | (1 << key))
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        self.display.toggle_fullscreen();
+        self.render();
+    }
+
+    fn run_action(&mut self, action: Action) {
+        match action {
+            // Only reached when quitting needs to be confirmed, the event loop ends otherwise
+            Action::Quit => self.set_confirming_exit(true),
+            Action::Pause => self.toggle_pause(),
+            Action::FrameAdvance => self.advance_frame(),
+            Action::SlowMotion => self.cycle_speed(),
+            Action::TakeOverReplay => self.chip8.stop_input_playback(),
+            Action::Reset => self.chip8.reset(),
+            Action::SaveState => self.save_state = Some(self.chip8.save_state()),
+            Action::LoadState => {
+                if let Some(state) = &self.save_state {
+                    self.chip8.load_state(state);
+                }
+            }
+            Action::SpeedDown => self.change_speed(false),
+            Action::SpeedUp => self.change_speed(true),
+            Action::FastForward => {
+                self.is_fast_forwarding = true;
+                self.apply_speed();
+            }
+            Action::VolumeDown => {
+                self.set_volume(self.volume.saturating_sub(constants::VOLUME_STEP))
+            }
+            Action::VolumeUp => self.set_volume((self.volume + constants::VOLUME_STEP).min(100)),
+            Action::Mute => self.toggle_mute(),
+            Action::Screenshot => self.save_screenshot(),
+            Action::Record => self.toggle_recording(),
+            Action::Fullscreen => self.toggle_fullscreen(),
+            Action::Stats => self.toggle_stats(),
+            Action::Keypad => self.toggle_keypad(),
+            Action::Theme => self.cycle_theme(),
+            Action::BindKeys => self.set_new_bindings(Some(Vec::new())),
+            Action::Step => {
+                if self.is_stepping {
+                    self.cycle();
+                }
+            }
+        }
+    }
+
+    // Pauses while binding keys
+    fn set_new_bindings(&mut self, new_bindings: Option<Vec<(String, u8)>>) {
+        self.new_bindings = new_bindings;
+        self.apply_speed();
+        self.update_overlay();
+        self.render();
+    }
+
+    // Escape cancels, the old keymap stays in place until all keys are bound. Keys bound already,
+    // including those of player 2, are refused and the same key is asked for again.
+    fn bind_key(&mut self, scancode: Scancode) {
+        let Some(mut new_bindings) = self.new_bindings.take() else {
+            return;
+        };
+        if scancode == Scancode::Escape {
+            self.is_binding_taken = false;
+            self.set_new_bindings(None);
+            return;
+        }
+
+        self.is_binding_taken = new_bindings.iter().any(|(name, _)| name == scancode.name())
+            || self.player2_keymap.contains_key(&scancode);
+        if self.is_binding_taken {
+            self.set_new_bindings(Some(new_bindings));
+            return;
+        }
+
+        let key = new_bindings.len() as u8;
+        new_bindings.push((scancode.name().to_string(), key));
+        if new_bindings.len() < constants::KEY_COUNT {
+            self.set_new_bindings(Some(new_bindings));
+            return;
+        }
+
+        let keymap = Keymap::from_bindings(&new_bindings);
+        match keymap.save(&self.keymap_path) {
+            Ok(()) => println!("Saved keymap to {:?}", self.keymap_path),
+            Err(error) => eprintln!("Failed to save keymap: {:?}", error),
+        }
+        self.keymap = resolve_keymap(&keymap);
+        self.keymap.extend(&self.player2_keymap);
+        self.set_new_bindings(None);
+    }
+
+    // Pauses and asks to press the quit key again
+    fn set_confirming_exit(&mut self, is_confirming_exit: bool) {
+        self.is_confirming_exit = is_confirming_exit;
+        self.apply_speed();
+        self.update_overlay();
+        self.render();
+    }
+
+    fn set_stepping(&mut self, is_stepping: bool) {
+        self.is_stepping = is_stepping;
+        self.chip8.set_debug(is_stepping);
+        self.update_title();
+    }
+
+    fn toggle_pause(&mut self) {
+        self.is_paused = !self.is_paused;
+        self.apply_speed();
+    }
+
+    // Pauses first when running, then lets exactly one frame of time pass for the core
+    fn advance_frame(&mut self) {
+        if !self.is_paused {
+            self.toggle_pause();
+        }
+        self.chip8.advance_time(constants::TIMER_DECREMENT_TIME);
+    }
+
+    fn cycle_speed(&mut self) {
+        let index = constants::SLOW_MOTION_SPEEDS
+            .iter()
+            .position(|&speed| speed == self.speed)
+            .unwrap_or(0);
+        self.set_speed(
+            constants::SLOW_MOTION_SPEEDS[(index + 1) % constants::SLOW_MOTION_SPEEDS.len()],
+        );
+    }
+
+    // Steps to the next speed in `SPEED_STEPS`, staying at the ends
+    fn change_speed(&mut self, faster: bool) {
+        let speed = if faster {
+            constants::SPEED_STEPS
+                .iter()
+                .find(|&&speed| speed > self.speed)
+        } else {
+            constants::SPEED_STEPS
+                .iter()
+                .rev()
+                .find(|&&speed| speed < self.speed)
+        };
+        if let Some(&speed) = speed {
+            self.set_speed(speed);
+        }
+    }
+
+    fn set_speed(&mut self, speed: u32) {
+        self.speed = speed;
+        self.apply_speed();
+    }
+
+    fn apply_speed(&mut self) {
+        let speed = if self.is_paused || self.is_confirming_exit || self.new_bindings.is_some() {
+            0
+        } else if self.is_fast_forwarding {
+            self.speed * self.fast_forward_factor
+        } else {
+            self.speed
+        };
+        // Key repeats while fast forwarding would otherwise keep resetting the speed
+        if speed != self.chip8.speed() {
+            self.chip8.set_speed(speed);
+        }
+        self.update_title();
+    }
+
+    fn toggle_stats(&mut self) {
+        self.show_stats = !self.show_stats;
+        self.update_overlay();
+        self.render();
+    }
+
+    fn toggle_keypad(&mut self) {
+        self.show_keypad = !self.show_keypad;
+        self.update_keypad();
+        self.render();
+    }
+
+    fn set_volume(&mut self, volume: u8) {
+        self.volume = volume;
+        self.is_muted = false;
+        self.beep.set_volume(volume);
+        self.update_title();
+    }
+
+    fn toggle_mute(&mut self) {
+        self.is_muted = !self.is_muted;
+        self.beep
+            .set_volume(if self.is_muted { 0 } else { self.volume });
+        self.update_title();
+    }
+
+    // Switches to the next built-in theme, replacing any custom colors
+    fn cycle_theme(&mut self) {
+        let themes = Theme::value_variants();
+        let index = themes
+            .iter()
+            .position(|&theme| theme == self.theme)
+            .unwrap();
+        self.theme = themes[(index + 1) % themes.len()];
+        self.display
+            .set_colors(self.theme.background_color(), self.theme.foreground_color());
+        self.render();
+        println!(
+            "Switched to theme {}",
+            self.theme.to_possible_value().unwrap().get_name()
+        );
+    }
+
+    fn update_keypad(&mut self) {
+        let keypad = self
+            .show_keypad
+            .then(|| (self.chip8.input().pressed_keys(), self.chip8.polled_keys()));
+        self.display.set_keypad(keypad);
+    }
+
+    // Samples the frame and instruction rates about once a second, returns whether it did
+    fn update_stats(&mut self) -> bool {
+        let elapsed = self.stats_start.elapsed();
+        if elapsed < time::Duration::from_secs(1) {
+            return false;
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        self.frames_per_second = (self.rendered_frames as f64 / seconds).round() as u32;
+        self.instructions_per_second = (self.executed_cycles as f64 / seconds).round() as u32;
+        self.rendered_frames = 0;
+        self.executed_cycles = 0;
+        self.stats_start = time::Instant::now();
+        true
+    }
+
+    fn update_title(&mut self) {
+        let title = &mut self.next_window_title;
+        title.clear();
+        write!(title, "{} - ", self.title).unwrap();
+        if self.is_stepping {
+            title.push_str("stepping");
+        } else if self.is_paused {
+            title.push_str("paused");
+        } else if self.is_fast_forwarding {
+            write!(title, "{} IPS, fast forward", self.instructions_per_second).unwrap();
+        } else if self.speed != 100 {
+            write!(
+                title,
+                "{} IPS at {}%",
+                self.instructions_per_second, self.speed
+            )
+            .unwrap();
+        } else {
+            write!(title, "{} IPS", self.instructions_per_second).unwrap();
+        }
+        if self.chip8.is_playing_input() {
+            title.push_str(", replaying input");
+        }
+        if self.is_muted {
+            title.push_str(" - muted");
+        } else {
+            write!(title, " - volume {}%", self.volume).unwrap();
+        }
+
+        // SDL copies the title, so only pass it on when it changed
+        if self.next_window_title != self.window_title {
+            self.display.set_title(&self.next_window_title);
+            std::mem::swap(&mut self.window_title, &mut self.next_window_title);
+        }
+    }
+
+    // Shows the stats over the game area
+    fn update_overlay(&mut self) {
+        let overlay = self.display.overlay_mut();
+        overlay.clear();
+        if self.show_stats {
+            writeln!(
+                overlay,
+                "FPS {} IPS {}",
+                self.frames_per_second, self.instructions_per_second
+            )
+            .unwrap();
+            writeln!(
+                overlay,
+                "DT {} ST {}",
+                self.chip8.delay_timer(),
+                self.chip8.sound_timer()
+            )
+            .unwrap();
+        }
+        if self.is_confirming_exit {
+            overlay.push_str("QUIT: PRESS AGAIN\n");
+        }
+        if let Some(new_bindings) = &self.new_bindings {
+            if self.is_binding_taken {
+                overlay.push_str("ALREADY BOUND\n");
+            }
+            writeln!(overlay, "BIND KEY {:X}, ESCAPE CANCELS", new_bindings.len()).unwrap();
+        }
+    }
+
+    fn render(&mut self) {
+        self.rendered_frames += 1;
+        self.presented_frames += 1;
+        self.display.render_buffer(self.chip8.display_buffer());
+    }
+
+    // Path for a new capture named after the ROM and the current time, creating its directory
+    fn capture_path(&self, extension: &str) -> io::Result<PathBuf> {
+        let timestamp = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        fs::create_dir_all(&self.screenshot_directory)?;
+        Ok(self
+            .screenshot_directory
+            .join(format!("{}-{}.{}", self.rom_name, timestamp, extension)))
+    }
+
+    fn save_screenshot(&self) {
+        match self
+            .capture_path("png")
+            .map_err(png::EncodingError::from)
+            .and_then(|path| self.display.save_screenshot(&path).map(|_| path))
+        {
+            Ok(path) => println!("Saved screenshot to {}", path.display()),
+            Err(error) => eprintln!("Failed to save screenshot: {:?}", error),
+        }
+    }
+
+    fn toggle_recording(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            self.recorder = Some(Recorder::new());
+            println!("Started recording");
+            return;
+        };
+
+        match self
+            .capture_path("gif")
+            .map_err(gif::EncodingError::from)
+            .and_then(|path| {
+                recorder
+                    .save(&path, self.display.pixel_size(), self.tone)
+                    .map(|_| path)
+            }) {
+            Ok(path) => println!(
+                "Saved recording to {} and its audio to {}",
+                path.display(),
+                path.with_extension("wav").display()
+            ),
+            Err(error) => eprintln!("Failed to save recording: {:?}", error),
+        }
+    }
+
+    // Enters step mode at breakpoints and watchpoints, returning whether it stopped at one
+    fn cycle(&mut self) -> bool {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.record(&self.chip8);
+            debugger.watched_accesses(&self.chip8, &mut self.watched_accesses);
+        }
+        self.chip8.cycle();
+        self.executed_cycles += 1;
+        // The core pauses itself when the ROM halts, see `HaltAction::Pause`
+        if self.chip8.speed() == 0 && !self.is_paused {
+            self.is_paused = true;
+            self.update_title();
+        }
+
+        // Show each step in the game window, the debugger catches up once per frame
+        if self.is_stepping {
+            self.chip8.take_update_display();
+            self.render();
+        } else if !self.vsync && self.chip8.take_update_display() {
+            self.render();
+        }
+
+        let mut should_stop = self
+            .debugger
+            .as_mut()
+            .is_some_and(|debugger| debugger.check_stop(&self.chip8, &self.watched_accesses));
+        should_stop |= self
+            .remote_debugger
+            .as_mut()
+            .is_some_and(|remote_debugger| remote_debugger.check_stop(&self.chip8));
+        if should_stop {
+            self.set_stepping(true);
+        }
+        should_stop
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::condition::parse_number;
+use crate::constants;
+
+// The source line each instruction was assembled from, read from a `:source game.8o` line
+// naming the source file followed by lines of `0x2A4 12`, with `#` starting a comment
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    source_path: Option<PathBuf>,
+    source_lines: Vec<String>,
+    lines: BTreeMap<usize, usize>,
+}
+
+fn parse_line(line: &str) -> Option<(usize, usize)> {
+    let mut words = line.split_whitespace();
+    let address = parse_number(words.next()?)? as usize;
+    let source_line = words.next()?.parse().ok()?;
+    (words.next().is_none() && address < constants::RAM_LEN).then_some((address, source_line))
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap {
+            source_path: None,
+            source_lines: Vec::new(),
+            lines: BTreeMap::new(),
+        }
+    }
+
+    // Only the line numbers, the source itself is read by `load`
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut source_map = SourceMap::new();
+        for (index, line) in contents.lines().enumerate() {
+            if let Some(path) = line.strip_prefix(":source ") {
+                source_map.source_path = Some(PathBuf::from(path.trim()));
+                continue;
+            }
+            let code = line.split('#').next().unwrap_or_default();
+            if code.trim().is_empty() {
+                continue;
+            }
+            let (address, source_line) = parse_line(code)
+                .ok_or_else(|| format!("Line {}: Invalid source line: {}", index + 1, line))?;
+            source_map.insert(address, source_line);
+        }
+        Ok(source_map)
+    }
+
+    // A relative source path is relative to the map
+    pub fn load(path: &Path) -> Self {
+        let contents = fs::read_to_string(path)
+            .unwrap_or_else(|error| panic!("Failed to read source map: {:?}", error));
+        let mut source_map = SourceMap::parse(&contents)
+            .unwrap_or_else(|error| panic!("Invalid source map in {:?}: {}", path, error));
+        let source_path = source_map
+            .source_path
+            .take()
+            .unwrap_or_else(|| panic!("Missing :source line in {:?}", path));
+        let source_path = path.parent().unwrap_or(Path::new("")).join(source_path);
+        let source = fs::read_to_string(&source_path)
+            .unwrap_or_else(|error| panic!("Failed to read source: {:?}", error));
+        source_map.source_lines = source.lines().map(str::to_string).collect();
+        source_map.source_path = Some(source_path);
+        source_map
+    }
+
+    // The `.map` file next to the ROM, if there is one
+    pub fn for_rom(rom_path: &Path) -> Self {
+        let path = source_map_path(rom_path);
+        if path.exists() {
+            SourceMap::load(&path)
+        } else {
+            SourceMap::new()
+        }
+    }
+
+    pub fn insert(&mut self, address: usize, source_line: usize) {
+        self.lines.insert(address, source_line);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn source_path(&self) -> Option<&Path> {
+        self.source_path.as_deref()
+    }
+
+    pub fn file_name(&self) -> String {
+        self.source_path
+            .as_deref()
+            .and_then(Path::file_name)
+            .map_or_else(
+                || "source".to_string(),
+                |name| name.to_string_lossy().into(),
+            )
+    }
+
+    // Whether a path names the same file as the source, as clients may spell it differently
+    pub fn is_source(&self, path: &Path) -> bool {
+        let Some(source_path) = &self.source_path else {
+            return false;
+        };
+        match (fs::canonicalize(source_path), fs::canonicalize(path)) {
+            (Ok(source_path), Ok(path)) => source_path == path,
+            _ => source_path == path,
+        }
+    }
+
+    pub fn line(&self, address: usize) -> Option<usize> {
+        self.lines.get(&address).copied()
+    }
+
+    // The first line from the given one on with an instruction, and that instruction's address
+    pub fn address(&self, source_line: usize) -> Option<(usize, usize)> {
+        self.lines
+            .iter()
+            .filter(|(_, &line)| line >= source_line)
+            .map(|(&address, &line)| (line, address))
+            .min()
+    }
+
+    // The text of a line, counted from 1
+    pub fn source_line(&self, source_line: usize) -> Option<&str> {
+        self.source_lines
+            .get(source_line.checked_sub(1)?)
+            .map(String::as_str)
+    }
+
+    pub fn source_len(&self) -> usize {
+        self.source_lines.len()
+    }
+
+    // The map as written next to an assembled ROM
+    pub fn to_text(&self, source_path: &Path) -> String {
+        let lines: String = self
+            .lines
+            .iter()
+            .map(|(address, line)| format!("{:#05X} {}\n", address, line))
+            .collect();
+        format!(":source {}\n{}", source_path.display(), lines)
+    }
+}
+
+impl Default for SourceMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn source_map_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("map")
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::condition::parse_number;
+use crate::constants;
+
+// Names for addresses, read from lines of `:const name 0x2A4` as Octo would define them,
+// `name = 0x2A4` or `name 0x2A4`, with `#` starting a comment
+#[derive(Debug, Clone)]
+pub struct Symbols {
+    names: BTreeMap<usize, String>,
+}
+
+fn parse_symbol(line: &str) -> Option<(String, usize)> {
+    let mut words = line
+        .split_whitespace()
+        .filter(|&word| word != ":const" && word != ":" && word != "=");
+    let name = words.next()?;
+    let address = parse_number(words.next()?)? as usize;
+    (words.next().is_none() && address < constants::RAM_LEN).then(|| (name.to_string(), address))
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols {
+            names: BTreeMap::new(),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut symbols = Symbols::new();
+        for (index, line) in contents.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or_default();
+            if code.trim().is_empty() {
+                continue;
+            }
+            let (name, address) = parse_symbol(code)
+                .ok_or_else(|| format!("Line {}: Invalid symbol: {}", index + 1, line))?;
+            symbols.insert(name, address);
+        }
+        Ok(symbols)
+    }
+
+    pub fn load(path: &Path) -> Self {
+        let contents = fs::read_to_string(path)
+            .unwrap_or_else(|error| panic!("Failed to read symbols: {:?}", error));
+        Symbols::parse(&contents)
+            .unwrap_or_else(|error| panic!("Invalid symbols in {:?}: {}", path, error))
+    }
+
+    // The `.sym` file next to the ROM, if there is one
+    pub fn for_rom(rom_path: &Path) -> Self {
+        let path = symbols_path(rom_path);
+        if path.exists() {
+            Symbols::load(&path)
+        } else {
+            Symbols::new()
+        }
+    }
+
+    // The first name given to an address is kept
+    pub fn insert(&mut self, name: String, address: usize) {
+        self.names.entry(address).or_insert(name);
+    }
+
+    pub fn name(&self, address: usize) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .find(|(_, symbol)| symbol.as_str() == name)
+            .map(|(&address, _)| address)
+    }
+
+    // The name of an address, or the address in hex
+    pub fn label(&self, address: usize) -> String {
+        self.name(address)
+            .map_or_else(|| format!("{:03X}", address), str::to_string)
+    }
+
+    // In the `:const` form, so the file can also be included in Octo sources
+    pub fn to_source(&self) -> String {
+        self.names
+            .iter()
+            .map(|(address, name)| format!(":const {} {:#05X}\n", name, address))
+            .collect()
+    }
+}
+
+impl Default for Symbols {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn symbols_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sym")
+}
@@ -0,0 +1,292 @@
+use std::{
+    io::{self, Stdout, Write},
+    time,
+};
+
+use clap::ValueEnum;
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::Print,
+    terminal,
+};
+
+use crate::audio_sink::AudioSink;
+use crate::chip_8::Chip8;
+use crate::constants;
+use crate::renderer::{self, Renderer};
+
+fn map_char_to_value(character: char) -> Option<u8> {
+    match character.to_ascii_lowercase() {
+        'x' => Some(0x00),
+        '1' => Some(0x01),
+        '2' => Some(0x02),
+        '3' => Some(0x03),
+        'q' => Some(0x04),
+        'w' => Some(0x05),
+        'e' => Some(0x06),
+        'a' => Some(0x07),
+        's' => Some(0x08),
+        'd' => Some(0x09),
+        'z' => Some(0x0A),
+        'c' => Some(0x0B),
+        '4' => Some(0x0C),
+        'r' => Some(0x0D),
+        'f' => Some(0x0E),
+        'v' => Some(0x0F),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TerminalMode {
+    // 1x2 pixels per character, 64x16 characters
+    HalfBlocks,
+    // 2x4 pixels per character, 32x8 characters
+    Braille,
+}
+
+fn pixel(buffer: &[bool; constants::DISPLAY_LEN], x: usize, y: usize) -> bool {
+    buffer[y * constants::DISPLAY_WIDTH + x]
+}
+
+// Each character cell shows two vertically stacked pixels using half blocks
+fn half_block_lines(buffer: &[bool; constants::DISPLAY_LEN]) -> Vec<String> {
+    (0..constants::DISPLAY_HEIGHT / 2)
+        .map(|row| {
+            (0..constants::DISPLAY_WIDTH)
+                .map(
+                    |x| match (pixel(buffer, x, row * 2), pixel(buffer, x, row * 2 + 1)) {
+                        (false, false) => ' ',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (true, true) => '█',
+                    },
+                )
+                .collect()
+        })
+        .collect()
+}
+
+// Each character cell shows a 2x4 block of pixels as braille dots
+fn braille_lines(buffer: &[bool; constants::DISPLAY_LEN]) -> Vec<String> {
+    (0..constants::DISPLAY_HEIGHT / 4)
+        .map(|row| {
+            (0..constants::DISPLAY_WIDTH / 2)
+                .map(|column| {
+                    let mut dots = 0;
+                    for (dot_row, row_dots) in constants::BRAILLE_DOTS.iter().enumerate() {
+                        for (dot_column, &dot) in row_dots.iter().enumerate() {
+                            if pixel(buffer, column * 2 + dot_column, row * 4 + dot_row) {
+                                dots |= dot;
+                            }
+                        }
+                    }
+                    char::from_u32(constants::BRAILLE_BLANK + dots).unwrap()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Rings the terminal bell once per sound rather than for its duration
+pub struct TerminalBell {
+    is_ringing: bool,
+    is_muted: bool,
+}
+
+impl TerminalBell {
+    pub fn new() -> Self {
+        TerminalBell {
+            is_ringing: false,
+            is_muted: false,
+        }
+    }
+}
+
+impl Default for TerminalBell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioSink for TerminalBell {
+    // Written with the next frame, which flushes stdout
+    fn play(&mut self) {
+        if !self.is_ringing && !self.is_muted {
+            queue!(io::stdout(), Print('\x07')).unwrap();
+        }
+        self.is_ringing = true;
+    }
+
+    fn stop(&mut self) {
+        self.is_ringing = false;
+    }
+
+    // The bell has no pitch
+    fn queue_pattern(&mut self, _pattern: [u8; constants::AUDIO_PATTERN_LEN], _playback_rate: f32) {
+    }
+
+    fn set_volume(&mut self, volume: u8) {
+        self.is_muted = volume == 0;
+    }
+}
+
+// Raw mode on the alternate screen, undone when dropped so the terminal is restored however the
+// frontend stops, including by a panic
+struct RawTerminal {
+    reports_releases: bool,
+}
+
+impl RawTerminal {
+    fn enter() -> Self {
+        terminal::enable_raw_mode().unwrap();
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide).unwrap();
+
+        // Most terminals only report presses, in which case keys are held for a few frames
+        let reports_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_releases {
+            execute!(
+                io::stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )
+            .unwrap();
+        }
+        RawTerminal { reports_releases }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        if self.reports_releases {
+            execute!(io::stdout(), PopKeyboardEnhancementFlags).unwrap();
+        }
+        execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen).unwrap();
+        terminal::disable_raw_mode().unwrap();
+    }
+}
+
+// Draws to the terminal and reads the keypad from key events
+struct TerminalDisplay {
+    mode: TerminalMode,
+    stdout: Stdout,
+    raw_terminal: Option<RawTerminal>,
+    is_quitting: bool,
+
+    // Frame until which each key counts as pressed
+    held_until: [u64; constants::KEY_COUNT],
+    audio: Box<dyn AudioSink>,
+    presented_frames: u64,
+}
+
+impl TerminalDisplay {
+    fn reports_releases(&self) -> bool {
+        self.raw_terminal
+            .as_ref()
+            .is_some_and(|raw_terminal| raw_terminal.reports_releases)
+    }
+}
+
+impl Renderer for TerminalDisplay {
+    fn render_buffer(&mut self, buffer: &[bool; constants::DISPLAY_LEN]) {
+        self.presented_frames += 1;
+        let lines = match self.mode {
+            TerminalMode::HalfBlocks => half_block_lines(buffer),
+            TerminalMode::Braille => braille_lines(buffer),
+        };
+        for (row, line) in lines.into_iter().enumerate() {
+            queue!(self.stdout, cursor::MoveTo(0, row as u16), Print(line)).unwrap();
+        }
+        self.stdout.flush().unwrap();
+    }
+
+    fn skip_frame(&mut self) {
+        self.stdout.flush().unwrap();
+    }
+
+    fn tick(&mut self, sound_timer: u8) {
+        self.audio.update(sound_timer);
+    }
+
+    fn is_open(&self, _frame: u64) -> bool {
+        !self.is_quitting
+    }
+
+    fn pressed_keys(&mut self, frame: u64) -> Option<u16> {
+        while event::poll(time::Duration::ZERO).unwrap() {
+            let Event::Key(key_event) = event::read().unwrap() else {
+                continue;
+            };
+
+            match key_event.code {
+                KeyCode::Esc => self.is_quitting = true,
+                KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.is_quitting = true
+                }
+                KeyCode::Char(character) => {
+                    if let Some(key) = map_char_to_value(character) {
+                        self.held_until[key as usize] = match key_event.kind {
+                            KeyEventKind::Release => 0,
+                            _ if self.reports_releases() => u64::MAX,
+                            _ => frame + constants::TERMINAL_KEY_HOLD_FRAMES,
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let pressed_keys = self
+            .held_until
+            .iter()
+            .enumerate()
+            .filter(|(_, &held_until)| held_until > frame)
+            .fold(0u16, |mask, (key, _)| mask | (1 << key));
+        Some(pressed_keys)
+    }
+}
+
+pub struct TerminalFrontend {
+    chip8: Chip8,
+
+    display: TerminalDisplay,
+}
+
+impl TerminalFrontend {
+    pub fn build(chip8: Chip8, mode: TerminalMode, audio: Box<dyn AudioSink>) -> Self {
+        TerminalFrontend {
+            chip8,
+
+            display: TerminalDisplay {
+                mode,
+                stdout: io::stdout(),
+                raw_terminal: None,
+                is_quitting: false,
+
+                held_until: [0; constants::KEY_COUNT],
+                audio,
+                presented_frames: 0,
+            },
+        }
+    }
+
+    pub fn into_chip8(self) -> Chip8 {
+        self.chip8
+    }
+
+    // Frames shown since the start, for the statistics of a run
+    pub fn presented_frames(&self) -> u64 {
+        self.display.presented_frames
+    }
+
+    pub fn run(&mut self) {
+        self.display.raw_terminal = Some(RawTerminal::enter());
+        renderer::run(&mut self.chip8, &mut self.display);
+        // Restores the terminal, which dropping the frontend also does if the run panics
+        self.display.raw_terminal = None;
+    }
+}
@@ -0,0 +1,150 @@
+use std::f32::consts::TAU;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::constants;
+
+// Parses a duty cycle strictly between 0 and 1
+pub fn parse_duty_cycle(value: &str) -> Result<f32, String> {
+    match value.trim().parse::<f32>() {
+        Ok(duty_cycle) if duty_cycle > 0.0 && duty_cycle < 1.0 => Ok(duty_cycle),
+        _ => Err(format!(
+            "Invalid duty cycle, expected a number between 0 and 1: {}",
+            value
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+impl Waveform {
+    // Amplitude between -1 and 1 at a phase between 0 and 1
+    fn sample(self, phase: f32, duty_cycle: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < duty_cycle {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneOptions {
+    pub frequency: f32,
+    pub waveform: Waveform,
+    // Fraction of each period a square wave is high
+    pub duty_cycle: f32,
+    // Percentage of the full beep volume
+    pub volume: u8,
+}
+
+impl ToneOptions {
+    pub fn new() -> Self {
+        ToneOptions {
+            frequency: constants::BEEP_FREQUENCY,
+            waveform: Waveform::Square,
+            duty_cycle: constants::BEEP_DUTY_CYCLE,
+            volume: constants::DEFAULT_VOLUME,
+        }
+    }
+}
+
+impl Default for ToneOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct ToneGenerator {
+    sample_rate: f32,
+    phase_inc: f32,
+    phase: f32,
+    waveform: Waveform,
+    // Replaces the waveform once set
+    pattern: Option<[u8; constants::AUDIO_PATTERN_LEN]>,
+    duty_cycle: f32,
+    volume: f32,
+    is_playing: bool,
+    // Linear attack and release envelope between 0 and 1
+    gain: f32,
+    gain_step: f32,
+}
+
+impl ToneGenerator {
+    pub fn new(sample_rate: i32, tone: ToneOptions) -> Self {
+        let mut tone_generator = ToneGenerator {
+            sample_rate: sample_rate as f32,
+            phase_inc: tone.frequency / sample_rate as f32,
+            phase: 0.0,
+            waveform: tone.waveform,
+            pattern: None,
+            duty_cycle: tone.duty_cycle,
+            volume: 0.0,
+            is_playing: false,
+            gain: 0.0,
+            gain_step: 1.0 / (sample_rate as f32 * constants::BEEP_ENVELOPE_TIME),
+        };
+        tone_generator.set_volume(tone.volume);
+        tone_generator
+    }
+
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = constants::BEEP_VOLUME * volume as f32 / 100.0;
+    }
+
+    // One period of the wave plays the whole pattern, so it is stretched over its samples
+    pub fn set_pattern(&mut self, pattern: [u8; constants::AUDIO_PATTERN_LEN], playback_rate: f32) {
+        self.pattern = Some(pattern);
+        self.phase_inc =
+            playback_rate / (constants::AUDIO_PATTERN_LEN * 8) as f32 / self.sample_rate;
+    }
+
+    // The tone fades in or out from wherever the envelope currently is
+    pub fn set_playing(&mut self, is_playing: bool) {
+        self.is_playing = is_playing;
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            self.gain = if self.is_playing {
+                (self.gain + self.gain_step).min(1.0)
+            } else {
+                (self.gain - self.gain_step).max(0.0)
+            };
+            // Like a paused device, the wave only advances while it can be heard
+            if self.gain == 0.0 {
+                *x = 0.0;
+                continue;
+            }
+
+            let sample = match self.pattern {
+                Some(pattern) => {
+                    let bit = (self.phase * (pattern.len() * 8) as f32) as usize;
+                    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                None => self.waveform.sample(self.phase, self.duty_cycle),
+            };
+            *x = sample * self.volume * self.gain;
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
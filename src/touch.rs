@@ -0,0 +1,58 @@
+use serde::Deserialize;
+
+use crate::constants;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TouchLayout {
+    // The screen split into four quadrants
+    Quadrants,
+    // The screen split diagonally into up, left, down and right around its center
+    Dpad,
+}
+
+// Keypad keys pressed by touching the screen, in the order up, left, down, right for the
+// D-pad and top left, top right, bottom left, bottom right for quadrants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TouchControls {
+    pub layout: TouchLayout,
+    pub keys: [u8; 4],
+}
+
+impl TouchControls {
+    pub fn new() -> Self {
+        TouchControls {
+            layout: TouchLayout::Dpad,
+            keys: constants::DEFAULT_TOUCH_KEYS,
+        }
+    }
+
+    // Takes coordinates normalized to 0 to 1 as SDL reports them for touches
+    pub fn key_at(&self, x: f32, y: f32) -> u8 {
+        let index = match self.layout {
+            TouchLayout::Quadrants => (y >= 0.5) as usize * 2 + (x >= 0.5) as usize,
+            TouchLayout::Dpad => {
+                let (dx, dy) = (x - 0.5, y - 0.5);
+                if dx.abs() > dy.abs() {
+                    if dx < 0.0 {
+                        1
+                    } else {
+                        3
+                    }
+                } else if dy < 0.0 {
+                    0
+                } else {
+                    2
+                }
+            }
+        };
+        self.keys[index]
+    }
+}
+
+impl Default for TouchControls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use crate::condition::parse_address;
+use crate::constants;
+use crate::disassembler::disassemble_with_symbols;
+use crate::symbols::Symbols;
+
+// Machine state an instruction can change, compared before and after it runs
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TracedState {
+    pub registers: [u8; constants::REGISTER_COUNT],
+    pub index_register: u16,
+}
+
+// Appends a line per executed instruction as `<address> <opcode> <mnemonic> [changes]`, e.g.
+// `0x204 7A01 ADD VA, 01 VA=06`
+pub struct Tracer {
+    writer: BufWriter<File>,
+    range: Option<RangeInclusive<usize>>,
+    symbols: Symbols,
+}
+
+impl Tracer {
+    pub fn create(
+        path: &Path,
+        range: Option<RangeInclusive<usize>>,
+        symbols: Symbols,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Tracer {
+            writer: BufWriter::new(file),
+            range,
+            symbols,
+        })
+    }
+
+    pub fn is_traced(&self, program_counter: usize) -> bool {
+        self.range
+            .as_ref()
+            .is_none_or(|range| range.contains(&program_counter))
+    }
+
+    pub fn trace(
+        &mut self,
+        program_counter: usize,
+        instruction: u16,
+        before: &TracedState,
+        after: &TracedState,
+    ) -> io::Result<()> {
+        write!(
+            self.writer,
+            "{:#05X} {:04X} {}",
+            program_counter,
+            instruction,
+            disassemble_with_symbols(instruction, &self.symbols)
+        )?;
+        for (register, (old, new)) in before.registers.iter().zip(&after.registers).enumerate() {
+            if old != new {
+                write!(self.writer, " V{:X}={:02X}", register, new)?;
+            }
+        }
+        if before.index_register != after.index_register {
+            write!(self.writer, " I={:03X}", after.index_register)?;
+        }
+        writeln!(self.writer)
+    }
+}
+
+// Inclusive range of hex addresses, e.g. `0x200-0x2FF` or `200-2FF`
+pub fn parse_range(value: &str) -> Result<RangeInclusive<usize>, String> {
+    let invalid = || format!("Invalid address range: {}", value);
+    let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+    let start = parse_address(start.trim()).map_err(|_| invalid())?;
+    let end = parse_address(end.trim()).map_err(|_| invalid())?;
+    if start > end {
+        return Err(invalid());
+    }
+    Ok(start..=end)
+}
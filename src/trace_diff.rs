@@ -0,0 +1,329 @@
+use std::fmt::Write;
+
+use crate::constants;
+
+// A register an instruction changed, written after the mnemonic as e.g. `VA=06` or `I=2A4`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Register(usize, u8),
+    IndexRegister(u16),
+}
+
+fn parse_change(word: &str) -> Option<Change> {
+    let (name, value) = word.split_once('=')?;
+    let value = u16::from_str_radix(value, 16).ok()?;
+    if name == "I" {
+        return Some(Change::IndexRegister(value));
+    }
+    let register = name
+        .strip_prefix('V')
+        .filter(|register| register.len() == 1)?;
+    Some(Change::Register(
+        usize::from_str_radix(register, 16).ok()?,
+        u8::try_from(value).ok()?,
+    ))
+}
+
+struct Entry<'a> {
+    line_number: usize,
+    line: &'a str,
+    address: u16,
+    instruction: u16,
+    changes: Vec<Change>,
+}
+
+impl<'a> Entry<'a> {
+    // Takes a line in the `--trace` format, `<address> <opcode> <mnemonic> [changes]`. The
+    // mnemonic is not compared, so traces of other emulators may disassemble differently.
+    fn parse(line_number: usize, line: &'a str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let address = words.next()?;
+        let address =
+            u16::from_str_radix(address.strip_prefix("0x").unwrap_or(address), 16).ok()?;
+        let instruction = words.next().filter(|instruction| instruction.len() == 4)?;
+        let instruction = u16::from_str_radix(instruction, 16).ok()?;
+        let mut changes: Vec<Change> = words.rev().map_while(parse_change).collect();
+        changes.reverse();
+        Some(Entry {
+            line_number,
+            line: line.trim(),
+            address,
+            instruction,
+            changes,
+        })
+    }
+
+    fn is_same_instruction(&self, other: &Entry) -> bool {
+        self.address == other.address && self.instruction == other.instruction
+    }
+
+    fn matches(&self, other: &Entry) -> bool {
+        self.is_same_instruction(other) && self.changes == other.changes
+    }
+}
+
+// An execution trace, lines that are not instructions such as headers are skipped
+pub struct Trace<'a> {
+    name: &'a str,
+    entries: Vec<Entry<'a>>,
+}
+
+impl<'a> Trace<'a> {
+    pub fn parse(name: &'a str, contents: &'a str) -> Self {
+        Trace {
+            name,
+            entries: contents
+                .lines()
+                .enumerate()
+                .filter_map(|(index, line)| Entry::parse(index + 1, line))
+                .collect(),
+        }
+    }
+}
+
+// Registers as far as a trace has shown them, as only changes are written
+struct KnownState {
+    registers: [Option<u8>; constants::REGISTER_COUNT],
+    index_register: Option<u16>,
+}
+
+impl KnownState {
+    // A trace starting at the program start begins at reset, with all registers cleared
+    fn new(trace: &Trace) -> Self {
+        let initial = trace
+            .entries
+            .first()
+            .filter(|entry| entry.address as usize == constants::PROGRAM_START)
+            .map(|_| 0);
+        KnownState {
+            registers: [initial; constants::REGISTER_COUNT],
+            index_register: initial.map(u16::from),
+        }
+    }
+
+    fn apply(&mut self, changes: &[Change]) {
+        for &change in changes {
+            match change {
+                Change::Register(register, value) => self.registers[register] = Some(value),
+                Change::IndexRegister(value) => self.index_register = Some(value),
+            }
+        }
+    }
+
+    // Name and value of each register, None if the trace has not shown it yet
+    fn values(&self) -> Vec<(String, Option<String>)> {
+        let mut values: Vec<(String, Option<String>)> = self
+            .registers
+            .iter()
+            .enumerate()
+            .map(|(register, value)| {
+                (
+                    format!("V{:X}", register),
+                    value.map(|value| format!("{:02X}", value)),
+                )
+            })
+            .collect();
+        values.push((
+            "I".to_string(),
+            self.index_register.map(|value| format!("{:03X}", value)),
+        ));
+        values
+    }
+}
+
+// Where both traces start with the same instruction, skipping the start of one of them in case it
+// began tracing earlier
+fn align(a: &Trace, b: &Trace) -> Option<(usize, usize)> {
+    let (a_first, b_first) = (a.entries.first()?, b.entries.first()?);
+    if let Some(b_start) = b
+        .entries
+        .iter()
+        .position(|entry| entry.is_same_instruction(a_first))
+    {
+        return Some((0, b_start));
+    }
+    let a_start = a
+        .entries
+        .iter()
+        .position(|entry| entry.is_same_instruction(b_first))?;
+    Some((a_start, 0))
+}
+
+// Compares two traces instruction by instruction, e.g. of this emulator and a reference one.
+// Returns a summary if they match until either ends, or the first divergence with the
+// instructions leading up to it and the registers of both traces after it.
+pub fn diff_traces(a: &Trace, b: &Trace) -> Result<String, String> {
+    let (a_start, b_start) =
+        align(a, b).ok_or("The traces have no instruction in common to align them at\n")?;
+    let mut text = String::new();
+    for (trace, start) in [(a, a_start), (b, b_start)] {
+        if start > 0 {
+            writeln!(
+                text,
+                "Skipped {} instructions at the start of {} to align the traces",
+                start, trace.name
+            )
+            .unwrap();
+        }
+    }
+
+    let (mut a_state, mut b_state) = (KnownState::new(a), KnownState::new(b));
+    for entry in &a.entries[..a_start] {
+        a_state.apply(&entry.changes);
+    }
+    for entry in &b.entries[..b_start] {
+        b_state.apply(&entry.changes);
+    }
+
+    let pairs = a.entries[a_start..].iter().zip(&b.entries[b_start..]);
+    for (matching, (a_entry, b_entry)) in pairs.enumerate() {
+        a_state.apply(&a_entry.changes);
+        b_state.apply(&b_entry.changes);
+        if a_entry.matches(b_entry) {
+            continue;
+        }
+
+        let name_width = a.name.len().max(b.name.len());
+        writeln!(
+            text,
+            "Traces diverge after {} matching instructions\n",
+            matching
+        )
+        .unwrap();
+        let context_start = (a_start + matching).saturating_sub(constants::TRACE_DIFF_CONTEXT);
+        for entry in &a.entries[context_start.max(a_start)..a_start + matching] {
+            writeln!(text, "  {:>5}  {}", entry.line_number, entry.line).unwrap();
+        }
+        for (trace, entry) in [(a, a_entry), (b, b_entry)] {
+            writeln!(
+                text,
+                "> {:>5}  {}  ({})",
+                entry.line_number, entry.line, trace.name
+            )
+            .unwrap();
+        }
+
+        writeln!(text, "\nRegisters after it").unwrap();
+        writeln!(
+            text,
+            "     {:<width$}  {}",
+            a.name,
+            b.name,
+            width = name_width
+        )
+        .unwrap();
+        for ((name, a_value), (_, b_value)) in a_state.values().into_iter().zip(b_state.values()) {
+            // Registers unknown to either trace are not marked
+            let marker = match (&a_value, &b_value) {
+                (Some(a_value), Some(b_value)) if a_value != b_value => "  <",
+                _ => "",
+            };
+            let unknown = || "??".to_string();
+            writeln!(
+                text,
+                "{:<3}  {:<width$}  {}{}",
+                name,
+                a_value.unwrap_or_else(unknown),
+                b_value.unwrap_or_else(unknown),
+                marker,
+                width = name_width
+            )
+            .unwrap();
+        }
+        return Err(text);
+    }
+
+    let matching = (a.entries.len() - a_start).min(b.entries.len() - b_start);
+    let ended = match (a.entries.len() - a_start).cmp(&(b.entries.len() - b_start)) {
+        std::cmp::Ordering::Less => format!(", then {} ends", a.name),
+        std::cmp::Ordering::Greater => format!(", then {} ends", b.name),
+        std::cmp::Ordering::Equal => String::new(),
+    };
+    writeln!(text, "Traces match for {} instructions{}", matching, ended).unwrap();
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // V0 = 5, V1 = 7, I = 300, V0 += V1 with the carry cleared
+    const OURS: &str = "\
+200 6005 LD V0, 05 V0=05
+202 6107 LD V1, 07 V1=07
+204 A300 LD I, 300 I=300
+206 8014 ADD V0, V1 V0=0C VF=00
+208 1208 JP 208
+";
+
+    #[test]
+    fn traces_are_aligned_past_an_earlier_start() {
+        // Another emulator tracing from reset, with its own header and mnemonics
+        let theirs = "\
+address opcode
+0x000 0000 ???
+0x200 6005 v0 := 0x05 V0=05
+0x202 6107 v1 := 0x07 V1=07
+0x204 A300 i := 0x300 I=300
+0x206 8014 v0 += v1 V0=0C VF=00
+";
+        let diff = diff_traces(&Trace::parse("ours", OURS), &Trace::parse("theirs", theirs));
+        assert_eq!(
+            diff.as_deref(),
+            Ok(
+                "Skipped 1 instructions at the start of theirs to align the traces\n\
+                Traces match for 4 instructions, then theirs ends\n"
+            )
+        );
+    }
+
+    #[test]
+    fn the_first_divergence_is_shown_with_the_registers() {
+        // The carry of 8XY4 set where it should not be
+        let theirs = OURS.replace("V0=0C VF=00", "V0=0C VF=01");
+        let diff = diff_traces(
+            &Trace::parse("ours", OURS),
+            &Trace::parse("theirs", &theirs),
+        )
+        .unwrap_err();
+        let lines: Vec<&str> = diff.lines().collect();
+        assert_eq!(
+            lines[..7],
+            [
+                "Traces diverge after 3 matching instructions",
+                "",
+                "      1  200 6005 LD V0, 05 V0=05",
+                "      2  202 6107 LD V1, 07 V1=07",
+                "      3  204 A300 LD I, 300 I=300",
+                ">     4  206 8014 ADD V0, V1 V0=0C VF=00  (ours)",
+                ">     4  206 8014 ADD V0, V1 V0=0C VF=01  (theirs)",
+            ]
+        );
+        assert!(lines.contains(&"V0   0C      0C"), "{}", diff);
+        assert!(lines.contains(&"VF   00      01  <"), "{}", diff);
+        assert!(lines.contains(&"I    300     300"), "{}", diff);
+    }
+
+    #[test]
+    fn registers_not_yet_shown_are_unknown() {
+        // Starting after the registers were loaded
+        let ours = OURS.lines().skip(3).collect::<Vec<_>>().join("\n");
+        let theirs = ours.replace("V0=0C", "V0=0D");
+        let diff = diff_traces(
+            &Trace::parse("ours", &ours),
+            &Trace::parse("theirs", &theirs),
+        )
+        .unwrap_err();
+        assert!(diff.contains("\nV0   0C      0D  <\n"), "{}", diff);
+        assert!(diff.contains("\nV1   ??      ??\n"), "{}", diff);
+    }
+
+    #[test]
+    fn traces_without_a_common_instruction_are_not_compared() {
+        let theirs = "300 00E0 CLS\n";
+        assert_eq!(
+            diff_traces(&Trace::parse("ours", OURS), &Trace::parse("theirs", theirs)),
+            Err("The traces have no instruction in common to align them at\n".to_string())
+        );
+    }
+}
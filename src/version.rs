@@ -0,0 +1,72 @@
+use clap::ValueEnum;
+
+use crate::chip_8::{Platform, Quirks, QUIRK_FLAGS};
+
+// Cargo features compiled into this binary
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "sdl")]
+    "sdl",
+    #[cfg(feature = "minifb")]
+    "minifb",
+    #[cfg(feature = "pixels")]
+    "pixels",
+    #[cfg(feature = "cpal")]
+    "cpal",
+    #[cfg(feature = "scripting")]
+    "scripting",
+];
+
+fn platform_name(platform: Platform) -> String {
+    platform.to_possible_value().unwrap().get_name().to_string()
+}
+
+#[cfg(feature = "sdl")]
+fn sdl_details() -> Vec<String> {
+    let mut lines = vec![format!("sdl_version={}", sdl2::version::version())];
+
+    match sdl2::init() {
+        Ok(sdl_context) => {
+            let video_driver = sdl_context
+                .video()
+                .map_or("unavailable", |video| video.current_video_driver());
+            let audio_driver = sdl_context
+                .audio()
+                .map_or("unavailable", |audio| audio.current_audio_driver());
+            lines.push(format!("video_driver={}", video_driver));
+            lines.push(format!("audio_driver={}", audio_driver));
+        }
+        Err(error) => lines.push(format!("sdl_error={}", error)),
+    }
+
+    lines
+}
+
+// Build and runtime details as `key=value` lines, for pasting into bug reports
+pub fn verbose_version() -> String {
+    let mut lines = vec![
+        format!("version={}", env!("CARGO_PKG_VERSION")),
+        format!("features={}", FEATURES.join(",")),
+    ];
+    #[cfg(feature = "sdl")]
+    lines.extend(sdl_details());
+
+    let platforms: Vec<String> = Platform::value_variants()
+        .iter()
+        .map(|&platform| platform_name(platform))
+        .collect();
+    lines.push(format!("platforms={}", platforms.join(",")));
+
+    for &platform in Platform::value_variants() {
+        let mut quirks = Quirks::new(platform);
+        for (name, flag) in QUIRK_FLAGS {
+            lines.push(format!(
+                "quirks.{}.{}={}",
+                platform_name(platform),
+                name,
+                flag(&mut quirks)
+            ));
+        }
+    }
+
+    lines.join("\n")
+}
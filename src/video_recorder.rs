@@ -0,0 +1,117 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+use crate::constants;
+use crate::tone::ToneOptions;
+use crate::wav::{beep_samples, save_wav};
+
+fn spawn_ffmpeg(arguments: &[&str]) -> io::Result<(Child, ChildStdin)> {
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error"])
+        .args(arguments)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let stdin = ffmpeg.stdin.take().unwrap();
+    Ok((ffmpeg, stdin))
+}
+
+fn wait_for_ffmpeg(mut ffmpeg: Child) -> io::Result<()> {
+    let status = ffmpeg.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("ffmpeg exited with {}", status)));
+    }
+    Ok(())
+}
+
+// Pipes raw frames into a lossless intermediate video, the beep is muxed in once recording ends
+pub struct VideoRecorder {
+    ffmpeg: Child,
+    stdin: ChildStdin,
+    video_path: PathBuf,
+    output_path: PathBuf,
+    beeping_frames: Vec<bool>,
+    tone: ToneOptions,
+}
+
+impl VideoRecorder {
+    pub fn start(
+        output_path: &Path,
+        (scale_x, scale_y): (u32, u32),
+        tone: ToneOptions,
+    ) -> io::Result<Self> {
+        let video_path = output_path.with_extension("video.mkv");
+        let (ffmpeg, stdin) = spawn_ffmpeg(&[
+            "-f",
+            "rawvideo",
+            "-pixel_format",
+            "rgb24",
+            "-video_size",
+            &format!("{}x{}", constants::DISPLAY_WIDTH, constants::DISPLAY_HEIGHT),
+            "-framerate",
+            &constants::FRAME_RATE.to_string(),
+            "-i",
+            "-",
+            "-vf",
+            &format!("scale=iw*{}:ih*{}:flags=neighbor", scale_x, scale_y),
+            "-c:v",
+            "libx264rgb",
+            "-qp",
+            "0",
+            video_path.to_str().unwrap(),
+        ])?;
+
+        Ok(VideoRecorder {
+            ffmpeg,
+            stdin,
+            video_path,
+            output_path: output_path.to_path_buf(),
+            beeping_frames: Vec::new(),
+            tone,
+        })
+    }
+
+    pub fn push_frame(&mut self, pixels: &[u8], is_beeping: bool) -> io::Result<()> {
+        self.beeping_frames.push(is_beeping);
+        self.stdin.write_all(pixels)
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.stdin);
+        wait_for_ffmpeg(self.ffmpeg)?;
+
+        let (ffmpeg, mut stdin) = spawn_ffmpeg(&[
+            "-i",
+            self.video_path.to_str().unwrap(),
+            "-f",
+            "s16le",
+            "-ar",
+            &constants::AUDIO_SAMPLE_RATE.to_string(),
+            "-ac",
+            "1",
+            "-i",
+            "-",
+            "-c:v",
+            "copy",
+            "-shortest",
+            self.output_path.to_str().unwrap(),
+        ])?;
+
+        let samples = beep_samples(&self.beeping_frames, self.tone);
+        let pcm: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        stdin.write_all(&pcm)?;
+        drop(stdin);
+        wait_for_ffmpeg(ffmpeg)?;
+
+        fs::remove_file(&self.video_path)?;
+
+        // The audio alongside, for editing without extracting it from the video
+        save_wav(&self.output_path.with_extension("wav"), &samples).map_err(io::Error::other)
+    }
+}
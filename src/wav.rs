@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use crate::constants;
+use crate::tone::{ToneGenerator, ToneOptions};
+
+// Renders the beep of each recorded frame as 16-bit PCM, driven like the live audio device
+pub fn beep_samples(beeping_frames: &[bool], tone: ToneOptions) -> Vec<i16> {
+    let mut tone_generator = ToneGenerator::new(constants::AUDIO_SAMPLE_RATE, tone);
+    let mut samples =
+        vec![0.0; constants::AUDIO_SAMPLE_RATE as usize / constants::FRAME_RATE as usize];
+    let mut pcm = Vec::with_capacity(beeping_frames.len() * samples.len());
+    for &is_beeping in beeping_frames {
+        tone_generator.set_playing(is_beeping);
+        tone_generator.fill(&mut samples);
+        pcm.extend(
+            samples
+                .iter()
+                .map(|&sample| (sample * i16::MAX as f32) as i16),
+        );
+    }
+    pcm
+}
+
+// Writes mono 16-bit PCM at the recording sample rate
+pub fn save_wav(path: &Path, samples: &[i16]) -> Result<(), hound::Error> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: constants::AUDIO_SAMPLE_RATE as u32,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for &sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()
+}
@@ -0,0 +1,376 @@
+use std::{collections::BTreeSet, io, net::TcpStream};
+
+use serde_json::{json, Map, Value};
+
+use crate::chip_8::Chip8;
+use crate::condition::{parse_number, parse_operand};
+use crate::constants;
+use crate::debugger::DebuggerCommand;
+use crate::inspect_server::display_rows;
+use crate::remote_debugger::{accept, read_available, write_all, RemoteDebugger};
+
+// Appended to the client's key to prove the server speaks WebSocket, see RFC 6455
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (index, &byte)| {
+                group | (byte as u32) << (16 - index * 8)
+            });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let digit = (group >> (18 - index * 6)) & 0x3F;
+                encoded.push(BASE64_ALPHABET[digit as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn accept_key(key: &str) -> String {
+    base64(
+        &sha1_smol::Sha1::from(format!("{}{}", key, HANDSHAKE_GUID))
+            .digest()
+            .bytes(),
+    )
+}
+
+// Server frames are never masked or fragmented
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn machine_state(chip8: &Chip8) -> Value {
+    json!({
+        "v": chip8.registers(),
+        "i": chip8.index_register(),
+        "pc": chip8.program_counter(),
+        "sp": chip8.stack_pointer(),
+        "stack": chip8.stack(),
+        "dt": chip8.delay_timer(),
+        "st": chip8.sound_timer(),
+        "frame": chip8.frame(),
+        "instructions": chip8.instruction_count(),
+    })
+}
+
+// A number, or a string such as "0x2A4"
+fn number_argument(request: &Value, name: &str) -> Result<u16, String> {
+    let value = &request[name];
+    value
+        .as_u64()
+        .and_then(|number| u16::try_from(number).ok())
+        .or_else(|| value.as_str().and_then(parse_number))
+        .ok_or_else(|| format!("Invalid {}: {}", name, value))
+}
+
+// Checked against memory
+fn memory_range(request: &Value, len: usize) -> Result<usize, String> {
+    let address = number_argument(request, "address")? as usize;
+    if address + len > constants::RAM_LEN {
+        return Err(format!(
+            "Range outside of memory: {:#05X} + {}",
+            address, len
+        ));
+    }
+    Ok(address)
+}
+
+// A debugger protocol for browser UIs over a single WebSocket connection. Clients send JSON
+// requests such as `{"id": 1, "command": "step", "count": 10}` and get a response with the same
+// id and `"success"`, along with the result or an `"error"`. Commands are `pause`, `continue`,
+// `step` (`count`), `state`, `set` (`name` such as "V3" or "PC", `value`), `read` (`address`,
+// `length`), `write` (`address`, `bytes`), `breakpoints` (`addresses`, replacing all of them)
+// and `framebuffer`. Events without an id report stops (`{"event": "stopped", "reason":
+// "breakpoint"}`) and push the display whenever it changes (`{"event": "frame", "rows": [...]}`,
+// a string per row like `--dump`). Fragmented messages are not supported.
+pub struct WebSocketServer {
+    stream: Option<TcpStream>,
+    input: Vec<u8>,
+    // The HTTP upgrade is done, messages are framed from then on
+    is_open: bool,
+    breakpoints: BTreeSet<usize>,
+    // The reason of the stopped event to send once the machine stops
+    stop_reason: Option<&'static str>,
+    pushed_display: Vec<bool>,
+    pushed_frame: u64,
+}
+
+impl WebSocketServer {
+    // Blocks until a client connects
+    pub fn listen(port: u16) -> io::Result<Self> {
+        Ok(WebSocketServer {
+            stream: Some(accept(port, "a WebSocket client")?),
+            input: Vec::new(),
+            is_open: false,
+            breakpoints: BTreeSet::new(),
+            stop_reason: None,
+            pushed_display: Vec::new(),
+            pushed_frame: 0,
+        })
+    }
+
+    // Answers the HTTP upgrade request once it has fully arrived
+    fn handshake(&mut self) {
+        let Some(header_end) = self
+            .input
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+        else {
+            return;
+        };
+        let request: Vec<u8> = self.input.drain(..header_end + 4).collect();
+        let key = String::from_utf8_lossy(&request).lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("Sec-WebSocket-Key")
+                .then(|| value.trim().to_string())
+        });
+        let Some(key) = key else {
+            self.send_raw(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n");
+            eprintln!("Expected a WebSocket upgrade request");
+            self.stream = None;
+            return;
+        };
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(&key)
+        );
+        self.send_raw(response.as_bytes());
+        self.is_open = true;
+        // The machine starts stepping
+        self.stop_reason = Some("entry");
+    }
+
+    // Removes the next frame from the input as its opcode and unmasked payload
+    fn take_frame(&mut self) -> Option<(u8, Vec<u8>)> {
+        let input = &self.input;
+        let opcode = input.first()? & 0x0F;
+        let is_masked = input.get(1)? & 0x80 != 0;
+        let (len, mut offset) = match input[1] & 0x7F {
+            126 => (
+                u16::from_be_bytes(input.get(2..4)?.try_into().ok()?) as usize,
+                4,
+            ),
+            127 => (
+                u64::from_be_bytes(input.get(2..10)?.try_into().ok()?) as usize,
+                10,
+            ),
+            len => (len as usize, 2),
+        };
+        let mut mask = [0; 4];
+        if is_masked {
+            mask.copy_from_slice(input.get(offset..offset + 4)?);
+            offset += 4;
+        }
+        if input.len() < offset.saturating_add(len) {
+            return None;
+        }
+        let frame: Vec<u8> = self.input.drain(..offset + len).collect();
+        let payload = frame[offset..]
+            .iter()
+            .enumerate()
+            .map(|(index, byte)| byte ^ mask[index % 4])
+            .collect();
+        Some((opcode, payload))
+    }
+
+    fn handle_request(&mut self, request: &Value, chip8: &mut Chip8) -> Option<DebuggerCommand> {
+        let mut debugger_command = None;
+        let result = match request["command"].as_str().unwrap_or_default() {
+            "pause" => {
+                self.stop_reason = Some("pause");
+                debugger_command = Some(DebuggerCommand::Pause);
+                Ok(json!({}))
+            }
+            "continue" => {
+                debugger_command = Some(DebuggerCommand::Continue);
+                Ok(json!({}))
+            }
+            "step" => {
+                let count = request["count"]
+                    .as_u64()
+                    .unwrap_or(1)
+                    .clamp(1, u32::MAX as u64);
+                self.stop_reason = Some("step");
+                debugger_command = Some(DebuggerCommand::Step(count as u32));
+                Ok(json!({}))
+            }
+            "state" => Ok(machine_state(chip8)),
+            "set" => set_value(request, chip8),
+            "read" => read_memory(request, chip8),
+            "write" => write_memory(request, chip8),
+            "breakpoints" => Ok(self.set_breakpoints(request)),
+            "framebuffer" => Ok(json!({ "rows": display_rows(chip8.display_buffer()) })),
+            command => Err(format!("Unsupported command: {}", command)),
+        };
+        let mut response = match result {
+            Ok(Value::Object(body)) => body,
+            Ok(_) => Map::new(),
+            Err(error) => Map::from_iter([("error".to_string(), error.into())]),
+        };
+        response.insert(
+            "success".to_string(),
+            (!response.contains_key("error")).into(),
+        );
+        response.insert("id".to_string(), request["id"].clone());
+        self.send(Value::Object(response));
+        debugger_command
+    }
+
+    fn set_breakpoints(&mut self, request: &Value) -> Value {
+        self.breakpoints = request["addresses"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|address| address.as_u64())
+            .map(|address| address as usize)
+            .filter(|&address| address < constants::RAM_LEN)
+            .collect();
+        json!({ "addresses": self.breakpoints })
+    }
+
+    // Sends the display if it changed, at most once per frame
+    fn push_display(&mut self, chip8: &Chip8) {
+        let display = chip8.display_buffer();
+        if chip8.frame() == self.pushed_frame
+            || self.pushed_display.as_slice() == display.as_slice()
+        {
+            return;
+        }
+        self.pushed_display = display.to_vec();
+        self.pushed_frame = chip8.frame();
+        self.send(json!({ "event": "frame", "rows": display_rows(display) }));
+    }
+
+    fn send(&mut self, message: Value) {
+        let frame = encode_frame(OPCODE_TEXT, message.to_string().as_bytes());
+        self.send_raw(&frame);
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) {
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+        if let Err(error) = write_all(stream, bytes) {
+            eprintln!("Lost WebSocket connection: {:?}", error);
+            self.stream = None;
+        }
+    }
+}
+
+impl RemoteDebugger for WebSocketServer {
+    // The machine runs again once the client disconnects
+    fn poll_command(&mut self, chip8: &mut Chip8) -> Option<DebuggerCommand> {
+        let stream = self.stream.as_mut()?;
+        match read_available(stream, &mut self.input) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.stream = None;
+                return Some(DebuggerCommand::Continue);
+            }
+            Err(error) => {
+                eprintln!("Lost WebSocket connection: {:?}", error);
+                self.stream = None;
+                return Some(DebuggerCommand::Continue);
+            }
+        }
+        if !self.is_open {
+            self.handshake();
+            if !self.is_open {
+                return None;
+            }
+        }
+
+        let mut command = None;
+        while let Some((opcode, payload)) = self.take_frame() {
+            match opcode {
+                OPCODE_TEXT => {
+                    let request = serde_json::from_slice(&payload).unwrap_or(Value::Null);
+                    if let Some(request_command) = self.handle_request(&request, chip8) {
+                        command = Some(request_command);
+                    }
+                }
+                OPCODE_PING => self.send_raw(&encode_frame(OPCODE_PONG, &payload)),
+                OPCODE_CLOSE => {
+                    self.send_raw(&encode_frame(OPCODE_CLOSE, &payload));
+                    self.stream = None;
+                    return Some(DebuggerCommand::Continue);
+                }
+                _ => {}
+            }
+        }
+        self.push_display(chip8);
+        command
+    }
+
+    fn check_stop(&mut self, chip8: &Chip8) -> bool {
+        if self.breakpoints.contains(&chip8.program_counter()) {
+            self.stop_reason = Some("breakpoint");
+            return true;
+        }
+        false
+    }
+
+    fn report_stop(&mut self) {
+        if let Some(reason) = self.stop_reason.take() {
+            self.send(json!({ "event": "stopped", "reason": reason }));
+        }
+    }
+}
+
+fn set_value(request: &Value, chip8: &mut Chip8) -> Result<Value, String> {
+    let operand = parse_operand(request["name"].as_str().unwrap_or_default())?;
+    let value = number_argument(request, "value")?;
+    operand.check_value(value)?;
+    operand.set(chip8, value);
+    Ok(json!({}))
+}
+
+fn read_memory(request: &Value, chip8: &Chip8) -> Result<Value, String> {
+    let len = number_argument(request, "length")? as usize;
+    let address = memory_range(request, len)?;
+    Ok(json!({ "bytes": chip8.ram()[address..address + len] }))
+}
+
+fn write_memory(request: &Value, chip8: &mut Chip8) -> Result<Value, String> {
+    let bytes: Vec<u8> = request["bytes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+        .collect::<Option<_>>()
+        .ok_or("Invalid bytes, expected numbers from 0 to 255")?;
+    let address = memory_range(request, bytes.len())?;
+    for (offset, byte) in bytes.into_iter().enumerate() {
+        chip8.poke(address + offset, byte);
+    }
+    Ok(json!({}))
+}
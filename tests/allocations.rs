@@ -0,0 +1,98 @@
+// The run loop must not allocate, as at a high instruction rate the allocator shows up in profiles.
+// An allocator counting its calls lives in its own test binary, so no other test is counted.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use chip_8_interpreter::{
+    chip_8::{Chip8, Platform, Quirks},
+    clock::VirtualClock,
+    constants,
+    coverage::Coverage,
+    renderer::{run_pass, Renderer},
+    rng::Pcg32,
+};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Draws a random digit, skips on its key, counts into the delay timer and stores the count's
+// digits, then clears the screen and starts over
+const ROM: [u8; 26] = [
+    0x60, 0x00, // V0 = 0
+    0x61, 0x00, // V1 = 0
+    0xF0, 0x29, // I = sprite of V0
+    0xD0, 0x15, // draw at V0, V1
+    0xC0, 0x0F, // V0 = random & 0x0F
+    0xE0, 0x9E, // skip if key V0 is pressed
+    0x71, 0x01, // V1 += 1
+    0xF1, 0x15, // delay timer = V1
+    0xA3, 0x00, // I = 0x300
+    0xF1, 0x33, // BCD of V1 at I
+    0xF2, 0x65, // load V0 to V2 from I
+    0x00, 0xE0, // clear the screen
+    0x12, 0x04, // jump to 0x204
+];
+
+// A frontend pressing a different key each frame
+struct KeyPresser;
+
+impl Renderer for KeyPresser {
+    fn render_buffer(&mut self, _buffer: &[bool; constants::DISPLAY_LEN]) {}
+
+    fn pressed_keys(&mut self, frame: u64) -> Option<u16> {
+        Some(1 << (frame % 16))
+    }
+}
+
+// Runs passes of the loop the frontends share until the next frame
+fn run_frame(chip8: &mut Chip8) {
+    let frame = chip8.frame();
+    while chip8.frame() == frame {
+        assert!(run_pass(chip8, &mut KeyPresser));
+    }
+}
+
+#[test]
+fn run_loop_does_not_allocate() {
+    let mut chip8 = Chip8::build(
+        &ROM,
+        100_000,
+        false,
+        Quirks::new(Platform::Chip8),
+        Box::new(Pcg32::new(1)),
+        Box::new(VirtualClock::new()),
+    );
+    // Works out what each instruction accesses before it runs, like watchpoints do
+    chip8.track_coverage(Coverage::new(ROM.len()));
+    // The first frame may set up buffers that are reused from then on
+    run_frame(&mut chip8);
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..120 {
+        run_frame(&mut chip8);
+    }
+    assert!(chip8.instruction_count() > 10_000);
+    assert_eq!(ALLOCATIONS.load(Ordering::Relaxed) - allocations, 0);
+}